@@ -24,6 +24,11 @@ internal static class NativeMethods
     public const int EVENT_MESSAGE_RECEIVED = 4;
     public const int EVENT_FILE_RECEIVED = 5;
     public const int EVENT_ERROR = 6;
+    public const int EVENT_PEER_UPDATED = 7;
+    public const int EVENT_PEER_LOST = 8;
+    public const int EVENT_NETWORK_CHANGED = 9;
+    public const int EVENT_PEER_CONFLICT = 10;
+    public const int EVENT_STATE_CHANGED = 11;
 
     // Event callback delegate
     [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
@@ -58,6 +63,9 @@ internal static class NativeMethods
     [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl)]
     public static extern IntPtr p2p_get_local_ip(IntPtr handle);
 
+    [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl)]
+    public static extern ushort p2p_get_tcp_port(IntPtr handle);
+
     // Discovery and connection
     [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl)]
     public static extern int p2p_discover_peers(IntPtr handle);
@@ -68,11 +76,52 @@ internal static class NativeMethods
     [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl)]
     public static extern int p2p_get_connected_peers_count(IntPtr handle);
 
+    [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl, CharSet = CharSet.Ansi)]
+    public static extern IntPtr p2p_get_peer_attribute(
+        IntPtr handle, 
+        [MarshalAs(UnmanagedType.LPStr)] string peerId,
+        [MarshalAs(UnmanagedType.LPStr)] string attribute);
+
+    // Announced profile
+    [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl, CharSet = CharSet.Ansi)]
+    public static extern int p2p_set_status(
+        IntPtr handle, 
+        [MarshalAs(UnmanagedType.LPStr)] string status);
+
+    [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl, CharSet = CharSet.Ansi)]
+    public static extern int p2p_set_metadata(
+        IntPtr handle, 
+        [MarshalAs(UnmanagedType.LPStr)] string key,
+        [MarshalAs(UnmanagedType.LPStr)] string? value);
+
+    // Interface selection
+    [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl, CharSet = CharSet.Ansi)]
+    public static extern int p2p_set_interface_filter(
+        IntPtr handle, 
+        [MarshalAs(UnmanagedType.LPStr)] string? include,
+        [MarshalAs(UnmanagedType.LPStr)] string? exclude);
+
+    // Announcement schedule
+    [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl)]
+    public static extern int p2p_set_announce_interval(IntPtr handle, uint minMs, uint maxMs);
+
+    // Network groups
+    [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl, CharSet = CharSet.Ansi)]
+    public static extern int p2p_set_network_group(
+        IntPtr handle, 
+        [MarshalAs(UnmanagedType.LPStr)] string name,
+        [MarshalAs(UnmanagedType.LPStr)] string? secret);
+
     [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl, CharSet = CharSet.Ansi)]
     public static extern int p2p_connect_to_peer(
         IntPtr handle, 
         [MarshalAs(UnmanagedType.LPStr)] string peerId);
 
+    [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl, CharSet = CharSet.Ansi)]
+    public static extern int p2p_connect_to_address(
+        IntPtr handle, 
+        [MarshalAs(UnmanagedType.LPStr)] string address);
+
     [DllImport(LibraryName, CallingConvention = CallingConvention.Cdecl, CharSet = CharSet.Ansi)]
     public static extern int p2p_disconnect_peer(
         IntPtr handle, 
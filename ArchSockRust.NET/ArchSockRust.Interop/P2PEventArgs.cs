@@ -12,7 +12,12 @@ public enum P2PEventType
     PeerDisconnected = 3,
     MessageReceived = 4,
     FileReceived = 5,
-    Error = 6
+    Error = 6,
+    PeerUpdated = 7,
+    PeerLost = 8,
+    NetworkChanged = 9,
+    PeerConflict = 10,
+    StateChanged = 11
 }
 
 /// <summary>
@@ -73,6 +78,58 @@ public class ErrorEventArgs : P2PEventArgs
     }
 }
 
+/// <summary>
+/// Event args for network interface changes
+/// </summary>
+public class NetworkChangedEventArgs : P2PEventArgs
+{
+    /// <summary>
+    /// Interfaces added (+) and removed (-), e.g. "+wlan0 192.168.1.20/24, -eth0 10.0.0.5/24"
+    /// </summary>
+    public string Changes { get; }
+
+    public NetworkChangedEventArgs(string changes) : base(P2PEventType.NetworkChanged)
+    {
+        Changes = changes ?? throw new ArgumentNullException(nameof(changes));
+    }
+}
+
+/// <summary>
+/// Event args for a claim to a known peer ID that didn't match its identity key
+/// </summary>
+public class PeerConflictEventArgs : P2PEventArgs
+{
+    public string PeerId { get; }
+
+    /// <summary>
+    /// Address the conflicting claim came from, e.g. "192.168.1.66:6969"
+    /// </summary>
+    public string Address { get; }
+
+    public PeerConflictEventArgs(string peerId, string address)
+        : base(P2PEventType.PeerConflict)
+    {
+        PeerId = peerId ?? throw new ArgumentNullException(nameof(peerId));
+        Address = address ?? throw new ArgumentNullException(nameof(address));
+    }
+}
+
+/// <summary>
+/// Event args for the messenger starting or stopping
+/// </summary>
+public class StateChangedEventArgs : P2PEventArgs
+{
+    /// <summary>
+    /// The new state: "stopped", "starting", "running" or "stopping"
+    /// </summary>
+    public string State { get; }
+
+    public StateChangedEventArgs(string state) : base(P2PEventType.StateChanged)
+    {
+        State = state ?? throw new ArgumentNullException(nameof(state));
+    }
+}
+
 /// <summary>
 /// Exception thrown by P2P operations
 /// </summary>
@@ -16,6 +16,11 @@ public class P2PMessenger : IDisposable
     public event EventHandler<PeerEventArgs>? PeerDiscovered;
     public event EventHandler<PeerEventArgs>? PeerConnected;
     public event EventHandler<PeerEventArgs>? PeerDisconnected;
+    public event EventHandler<PeerEventArgs>? PeerUpdated;
+    public event EventHandler<PeerEventArgs>? PeerLost;
+    public event EventHandler<NetworkChangedEventArgs>? NetworkChanged;
+    public event EventHandler<PeerConflictEventArgs>? PeerConflict;
+    public event EventHandler<StateChangedEventArgs>? StateChanged;
     public event EventHandler<MessageReceivedEventArgs>? MessageReceived;
     public event EventHandler<ErrorEventArgs>? Error;
 
@@ -57,7 +62,7 @@ public class P2PMessenger : IDisposable
     }
 
     /// <summary>
-    /// Stop the messenger
+    /// Stop the messenger; <see cref="Start"/> brings it back online
     /// </summary>
     public void Stop()
     {
@@ -106,6 +111,19 @@ public class P2PMessenger : IDisposable
         }
     }
 
+    /// <summary>
+    /// TCP port the messenger listens on, which the OS picks when created
+    /// with port 0; 0 until started
+    /// </summary>
+    public ushort TcpPort
+    {
+        get
+        {
+            ThrowIfDisposed();
+            return NativeMethods.p2p_get_tcp_port(_handle);
+        }
+    }
+
     /// <summary>
     /// Discover peers on the network
     /// </summary>
@@ -142,6 +160,90 @@ public class P2PMessenger : IDisposable
         }
     }
 
+    /// <summary>
+    /// Get an announced attribute of a discovered peer
+    /// </summary>
+    /// <param name="peerId">The discovered peer's ID</param>
+    /// <param name="attribute">"version", "os", "hostname", "status", "accepts_files" or an app metadata key</param>
+    /// <returns>The value, or null if the peer or attribute is unknown</returns>
+    public string? GetPeerAttribute(string peerId, string attribute)
+    {
+        ThrowIfDisposed();
+        if (string.IsNullOrWhiteSpace(peerId))
+            throw new ArgumentException("Peer ID cannot be null or empty", nameof(peerId));
+        if (string.IsNullOrWhiteSpace(attribute))
+            throw new ArgumentException("Attribute cannot be null or empty", nameof(attribute));
+
+        var ptr = NativeMethods.p2p_get_peer_attribute(_handle, peerId, attribute);
+        return NativeMethods.PtrToString(ptr);
+    }
+
+    /// <summary>
+    /// Set the status other peers see, e.g. "away"
+    /// </summary>
+    public void SetStatus(string status)
+    {
+        ThrowIfDisposed();
+        ArgumentNullException.ThrowIfNull(status);
+
+        var result = NativeMethods.p2p_set_status(_handle, status);
+        ThrowIfError(result, "Failed to set status");
+    }
+
+    /// <summary>
+    /// Announce an app-defined key/value pair; pass null to remove the key
+    /// </summary>
+    public void SetMetadata(string key, string? value)
+    {
+        ThrowIfDisposed();
+        if (string.IsNullOrWhiteSpace(key))
+            throw new ArgumentException("Key cannot be null or empty", nameof(key));
+
+        var result = NativeMethods.p2p_set_metadata(_handle, key, value);
+        ThrowIfError(result, $"Failed to set metadata {key}");
+    }
+
+    /// <summary>
+    /// Only announce and listen on some interfaces, e.g. to skip Docker bridges
+    /// </summary>
+    /// <param name="include">Comma separated names ("eth*") or subnets ("10.8.0.0/16") to use, or null for all</param>
+    /// <param name="exclude">Comma separated names or subnets never to use, or null</param>
+    public void SetInterfaceFilter(string? include, string? exclude)
+    {
+        ThrowIfDisposed();
+
+        var result = NativeMethods.p2p_set_interface_filter(_handle, include, exclude);
+        ThrowIfError(result, "Failed to set interface filter");
+    }
+
+    /// <summary>
+    /// Announce every minInterval at first, backing off to maxInterval while the network is stable
+    /// </summary>
+    /// <param name="minInterval">Interval right after starting or a network change</param>
+    /// <param name="maxInterval">Longest interval; at most a third of the peer timeout (20 seconds by default)</param>
+    public void SetAnnounceInterval(TimeSpan minInterval, TimeSpan maxInterval)
+    {
+        ThrowIfDisposed();
+
+        var result = NativeMethods.p2p_set_announce_interval(
+            _handle, (uint)minInterval.TotalMilliseconds, (uint)maxInterval.TotalMilliseconds);
+        ThrowIfError(result, "Failed to set announce interval");
+    }
+
+    /// <summary>
+    /// Only discover and connect to peers in the same group. Call before Start.
+    /// </summary>
+    /// <param name="name">Group name; empty for the default group</param>
+    /// <param name="secret">Shared secret peers must prove they hold, or null</param>
+    public void SetNetworkGroup(string name, string? secret = null)
+    {
+        ThrowIfDisposed();
+        ArgumentNullException.ThrowIfNull(name);
+
+        var result = NativeMethods.p2p_set_network_group(_handle, name, secret);
+        ThrowIfError(result, $"Failed to set network group {name}");
+    }
+
     /// <summary>
     /// Connect to a peer by ID
     /// </summary>
@@ -156,6 +258,20 @@ public class P2PMessenger : IDisposable
         ThrowIfError(result, $"Failed to connect to peer {peerId}");
     }
 
+    /// <summary>
+    /// Connect to a peer by address, e.g. "192.168.1.20:6969"
+    /// </summary>
+    /// <param name="address">The peer's host and TCP port</param>
+    public void ConnectToAddress(string address)
+    {
+        ThrowIfDisposed();
+        if (string.IsNullOrWhiteSpace(address))
+            throw new ArgumentException("Address cannot be null or empty", nameof(address));
+
+        var result = NativeMethods.p2p_connect_to_address(_handle, address);
+        ThrowIfError(result, $"Failed to connect to {address}");
+    }
+
     /// <summary>
     /// Disconnect from a peer
     /// </summary>
@@ -230,11 +346,36 @@ public class P2PMessenger : IDisposable
                         PeerDisconnected?.Invoke(this, new PeerEventArgs(P2PEventType.PeerDisconnected, peerId, peerName));
                     break;
 
+                case NativeMethods.EVENT_PEER_UPDATED:
+                    if (peerId != null && peerName != null)
+                        PeerUpdated?.Invoke(this, new PeerEventArgs(P2PEventType.PeerUpdated, peerId, peerName));
+                    break;
+
+                case NativeMethods.EVENT_PEER_LOST:
+                    if (peerId != null && peerName != null)
+                        PeerLost?.Invoke(this, new PeerEventArgs(P2PEventType.PeerLost, peerId, peerName));
+                    break;
+
                 case NativeMethods.EVENT_MESSAGE_RECEIVED:
                     if (peerId != null && peerName != null && message != null)
                         MessageReceived?.Invoke(this, new MessageReceivedEventArgs(peerId, peerName, message));
                     break;
 
+                case NativeMethods.EVENT_NETWORK_CHANGED:
+                    if (message != null)
+                        NetworkChanged?.Invoke(this, new NetworkChangedEventArgs(message));
+                    break;
+
+                case NativeMethods.EVENT_PEER_CONFLICT:
+                    if (peerId != null && message != null)
+                        PeerConflict?.Invoke(this, new PeerConflictEventArgs(peerId, message));
+                    break;
+
+                case NativeMethods.EVENT_STATE_CHANGED:
+                    if (message != null)
+                        StateChanged?.Invoke(this, new StateChangedEventArgs(message));
+                    break;
+
                 case NativeMethods.EVENT_ERROR:
                     if (message != null)
                         Error?.Invoke(this, new ErrorEventArgs(message));
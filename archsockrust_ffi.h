@@ -22,6 +22,14 @@ typedef struct P2PHandle P2PHandle;
 #define EVENT_MESSAGE_RECEIVED 4
 #define EVENT_FILE_RECEIVED 5
 #define EVENT_ERROR 6
+#define EVENT_PEER_UPDATED 7
+#define EVENT_PEER_LOST 8
+// message lists interface changes, e.g. "+wlan0 192.168.1.20/24, -eth0 10.0.0.5/24"
+#define EVENT_NETWORK_CHANGED 9
+// message is the address of a claim to peer_id under another identity key
+#define EVENT_PEER_CONFLICT 10
+// message is the new state: "stopped", "starting", "running" or "stopping"
+#define EVENT_STATE_CHANGED 11
 
 // Event callback type
 typedef void (*EventCallback)(int event_type, const char* peer_id, const char* peer_name, const char* message);
@@ -37,12 +45,27 @@ int p2p_destroy(P2PHandle* handle);
 char* p2p_get_peer_name(P2PHandle* handle);
 char* p2p_get_peer_id(P2PHandle* handle);
 char* p2p_get_local_ip(P2PHandle* handle);
+// 0 until p2p_start has bound the listener
+unsigned short p2p_get_tcp_port(P2PHandle* handle);
 
 // Discovery and connection
 int p2p_discover_peers(P2PHandle* handle);
 int p2p_get_discovered_peers_count(P2PHandle* handle);
 int p2p_get_connected_peers_count(P2PHandle* handle);
 int p2p_connect_to_peer(P2PHandle* handle, const char* peer_id);
+// Returns null if unknown; free with p2p_free_string
+char* p2p_get_peer_attribute(P2PHandle* handle, const char* peer_id, const char* attribute);
+int p2p_set_status(P2PHandle* handle, const char* status);
+// Pass a null value to remove the key
+int p2p_set_metadata(P2PHandle* handle, const char* key, const char* value);
+// Comma separated interface names ("docker*" wildcards) or subnets; null for none
+int p2p_set_interface_filter(P2PHandle* handle, const char* include, const char* exclude);
+// Announce every min_ms at first, backing off to max_ms while stable; max_ms may be
+// at most a third of the peer timeout (20000 by default)
+int p2p_set_announce_interval(P2PHandle* handle, unsigned int min_ms, unsigned int max_ms);
+// Call before p2p_start; pass a null secret for an unauthenticated group
+int p2p_set_network_group(P2PHandle* handle, const char* name, const char* secret);
+int p2p_connect_to_address(P2PHandle* handle, const char* address);
 int p2p_disconnect_peer(P2PHandle* handle, const char* peer_id);
 
 // Messaging
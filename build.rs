@@ -1,9 +1,15 @@
 use std::io::Result;
 
 fn main() -> Result<()> {
-    prost_build::compile_protos(
-        &["proto/messages.proto", "proto/discovery.proto"],
-        &["proto/"],
-    )?;
+    prost_build::Config::new()
+        // Announcements carry a key and signature; boxing them isn't worth it
+        .enum_attribute(
+            "archsockrust.DiscoveryMessage.message",
+            "#[allow(clippy::large_enum_variant)]",
+        )
+        .compile_protos(
+            &["proto/messages.proto", "proto/discovery.proto"],
+            &["proto/"],
+        )?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,5 +1,6 @@
+use crate::discovery::interfaces;
 use crate::{P2PMessenger, P2PEvent, message_content};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
@@ -25,6 +26,54 @@ pub struct PeerStatus {
     pub port: u32,
     pub last_seen: u64,
     pub is_connected: bool,
+    pub version: String,
+    pub accepts_files: bool,
+    pub os: String,
+    pub hostname: String,
+    pub status: String,
+    pub metadata: HashMap<String, String>,
+}
+
+impl PeerStatus {
+    pub fn from_peer(peer: crate::PeerInfo, is_connected: bool) -> Self {
+        Self {
+            id: peer.id,
+            name: peer.name,
+            ip: peer.ip,
+            port: peer.port,
+            last_seen: peer.last_seen,
+            is_connected,
+            version: peer.version,
+            accepts_files: peer.accepts_files,
+            os: peer.os,
+            hostname: peer.hostname,
+            status: peer.status,
+            metadata: peer.metadata,
+        }
+    }
+
+    /// One-line summary of the announced details, empty if none were announced
+    pub fn details(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.version.is_empty() {
+            parts.push(format!("v{}", self.version));
+        }
+        if !self.hostname.is_empty() || !self.os.is_empty() {
+            parts.push(format!("{}@{}", self.os, self.hostname));
+        }
+        if !self.status.is_empty() {
+            parts.push(self.status.clone());
+        }
+        if !self.version.is_empty() && !self.accepts_files {
+            parts.push("no files".to_string());
+        }
+
+        let mut metadata: Vec<_> = self.metadata.iter().collect();
+        metadata.sort();
+        parts.extend(metadata.into_iter().map(|(key, value)| format!("{}={}", key, value)));
+
+        parts.join(" · ")
+    }
 }
 
 pub struct AppState {
@@ -75,28 +124,14 @@ impl AppState {
         let discovered = self.messenger.get_discovered_peers();
         self.discovered_peers = discovered
             .into_iter()
-            .map(|peer| PeerStatus {
-                id: peer.id,
-                name: peer.name,
-                ip: peer.ip,
-                port: peer.port,
-                last_seen: peer.last_seen,
-                is_connected: false,
-            })
+            .map(|peer| PeerStatus::from_peer(peer, false))
             .collect();
 
         // Update connected peers
         let connected = self.messenger.get_connected_peers().await;
         self.connected_peers = connected
             .into_iter()
-            .map(|peer| PeerStatus {
-                id: peer.id,
-                name: peer.name,
-                ip: peer.ip,
-                port: peer.port,
-                last_seen: peer.last_seen,
-                is_connected: true,
-            })
+            .map(|peer| PeerStatus::from_peer(peer, true))
             .collect();
 
         // Mark discovered peers that are also connected
@@ -120,6 +155,7 @@ impl AppState {
                     ip: peer.ip.clone(),
                     port: peer.port,
                     last_seen: peer.last_seen,
+                    ..Default::default()
                 };
 
                 let peer_name = peer.name.clone();
@@ -186,6 +222,30 @@ impl AppState {
         }
     }
 
+    pub async fn broadcast_text_message(&mut self, text: String) -> Result<String, String> {
+        if text.trim().is_empty() {
+            return Err("Message cannot be empty".to_string());
+        }
+
+        match self.messenger.broadcast_text_message(text.clone()).await {
+            Ok(results) if results.is_empty() => Err("No peers connected".to_string()),
+            Ok(results) => {
+                let delivered = results.values().filter(|result| result.is_ok()).count();
+                if delivered > 0 {
+                    let message = ChatMessage {
+                        sender: format!("{} (You → all)", self.messenger.peer_name()),
+                        content: text,
+                        timestamp: crate::get_current_timestamp(),
+                        message_type: MessageType::Text,
+                    };
+                    self.add_message(message);
+                }
+                Ok(format!("Broadcast sent to {}/{} peers", delivered, results.len()))
+            }
+            Err(e) => Err(format!("Failed to broadcast message: {}", e))
+        }
+    }
+
     pub async fn send_file(&mut self, file_path: String) -> Result<String, String> {
         if file_path.trim().is_empty() {
             return Err("File path cannot be empty".to_string());
@@ -205,6 +265,16 @@ impl AppState {
         }
     }
 
+    pub async fn connect_to_address(&mut self, address: &str) -> Result<String, String> {
+        match self.messenger.connect_to_address(address).await {
+            Ok(peer) => {
+                self.add_system_message(format!("Connected to {} at {}", peer.name, address));
+                Ok(format!("Connected to {} ({})", peer.name, peer.id))
+            }
+            Err(e) => Err(format!("Failed to connect to {}: {}", address, e))
+        }
+    }
+
     pub fn force_discovery(&self) -> Result<String, String> {
         match self.messenger.discover_peers() {
             Ok(_) => Ok("Discovery broadcast sent!".to_string()),
@@ -236,6 +306,26 @@ impl AppEventHandler {
                 ));
                 app_state.refresh_peers().await;
             }
+            P2PEvent::PeerUpdated(peer) => {
+                app_state.add_system_message(format!(
+                    "✏️ Peer updated: {} ({}:{}) ID:{:.8}...",
+                    peer.name, peer.ip, peer.port, peer.id
+                ));
+                app_state.refresh_peers().await;
+            }
+            P2PEvent::PeerLost(peer) => {
+                app_state.add_system_message(format!(
+                    "👻 Peer lost: {} ID:{:.8}...",
+                    peer.name, peer.id
+                ));
+                app_state.refresh_peers().await;
+            }
+            P2PEvent::PeerConflict(peer) => {
+                app_state.add_system_message(format!(
+                    "⚠️ Conflicting claim for ID:{:.8}... from {}:{} (keeping known address)",
+                    peer.id, peer.ip, peer.port
+                ));
+            }
             P2PEvent::PeerConnected(peer) => {
                 app_state.add_system_message(format!(
                     "🔗 Peer connected: {} ({}:{}) ID:{:.8}...",
@@ -295,6 +385,13 @@ impl AppEventHandler {
                     }
                 }
             }
+            P2PEvent::CustomMessage { message, payload } => {
+                app_state.add_system_message(format!(
+                    "📦 {} from {}",
+                    payload.type_url(),
+                    message.sender_name
+                ));
+            }
             P2PEvent::FileTransferStarted { filename, size, .. } => {
                 let size_kb = size / 1024;
                 app_state.add_system_message(format!(
@@ -311,6 +408,18 @@ impl AppEventHandler {
                     filename, error
                 ));
             }
+            P2PEvent::NetworkChanged { added, removed } => {
+                app_state.add_system_message(format!(
+                    "🌐 Network changed: {}",
+                    interfaces::describe_changes(&added, &removed)
+                ));
+            }
+            P2PEvent::StateChanged(state) => {
+                app_state.add_system_message(format!("🔌 Messenger {}", state.as_str()));
+            }
+            P2PEvent::Lagged { missed } => {
+                app_state.add_system_message(format!("⚠️ Missed {} events", missed));
+            }
             P2PEvent::Error(error) => {
                 app_state.add_system_message(format!("❌ Library error: {}", error));
             }
@@ -1,5 +1,6 @@
 use crate::app::AppState;
-use crate::{P2PMessenger, P2PEvent};
+use crate::discovery::interfaces;
+use crate::{ConfigFile, P2PMessenger, P2PEvent};
 use std::env;
 use std::io::{self, Write};
 use tokio::time::{sleep, Duration};
@@ -8,30 +9,35 @@ pub async fn run_cli() -> Result<(), Box<dyn std::error::Error>> {
     println!("🦀 ArchSockRust CLI - P2P Messenger Testing Tool");
     println!("===============================================");
 
-    // Parse CLI args: [name] [tcp_port] [discovery_port]
-    let args: Vec<String> = env::args().collect();
-    let (name, tcp_port, discovery_port) = if args.len() > 1 {
-        let name = args[1].clone();
-        let tcp_port = args.get(2).and_then(|p| p.parse().ok()).unwrap_or(6969);
-        let discovery_port = args.get(3).and_then(|p| p.parse().ok()).unwrap_or(6968);
-        (name, tcp_port, discovery_port)
-    } else {
+    // Config file and ARCHSOCKRUST_* variables, then CLI args:
+    // [--config <path>] [name] [tcp_port] [discovery_port]
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut settings = ConfigFile::from_args(&args)?;
+    for warning in &settings.warnings {
+        println!("⚠️  {}", warning);
+    }
+    if settings.name.is_none() {
         print!("Enter your name: ");
         io::stdout().flush()?;
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
-        (input.trim().to_string(), 6969, 6968)
-    };
+        settings.name = Some(input.trim().to_string());
+    }
+    let config = settings.into_builder("CLI User")?.build()?;
 
-    let mut messenger = P2PMessenger::with_ports(name, tcp_port, discovery_port)?;
+    let messenger = P2PMessenger::with_config(config)?;
     println!("✅ Created messenger with ID: {}", messenger.peer_id());
     println!("📡 Local IP: {}", messenger.get_local_ip());
-    println!("🔍 Discovery port: {}, TCP port: {}", discovery_port, tcp_port);
 
-    messenger.start().await?;
-    println!("🚀 Messenger started! Auto-discovering peers every 5s...");
+    let mut event_receiver = messenger.subscribe_events();
+    // Ports configured as 0 are only known once bound
+    let addresses = messenger.start().await?;
+    match addresses.discovery {
+        Some(discovery) => println!("🔍 Discovery on {}, TCP on {}", discovery, addresses.tcp),
+        None => println!("🔍 TCP on {} (multicast discovery off)", addresses.tcp),
+    }
+    println!("🚀 Messenger started! Auto-discovering peers...");
 
-    let mut event_receiver = messenger.get_event_receiver().unwrap();
     let mut app_state = AppState::new(messenger);
 
     // Event handler task - simplified for CLI
@@ -41,11 +47,10 @@ pub async fn run_cli() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
-    // Auto-discovery task - use Arc clone
+    // Expiry task - announcements follow the discovery schedule on their own
     let discovery_messenger = app_state.messenger.clone();
     tokio::spawn(async move {
         loop {
-            let _ = discovery_messenger.discover_peers();
             discovery_messenger.cleanup_stale_peers();
             sleep(Duration::from_secs(5)).await;
         }
@@ -65,6 +70,8 @@ pub async fn run_cli() -> Result<(), Box<dyn std::error::Error>> {
             "6" => disconnect_peer(&mut app_state).await,
             "7" => show_status(&mut app_state).await,
             "8" => force_discovery(&mut app_state),
+            "9" => broadcast_message(&mut app_state).await,
+            "a" => connect_to_address(&mut app_state).await,
             "h" | "help" => show_help(),
             "0" | "q" | "quit" => break,
             _ => println!("❌ Invalid option. Type 'h' for help."),
@@ -82,7 +89,9 @@ fn print_menu() {
     println!("2. List connected peers      6. Disconnect from peer");
     println!("3. Connect to peer           7. Show status");
     println!("4. Send text message         8. Force discovery");
-    println!("h. Help                      0/q. Exit");
+    println!("9. Broadcast message         a. Connect by address");
+    println!("h. Help");
+    println!("0/q. Exit");
 }
 
 fn show_help() {
@@ -93,13 +102,15 @@ fn show_help() {
     println!("• With ports: cargo run --bin archsockrust-cli -- \"Name\" 7000 7001");
     println!("• Interactive: cargo run --bin archsockrust-cli");
     println!("• TUI version: cargo run --bin archsockrust-tui -- \"Your Name\"");
-    println!("• Discovery runs automatically every 5 seconds");
+    println!("• Discovery runs automatically, quickly at startup and up to every 20 seconds");
     println!("• Connect to peers before sending messages");
+    println!("• Broadcast sends one message to every connected peer");
+    println!("• Connect by address (host:port) when discovery can't reach a peer");
     println!("• Files are saved to 'recibidos/' directory");
     println!("\n🌐 Network:");
     println!("• UDP Discovery: configurable port (default 6968)");
     println!("• TCP Messages: configurable port (default 6969)");
-    println!("• Multiple instances: use different ports");
+    println!("• Multiple instances: use different TCP ports, share the discovery port");
     println!("• Works on local network without internet");
 }
 
@@ -180,6 +191,20 @@ async fn connect_to_peer(app_state: &mut AppState) {
     }
 }
 
+async fn connect_to_address(app_state: &mut AppState) {
+    let address = read_input("Peer address (host:port): ");
+    let address = address.trim();
+    if address.is_empty() {
+        println!("❌ No address given");
+        return;
+    }
+
+    match app_state.connect_to_address(address).await {
+        Ok(msg) => println!("✅ {}", msg),
+        Err(e) => println!("❌ {}", e),
+    }
+}
+
 async fn send_message(app_state: &mut AppState) {
     app_state.refresh_peers().await;
     if app_state.connected_peers.is_empty() {
@@ -203,6 +228,20 @@ async fn send_message(app_state: &mut AppState) {
     }
 }
 
+async fn broadcast_message(app_state: &mut AppState) {
+    app_state.refresh_peers().await;
+    if app_state.connected_peers.is_empty() {
+        println!("❌ No peers connected");
+        return;
+    }
+
+    let message = read_input("Enter message for all peers: ");
+    match app_state.broadcast_text_message(message.trim().to_string()).await {
+        Ok(msg) => println!("✅ {}", msg),
+        Err(e) => println!("❌ {}", e),
+    }
+}
+
 async fn send_file(app_state: &mut AppState) {
     app_state.refresh_peers().await;
     if app_state.connected_peers.is_empty() {
@@ -256,6 +295,17 @@ fn print_event(event: &P2PEvent) {
             print!("Choose option: ");
             io::stdout().flush().unwrap();
         }
+        P2PEvent::PeerLost(peer) => {
+            println!("\n👻 Peer lost: {} ID:{:.8}...", peer.name, peer.id);
+            print!("Choose option: ");
+            io::stdout().flush().unwrap();
+        }
+        P2PEvent::PeerConflict(peer) => {
+            println!("\n⚠️ Conflicting claim for ID:{:.8}... from {}:{} (keeping known address)",
+                peer.id, peer.ip, peer.port);
+            print!("Choose option: ");
+            io::stdout().flush().unwrap();
+        }
         P2PEvent::PeerConnected(peer) => {
             println!("\n🔗 Peer connected: {} ({}:{}) ID:{:.8}...", 
                 peer.name, peer.ip, peer.port, peer.id);
@@ -280,6 +330,16 @@ fn print_event(event: &P2PEvent) {
                 }
             }
         }
+        P2PEvent::NetworkChanged { added, removed } => {
+            println!("\n🌐 Network changed: {}", interfaces::describe_changes(added, removed));
+            print!("Choose option: ");
+            io::stdout().flush().unwrap();
+        }
+        P2PEvent::StateChanged(state) => {
+            println!("\n🔌 Messenger {}", state.as_str());
+            print!("Choose option: ");
+            io::stdout().flush().unwrap();
+        }
         _ => {}
     }
 }
\ No newline at end of file
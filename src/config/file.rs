@@ -0,0 +1,305 @@
+use super::{P2PConfig, P2PConfigBuilder};
+use crate::discovery::{AnnounceSchedule, DiscoveryBackend, InterfaceFilter, NetworkGroup, PeerIdentity};
+use crate::error::{P2PError, P2PResult};
+use serde::Deserialize;
+use std::env;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Prefix of the environment variables that override file settings
+pub const ENV_PREFIX: &str = "ARCHSOCKRUST_";
+
+/// Names a config file to read instead of the default location
+pub const CONFIG_PATH_VAR: &str = "ARCHSOCKRUST_CONFIG";
+
+/// Settings read from a TOML config file and `ARCHSOCKRUST_*` variables.
+///
+/// Every setting is optional; anything left unset keeps the
+/// [`P2PConfig::builder`] default. A file looks like:
+///
+/// ```toml
+/// name = "Alice"
+/// tcp_port = 6969
+/// download_dir = "/srv/archsockrust/received"
+/// trusted_peers = ["10.0.0.5:6969"]
+///
+/// [discovery]
+/// backend = "both"
+/// group = "qa-lab"
+/// exclude_interfaces = ["docker*"]
+/// ```
+///
+/// and each key has a matching variable, e.g. `ARCHSOCKRUST_TCP_PORT` or
+/// `ARCHSOCKRUST_DISCOVERY_BACKEND`; lists are comma-separated there.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub name: Option<String>,
+    pub peer_id: Option<String>,
+    /// Hex-encoded 32-byte identity secret, so the announcement key survives restarts
+    pub identity_key: Option<String>,
+    pub tcp_port: Option<u16>,
+    /// Interface address to accept connections on instead of all of them
+    pub listen_address: Option<IpAddr>,
+    pub discovery_port: Option<u16>,
+    pub download_dir: Option<PathBuf>,
+    pub relay: Option<bool>,
+    /// `host:port` of peers to dial on start
+    pub trusted_peers: Vec<String>,
+    pub discovery: DiscoverySettings,
+    /// Problems that didn't stop the settings loading, such as unknown
+    /// `ARCHSOCKRUST_*` variables, for the caller to report
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+/// The `[discovery]` table of a [`ConfigFile`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoverySettings {
+    /// `multicast`, `mdns`, `both` or `none`
+    pub backend: Option<String>,
+    /// Address of the only interface to discover on
+    pub bind_address: Option<IpAddr>,
+    pub multicast_group: Option<Ipv4Addr>,
+    pub group: Option<String>,
+    pub group_secret: Option<String>,
+    pub include_interfaces: Vec<String>,
+    pub exclude_interfaces: Vec<String>,
+    pub announce_min_ms: Option<u64>,
+    pub announce_max_ms: Option<u64>,
+    pub peer_timeout_secs: Option<u64>,
+}
+
+impl ConfigFile {
+    /// Parse the contents of a TOML config file
+    pub fn parse(text: &str) -> P2PResult<Self> {
+        toml::from_str(text).map_err(|e| P2PError::InvalidConfig(e.to_string()))
+    }
+
+    pub fn read(path: &Path) -> P2PResult<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| P2PError::InvalidConfig(format!("cannot read {}: {}", path.display(), e)))?;
+        Self::parse(&text).map_err(|e| P2PError::InvalidConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Read `path`, or the file named by `ARCHSOCKRUST_CONFIG`, or the default
+    /// file if it exists, then apply the process environment on top
+    pub fn load(path: Option<&Path>) -> P2PResult<Self> {
+        let explicit = path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os(CONFIG_PATH_VAR).map(PathBuf::from));
+
+        let mut settings = match explicit {
+            Some(path) => Self::read(&path)?,
+            None => match default_config_path() {
+                Some(path) if path.is_file() => Self::read(&path)?,
+                _ => Self::default(),
+            },
+        };
+        // Other programs' variables need not be Unicode; only ours are read
+        let vars = env::vars_os().filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)));
+        settings.apply_env(vars)?;
+        Ok(settings)
+    }
+
+    /// Load settings for one of the binaries from their command line:
+    /// `[--config <path>] [name] [tcp_port] [discovery_port]`, where the
+    /// positional arguments win over the file and environment
+    pub fn from_args(args: &[String]) -> P2PResult<Self> {
+        let mut path = None;
+        let mut positional = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                let value = args
+                    .next()
+                    .ok_or_else(|| P2PError::InvalidConfig("--config needs a path".to_string()))?;
+                path = Some(PathBuf::from(value));
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        let mut settings = Self::load(path.as_deref())?;
+        if let Some(name) = positional.first() {
+            settings.name = Some(name.to_string());
+        }
+        if let Some(port) = positional.get(1) {
+            settings.tcp_port = Some(parse_value("tcp_port", port)?);
+        }
+        if let Some(port) = positional.get(2) {
+            settings.discovery_port = Some(parse_value("discovery_port", port)?);
+        }
+        Ok(settings)
+    }
+
+    /// Override settings from `ARCHSOCKRUST_*` entries in `vars`; other
+    /// variables are ignored, unknown `ARCHSOCKRUST_*` ones are added to
+    /// `warnings` so a stale one doesn't stop the program starting
+    pub fn apply_env<I>(&mut self, vars: I) -> P2PResult<()>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (key, value) in vars {
+            let Some(setting) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let discovery = &mut self.discovery;
+            match setting {
+                "CONFIG" => {}
+                "NAME" => self.name = Some(value),
+                "PEER_ID" => self.peer_id = Some(value),
+                "IDENTITY_KEY" => self.identity_key = Some(value),
+                "TCP_PORT" => self.tcp_port = Some(parse_value(&key, &value)?),
+                "LISTEN_ADDRESS" => self.listen_address = Some(parse_value(&key, &value)?),
+                "DISCOVERY_PORT" => self.discovery_port = Some(parse_value(&key, &value)?),
+                "DOWNLOAD_DIR" => self.download_dir = Some(PathBuf::from(value)),
+                "RELAY" => self.relay = Some(parse_value(&key, &value)?),
+                "TRUSTED_PEERS" => self.trusted_peers = split_list(&value),
+                "DISCOVERY_BACKEND" => discovery.backend = Some(value),
+                "DISCOVERY_BIND_ADDRESS" => discovery.bind_address = Some(parse_value(&key, &value)?),
+                "MULTICAST_GROUP" => discovery.multicast_group = Some(parse_value(&key, &value)?),
+                "GROUP" => discovery.group = Some(value),
+                "GROUP_SECRET" => discovery.group_secret = Some(value),
+                "INCLUDE_INTERFACES" => discovery.include_interfaces = split_list(&value),
+                "EXCLUDE_INTERFACES" => discovery.exclude_interfaces = split_list(&value),
+                "ANNOUNCE_MIN_MS" => discovery.announce_min_ms = Some(parse_value(&key, &value)?),
+                "ANNOUNCE_MAX_MS" => discovery.announce_max_ms = Some(parse_value(&key, &value)?),
+                "PEER_TIMEOUT_SECS" => discovery.peer_timeout_secs = Some(parse_value(&key, &value)?),
+                _ => self.warnings.push(format!("ignoring unknown setting {}", key)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn the settings into a builder, naming the peer `default_name` if
+    /// no name was configured. Further builder calls override these settings.
+    pub fn into_builder(self, default_name: &str) -> P2PResult<P2PConfigBuilder> {
+        let mut builder = P2PConfig::builder(self.name.as_deref().unwrap_or(default_name));
+
+        if let Some(peer_id) = &self.peer_id {
+            builder = builder.peer_id(peer_id);
+        }
+        if let Some(key) = &self.identity_key {
+            builder = builder.identity(PeerIdentity::from_secret(&decode_key(key)?));
+        }
+        if let Some(port) = self.tcp_port {
+            builder = builder.tcp_port(port);
+        }
+        if let Some(address) = self.listen_address {
+            builder = builder.listen_address(address);
+        }
+        if let Some(port) = self.discovery_port {
+            builder = builder.discovery_port(port);
+        }
+        if let Some(dir) = &self.download_dir {
+            builder = builder.download_dir(dir);
+        }
+        if let Some(relay) = self.relay {
+            builder = builder.relay_enabled(relay);
+        }
+        for address in &self.trusted_peers {
+            builder = builder.bootstrap_peer(address);
+        }
+
+        let discovery = self.discovery;
+        if let Some(backend) = &discovery.backend {
+            builder = builder.discovery_backend(parse_backend(backend)?);
+        }
+        if let Some(address) = discovery.bind_address {
+            builder = builder.discovery_bind_address(address);
+        }
+        if let Some(group) = discovery.multicast_group {
+            builder = builder.multicast_group(group);
+        }
+        if discovery.group.is_some() || discovery.group_secret.is_some() {
+            let mut group = NetworkGroup::new(discovery.group.as_deref().unwrap_or_default());
+            if let Some(secret) = &discovery.group_secret {
+                group = group.with_secret(secret.as_bytes());
+            }
+            builder = builder.network_group(group);
+        }
+        if !discovery.include_interfaces.is_empty() || !discovery.exclude_interfaces.is_empty() {
+            let mut filter = InterfaceFilter::new();
+            for rule in &discovery.include_interfaces {
+                filter = filter.include(rule)?;
+            }
+            for rule in &discovery.exclude_interfaces {
+                filter = filter.exclude(rule)?;
+            }
+            builder = builder.interface_filter(filter);
+        }
+        if discovery.announce_min_ms.is_some() || discovery.announce_max_ms.is_some() {
+            let defaults = AnnounceSchedule::default();
+            let min = discovery
+                .announce_min_ms
+                .map_or(defaults.min_interval(), Duration::from_millis);
+            let max = discovery
+                .announce_max_ms
+                .map_or(defaults.max_interval(), Duration::from_millis);
+            builder = builder.announce_schedule(AnnounceSchedule::new(min, max)?);
+        }
+        if let Some(secs) = discovery.peer_timeout_secs {
+            builder = builder.peer_timeout(Duration::from_secs(secs));
+        }
+
+        Ok(builder)
+    }
+}
+
+/// `archsockrust/config.toml` under the user's config directory
+/// (`$XDG_CONFIG_HOME`, `%APPDATA%` or `~/.config`)
+pub fn default_config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("archsockrust").join("config.toml"))
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> P2PResult<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| P2PError::InvalidConfig(format!("invalid value '{}' for {}", value, key)))
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_backend(name: &str) -> P2PResult<DiscoveryBackend> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "multicast" => Ok(DiscoveryBackend::Multicast),
+        "mdns" => Ok(DiscoveryBackend::Mdns),
+        "both" => Ok(DiscoveryBackend::Both),
+        "none" => Ok(DiscoveryBackend::None),
+        _ => Err(P2PError::InvalidConfig(format!(
+            "unknown discovery backend '{}' (expected multicast, mdns, both or none)",
+            name
+        ))),
+    }
+}
+
+fn decode_key(hex: &str) -> P2PResult<[u8; 32]> {
+    let invalid = || P2PError::InvalidConfig("identity key must be 64 hex digits".to_string());
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut key = [0u8; 32];
+    for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
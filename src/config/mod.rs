@@ -0,0 +1,307 @@
+pub mod file;
+
+pub use file::{default_config_path, ConfigFile, DiscoverySettings};
+
+use crate::discovery::{AnnounceSchedule, DiscoveryBackend, InterfaceFilter, NetworkGroup, PeerIdentity};
+use crate::error::{P2PError, P2PResult};
+use crate::protocol::discovery::{DISCOVERY_PORT, MULTICAST_ADDR, PEER_TIMEOUT_SECS};
+use crate::protocol::message::{DEFAULT_TCP_PORT, DOWNLOAD_DIR, EVENT_BUFFER_SIZE, HANDSHAKE_TIMEOUT_SECS};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Everything a `P2PMessenger` is built from.
+///
+/// Created with [`P2PConfig::builder`], whose `build` checks the settings
+/// fit together before anything is bound, then passed to
+/// `P2PMessenger::with_config`.
+#[derive(Debug, Clone)]
+pub struct P2PConfig {
+    pub(crate) peer_name: String,
+    pub(crate) peer_id: Option<String>,
+    pub(crate) identity: Option<PeerIdentity>,
+    pub(crate) tcp_port: u16,
+    pub(crate) listen_address: Option<IpAddr>,
+    pub(crate) discovery_port: u16,
+    pub(crate) discovery_bind_address: Option<IpAddr>,
+    pub(crate) discovery_backend: DiscoveryBackend,
+    pub(crate) multicast_group: Ipv4Addr,
+    pub(crate) network_group: NetworkGroup,
+    pub(crate) interface_filter: InterfaceFilter,
+    pub(crate) announce_schedule: AnnounceSchedule,
+    pub(crate) peer_timeout: Duration,
+    pub(crate) handshake_timeout: Duration,
+    pub(crate) event_buffer_size: usize,
+    pub(crate) download_dir: PathBuf,
+    pub(crate) relay_enabled: bool,
+    pub(crate) bootstrap_peers: Vec<String>,
+}
+
+impl P2PConfig {
+    /// Start from the defaults for a peer called `peer_name`
+    pub fn builder(peer_name: &str) -> P2PConfigBuilder {
+        P2PConfigBuilder {
+            config: Self {
+                peer_name: peer_name.to_string(),
+                peer_id: None,
+                identity: None,
+                tcp_port: DEFAULT_TCP_PORT,
+                listen_address: None,
+                discovery_port: DISCOVERY_PORT,
+                discovery_bind_address: None,
+                discovery_backend: DiscoveryBackend::default(),
+                multicast_group: MULTICAST_ADDR.parse().expect("MULTICAST_ADDR is a valid IPv4 address"),
+                network_group: NetworkGroup::default(),
+                interface_filter: InterfaceFilter::default(),
+                announce_schedule: AnnounceSchedule::default(),
+                peer_timeout: Duration::from_secs(PEER_TIMEOUT_SECS),
+                handshake_timeout: Duration::from_secs(HANDSHAKE_TIMEOUT_SECS),
+                event_buffer_size: EVENT_BUFFER_SIZE,
+                download_dir: PathBuf::from(DOWNLOAD_DIR),
+                relay_enabled: false,
+                bootstrap_peers: Vec::new(),
+            },
+        }
+    }
+
+    pub fn peer_name(&self) -> &str {
+        &self.peer_name
+    }
+
+    pub fn tcp_port(&self) -> u16 {
+        self.tcp_port
+    }
+
+    /// Address the listener binds to; `None` for every interface
+    pub fn listen_address(&self) -> Option<IpAddr> {
+        self.listen_address
+    }
+
+    pub fn discovery_port(&self) -> u16 {
+        self.discovery_port
+    }
+
+    /// Address of the only interface discovery uses; `None` for all allowed ones
+    pub fn discovery_bind_address(&self) -> Option<IpAddr> {
+        self.discovery_bind_address
+    }
+
+    pub fn discovery_backend(&self) -> DiscoveryBackend {
+        self.discovery_backend
+    }
+
+    pub fn multicast_group(&self) -> Ipv4Addr {
+        self.multicast_group
+    }
+
+    pub fn network_group(&self) -> &NetworkGroup {
+        &self.network_group
+    }
+
+    pub fn interface_filter(&self) -> &InterfaceFilter {
+        &self.interface_filter
+    }
+
+    pub fn announce_schedule(&self) -> AnnounceSchedule {
+        self.announce_schedule
+    }
+
+    pub fn peer_timeout(&self) -> Duration {
+        self.peer_timeout
+    }
+
+    pub fn handshake_timeout(&self) -> Duration {
+        self.handshake_timeout
+    }
+
+    pub fn event_buffer_size(&self) -> usize {
+        self.event_buffer_size
+    }
+
+    pub fn download_dir(&self) -> &Path {
+        &self.download_dir
+    }
+
+    pub fn relay_enabled(&self) -> bool {
+        self.relay_enabled
+    }
+
+    pub fn bootstrap_peers(&self) -> &[String] {
+        &self.bootstrap_peers
+    }
+}
+
+/// Collects settings for a [`P2PConfig`]; see [`P2PConfig::builder`]
+#[derive(Debug, Clone)]
+pub struct P2PConfigBuilder {
+    config: P2PConfig,
+}
+
+impl P2PConfigBuilder {
+    /// Keep a peer ID across restarts instead of generating one
+    pub fn peer_id(mut self, peer_id: &str) -> Self {
+        self.config.peer_id = Some(peer_id.to_string());
+        self
+    }
+
+    /// Sign announcements with a saved identity instead of a generated one
+    pub fn identity(mut self, identity: PeerIdentity) -> Self {
+        self.config.identity = Some(identity);
+        self
+    }
+
+    /// Port to accept connections on; 0 lets the OS pick a free one
+    pub fn tcp_port(mut self, port: u16) -> Self {
+        self.config.tcp_port = port;
+        self
+    }
+
+    /// Only accept connections on `address`, e.g. a LAN address on a
+    /// multi-homed host, instead of every interface
+    pub fn listen_address(mut self, address: IpAddr) -> Self {
+        self.config.listen_address = Some(address);
+        self
+    }
+
+    /// UDP port shared by every instance that should find each other; with
+    /// 0 the OS picks one, which only peers told about it can use
+    pub fn discovery_port(mut self, port: u16) -> Self {
+        self.config.discovery_port = port;
+        self
+    }
+
+    /// Only discover on the interface with `address`, over its address family
+    pub fn discovery_bind_address(mut self, address: IpAddr) -> Self {
+        self.config.discovery_bind_address = Some(address);
+        self
+    }
+
+    pub fn discovery_backend(mut self, backend: DiscoveryBackend) -> Self {
+        self.config.discovery_backend = backend;
+        self
+    }
+
+    pub fn multicast_group(mut self, group: Ipv4Addr) -> Self {
+        self.config.multicast_group = group;
+        self
+    }
+
+    pub fn network_group(mut self, group: NetworkGroup) -> Self {
+        self.config.network_group = group;
+        self
+    }
+
+    pub fn interface_filter(mut self, filter: InterfaceFilter) -> Self {
+        self.config.interface_filter = filter;
+        self
+    }
+
+    pub fn announce_schedule(mut self, schedule: AnnounceSchedule) -> Self {
+        self.config.announce_schedule = schedule;
+        self
+    }
+
+    /// Drop peers not heard from for this long
+    pub fn peer_timeout(mut self, timeout: Duration) -> Self {
+        self.config.peer_timeout = timeout;
+        self
+    }
+
+    /// Give up on a connection whose handshake hasn't arrived after this long
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.config.handshake_timeout = timeout;
+        self
+    }
+
+    /// Events a subscriber can fall behind by before it misses the oldest
+    pub fn event_buffer_size(mut self, size: usize) -> Self {
+        self.config.event_buffer_size = size;
+        self
+    }
+
+    /// Where `save_received_file` writes files
+    pub fn download_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.config.download_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn relay_enabled(mut self, enabled: bool) -> Self {
+        self.config.relay_enabled = enabled;
+        self
+    }
+
+    /// Dial `address` (`host:port`) on start; may be given several times
+    pub fn bootstrap_peer(mut self, address: &str) -> Self {
+        self.config.bootstrap_peers.push(address.to_string());
+        self
+    }
+
+    /// Check the settings and produce the config
+    pub fn build(self) -> P2PResult<P2PConfig> {
+        let config = self.config;
+        let invalid = |reason: String| Err(P2PError::InvalidConfig(reason));
+
+        if config.peer_name.trim().is_empty() {
+            return invalid("peer name must not be empty".to_string());
+        }
+        if matches!(&config.peer_id, Some(id) if id.trim().is_empty()) {
+            return invalid("peer ID must not be empty".to_string());
+        }
+        if config.listen_address.is_some_and(|ip| ip.is_multicast()) {
+            return invalid("listen address must not be a multicast address".to_string());
+        }
+        if config.discovery_bind_address.is_some_and(|ip| ip.is_multicast()) {
+            return invalid("discovery bind address must be an interface address".to_string());
+        }
+        if let Some(address) = config.discovery_bind_address {
+            // Peers connect to the address they discovered us on, so the
+            // listener must accept that family; `::` takes IPv4 as well
+            if let Some(listen) = config.listen_address {
+                let reachable = match listen {
+                    IpAddr::V4(_) => address.is_ipv4(),
+                    IpAddr::V6(listen) => listen.is_unspecified() || address.is_ipv6(),
+                };
+                if !reachable {
+                    return invalid(format!(
+                        "discovery bind address {} is not reachable on listen address {}",
+                        address, listen
+                    ));
+                }
+            }
+        }
+        if !config.multicast_group.is_multicast() {
+            return invalid(format!("{} is not a multicast address", config.multicast_group));
+        }
+        check_announce_schedule(&config.announce_schedule, config.peer_timeout)?;
+        if config.handshake_timeout.is_zero() {
+            return invalid("handshake timeout must be non-zero".to_string());
+        }
+        if config.event_buffer_size == 0 {
+            return invalid("event buffer size must be non-zero".to_string());
+        }
+        if config.download_dir.as_os_str().is_empty() {
+            return invalid("download directory must not be empty".to_string());
+        }
+        for address in &config.bootstrap_peers {
+            let port = address.rsplit_once(':').and_then(|(_, port)| port.parse::<u16>().ok());
+            if !matches!(port, Some(port) if port != 0) {
+                return invalid(format!("bootstrap peer '{}' must be host:port", address));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+// Peers must hear from us at least three times within their timeout, so two
+// lost announcements in a row don't drop us
+pub(crate) fn check_announce_schedule(schedule: &AnnounceSchedule, peer_timeout: Duration) -> P2PResult<()> {
+    if peer_timeout < schedule.max_interval() * 3 {
+        return Err(P2PError::InvalidConfig(format!(
+            "peer timeout {:?} must be at least three announce intervals ({:?})",
+            peer_timeout,
+            schedule.max_interval() * 3
+        )));
+    }
+    Ok(())
+}
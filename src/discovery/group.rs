@@ -0,0 +1,153 @@
+use crate::{DiscoveryMessage, HandshakeMessage};
+use hmac::{Hmac, Mac};
+use prost::Message;
+use sha2::Sha256;
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Field number of `DiscoveryMessage.auth`, appended after the signed bytes
+const AUTH_FIELD: u32 = 5;
+
+/// Namespace that keeps separate sets of instances apart on one LAN.
+///
+/// Peers only discover and accept connections from peers in the same group.
+/// With a shared secret, announcements and handshakes also carry an
+/// HMAC-SHA256 tag, so knowing the group name alone is not enough to join.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct NetworkGroup {
+    name: String,
+    secret: Option<Vec<u8>>,
+}
+
+impl NetworkGroup {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            secret: None,
+        }
+    }
+
+    /// Require peers to prove they hold `secret`
+    pub fn with_secret(mut self, secret: &[u8]) -> Self {
+        self.secret = Some(secret.to_vec());
+        self
+    }
+
+    /// Group name; empty for the default group
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn has_secret(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Encode `message` for this group, signing it if there is a secret
+    pub fn seal(&self, mut message: DiscoveryMessage) -> Vec<u8> {
+        message.group = self.name.clone();
+        message.auth.clear();
+
+        let mut datagram = message.encode_to_vec();
+        if let Some(tag) = self.tag(&[&datagram]) {
+            prost::encoding::bytes::encode(AUTH_FIELD, &tag, &mut datagram);
+        }
+        datagram
+    }
+
+    /// Decode a datagram, or `None` if it is from another group or fails
+    /// authentication
+    pub fn open(&self, datagram: &[u8]) -> Option<DiscoveryMessage> {
+        let message = DiscoveryMessage::decode(datagram).ok()?;
+        if message.group != self.name {
+            return None;
+        }
+
+        match &self.secret {
+            None => message.auth.is_empty().then_some(message),
+            Some(_) => {
+                // The tag covers everything before the trailing auth field;
+                // decode only those bytes so nothing unsigned gets through
+                let trailer = prost::encoding::bytes::encoded_len(AUTH_FIELD, &message.auth);
+                let signed = datagram.get(..datagram.len().checked_sub(trailer)?)?;
+                if !self.verify(&[signed], &message.auth) {
+                    return None;
+                }
+                DiscoveryMessage::decode(signed).ok()
+            }
+        }
+    }
+
+    /// Proof that `peer_id` holds the secret, answering the challenge
+    /// `verifier_id` sent; `None` without a secret.
+    ///
+    /// Both ids are covered, prover first, so a proof one side produced
+    /// can't be reflected back to it on another connection.
+    pub(crate) fn handshake_proof(
+        &self,
+        peer_id: &str,
+        verifier_id: &str,
+        challenge: &[u8],
+        nonce: &[u8],
+    ) -> Option<Vec<u8>> {
+        self.tag(&Self::proof_parts(&self.name, peer_id, verifier_id, challenge, nonce))
+    }
+
+    /// Whether a remote handshake names this group; with a secret the peer
+    /// still has to answer our challenge with `accepts_proof`
+    pub(crate) fn accepts_handshake(&self, handshake: &HandshakeMessage) -> bool {
+        handshake.group == self.name && !handshake.nonce.is_empty()
+    }
+
+    /// Whether `proof` from `peer_id` answers the `challenge` we, as
+    /// `verifier_id`, sent, bound to the `nonce` it sent us
+    pub(crate) fn accepts_proof(
+        &self,
+        peer_id: &str,
+        verifier_id: &str,
+        challenge: &[u8],
+        nonce: &[u8],
+        proof: &[u8],
+    ) -> bool {
+        self.verify(&Self::proof_parts(&self.name, peer_id, verifier_id, challenge, nonce), proof)
+    }
+
+    fn proof_parts<'a>(
+        name: &'a str,
+        peer_id: &'a str,
+        verifier_id: &'a str,
+        challenge: &'a [u8],
+        nonce: &'a [u8],
+    ) -> [&'a [u8]; 6] {
+        [b"handshake", name.as_bytes(), peer_id.as_bytes(), verifier_id.as_bytes(), challenge, nonce]
+    }
+
+    fn mac(&self, parts: &[&[u8]]) -> Option<HmacSha256> {
+        let secret = self.secret.as_ref()?;
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+        for part in parts {
+            // Length-prefixed so part boundaries can't be shifted
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        Some(mac)
+    }
+
+    fn tag(&self, parts: &[&[u8]]) -> Option<Vec<u8>> {
+        self.mac(parts).map(|mac| mac.finalize().into_bytes().to_vec())
+    }
+
+    fn verify(&self, parts: &[&[u8]], tag: &[u8]) -> bool {
+        self.mac(parts).is_some_and(|mac| mac.verify_slice(tag).is_ok())
+    }
+}
+
+// Keeps the secret out of logs
+impl fmt::Debug for NetworkGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetworkGroup")
+            .field("name", &self.name)
+            .field("has_secret", &self.has_secret())
+            .finish()
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{PeerAnnouncement, PeerGoodbye};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::RngCore;
+use std::fmt;
+
+/// Ed25519 key pair a peer signs its announcements and goodbyes with.
+///
+/// Other peers pin the first key they see for a peer ID and report later
+/// claims to that ID under another key instead of trusting them.
+#[derive(Clone)]
+pub struct PeerIdentity {
+    key: SigningKey,
+}
+
+/// Outcome of checking a signed announcement or goodbye
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// Sent without a key, e.g. by an older version
+    Unsigned,
+    Valid,
+    Invalid,
+}
+
+impl PeerIdentity {
+    pub fn generate() -> Self {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self::from_secret(&secret)
+    }
+
+    /// Restore an identity saved with [`PeerIdentity::secret`]
+    pub fn from_secret(secret: &[u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(secret),
+        }
+    }
+
+    pub fn secret(&self) -> [u8; 32] {
+        self.key.to_bytes()
+    }
+
+    pub fn public_key(&self) -> Vec<u8> {
+        self.key.verifying_key().to_bytes().to_vec()
+    }
+
+    /// Fill in `announce.public_key` and sign it; set the timestamp first
+    pub fn sign_announcement(&self, announce: &mut PeerAnnouncement) {
+        announce.public_key = self.public_key();
+        announce.signature = self.key.sign(&announcement_bytes(announce)).to_bytes().to_vec();
+    }
+
+    /// Sign `goodbye`; set the timestamp first
+    pub fn sign_goodbye(&self, goodbye: &mut PeerGoodbye) {
+        goodbye.signature = self.key.sign(&goodbye_bytes(goodbye)).to_bytes().to_vec();
+    }
+}
+
+// Keeps the secret out of logs
+impl fmt::Debug for PeerIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerIdentity")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+/// Check an announcement against the key it carries
+pub fn verify_announcement(announce: &PeerAnnouncement) -> Verification {
+    if announce.public_key.is_empty() && announce.signature.is_empty() {
+        return Verification::Unsigned;
+    }
+    verify(&announce.public_key, &announcement_bytes(announce), &announce.signature)
+}
+
+/// Check a goodbye against the key its sender announced
+pub fn verify_goodbye(goodbye: &PeerGoodbye, public_key: &[u8]) -> Verification {
+    if goodbye.signature.is_empty() {
+        return Verification::Unsigned;
+    }
+    verify(public_key, &goodbye_bytes(goodbye), &goodbye.signature)
+}
+
+fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> Verification {
+    let Ok(public_key) = <[u8; 32]>::try_from(public_key) else {
+        return Verification::Invalid;
+    };
+    let Ok(signature) = Signature::from_slice(signature) else {
+        return Verification::Invalid;
+    };
+
+    match VerifyingKey::from_bytes(&public_key) {
+        Ok(key) if key.verify(message, &signature).is_ok() => Verification::Valid,
+        _ => Verification::Invalid,
+    }
+}
+
+// The signed fields in a fixed order, since protobuf encoding of the
+// metadata map isn't deterministic
+fn announcement_bytes(announce: &PeerAnnouncement) -> Vec<u8> {
+    let mut metadata: Vec<_> = announce.metadata.iter().collect();
+    metadata.sort();
+
+    let mut bytes = CanonicalBytes::new(b"announce");
+    bytes
+        .field(announce.peer_id.as_bytes())
+        .field(announce.peer_name.as_bytes())
+        .field(&announce.tcp_port.to_be_bytes())
+        .field(announce.version.as_bytes())
+        .field(&[announce.accepts_files as u8])
+        .field(announce.os.as_bytes())
+        .field(announce.hostname.as_bytes())
+        .field(announce.status.as_bytes())
+        .field(&(metadata.len() as u64).to_be_bytes());
+    for (key, value) in metadata {
+        bytes.field(key.as_bytes()).field(value.as_bytes());
+    }
+    bytes
+        .field(&announce.timestamp.to_be_bytes())
+        .field(&announce.public_key);
+    bytes.0
+}
+
+fn goodbye_bytes(goodbye: &PeerGoodbye) -> Vec<u8> {
+    let mut bytes = CanonicalBytes::new(b"goodbye");
+    bytes
+        .field(goodbye.peer_id.as_bytes())
+        .field(&goodbye.timestamp.to_be_bytes());
+    bytes.0
+}
+
+// Length-prefixed fields, so values can't be shifted between fields
+struct CanonicalBytes(Vec<u8>);
+
+impl CanonicalBytes {
+    fn new(domain: &[u8]) -> Self {
+        let mut bytes = Self(Vec::new());
+        bytes.field(domain);
+        bytes
+    }
+
+    fn field(&mut self, value: &[u8]) -> &mut Self {
+        self.0.extend_from_slice(&(value.len() as u64).to_be_bytes());
+        self.0.extend_from_slice(value);
+        self
+    }
+}
@@ -0,0 +1,173 @@
+use crate::error::{P2PError, P2PResult};
+use if_addrs::{get_if_addrs, IfAddr};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// One address on a local network interface
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NetworkInterface {
+    pub name: String,
+    /// OS interface index, used as the scope of link-local IPv6 traffic
+    pub index: u32,
+    pub ip: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl fmt::Display for NetworkInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}/{}", self.name, self.ip, self.prefix_len)
+    }
+}
+
+/// Matches interfaces by name or by the subnet their address is in.
+///
+/// Parsed from `"eth0"`, `"docker*"` (trailing wildcard), `"10.8.0.0/16"`
+/// or `"fd00::/8"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceRule {
+    Name(String),
+    Subnet { network: IpAddr, prefix_len: u8 },
+}
+
+impl InterfaceRule {
+    pub fn matches(&self, interface: &NetworkInterface) -> bool {
+        match self {
+            Self::Name(pattern) => match pattern.strip_suffix('*') {
+                Some(prefix) => interface.name.starts_with(prefix),
+                None => interface.name == *pattern,
+            },
+            Self::Subnet { network, prefix_len } => same_subnet(*network, interface.ip, *prefix_len),
+        }
+    }
+}
+
+impl FromStr for InterfaceRule {
+    type Err = P2PError;
+
+    fn from_str(rule: &str) -> P2PResult<Self> {
+        let invalid = || P2PError::InvalidConfig(format!("invalid interface rule '{}'", rule));
+
+        let rule = rule.trim();
+        if rule.is_empty() {
+            return Err(invalid());
+        }
+
+        let Some((network, prefix_len)) = rule.split_once('/') else {
+            return Ok(Self::Name(rule.to_string()));
+        };
+        let network: IpAddr = network.parse().map_err(|_| invalid())?;
+        let prefix_len: u8 = prefix_len.parse().map_err(|_| invalid())?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_len {
+            return Err(invalid());
+        }
+
+        Ok(Self::Subnet { network, prefix_len })
+    }
+}
+
+/// Which interfaces discovery announces on and listens to.
+///
+/// An interface is used unless an exclude rule matches it; when include
+/// rules are given it must also match one of them. The default uses every
+/// interface.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceFilter {
+    include: Vec<InterfaceRule>,
+    exclude: Vec<InterfaceRule>,
+    // Set by a bind address; only the interface holding it is used
+    address: Option<IpAddr>,
+}
+
+impl InterfaceFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only use interfaces matching `rule` (or another include rule)
+    pub fn include(mut self, rule: &str) -> P2PResult<Self> {
+        self.include.push(rule.parse()?);
+        Ok(self)
+    }
+
+    /// Never use interfaces matching `rule`, e.g. `"docker*"` or `"tun*"`
+    pub fn exclude(mut self, rule: &str) -> P2PResult<Self> {
+        self.exclude.push(rule.parse()?);
+        Ok(self)
+    }
+
+    /// Only use the interface holding `ip`, on top of the other rules
+    pub fn with_address(mut self, ip: IpAddr) -> Self {
+        self.address = Some(ip);
+        self
+    }
+
+    pub fn allows(&self, interface: &NetworkInterface) -> bool {
+        if self.address.is_some_and(|ip| ip != interface.ip) {
+            return false;
+        }
+        if self.exclude.iter().any(|rule| rule.matches(interface)) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|rule| rule.matches(interface))
+    }
+
+    /// Local interface addresses this filter allows, sorted
+    pub fn interfaces(&self) -> Vec<NetworkInterface> {
+        let mut interfaces: Vec<NetworkInterface> = local_interfaces()
+            .into_iter()
+            .filter(|interface| self.allows(interface))
+            .collect();
+        interfaces.sort();
+        interfaces
+    }
+}
+
+/// One-line description of a `P2PEvent::NetworkChanged`, e.g.
+/// `"+wlan0 192.168.1.20/24, -eth0 10.0.0.5/24"`
+pub fn describe_changes(added: &[NetworkInterface], removed: &[NetworkInterface]) -> String {
+    added
+        .iter()
+        .map(|interface| format!("+{}", interface))
+        .chain(removed.iter().map(|interface| format!("-{}", interface)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Every address on every interface, as reported by the OS
+pub(crate) fn local_interfaces() -> Vec<NetworkInterface> {
+    let Ok(interfaces) = get_if_addrs() else {
+        return Vec::new();
+    };
+
+    interfaces
+        .into_iter()
+        .map(|iface| {
+            let (ip, netmask) = match iface.addr {
+                IfAddr::V4(v4) => (IpAddr::V4(v4.ip), v4.netmask.to_bits().count_ones()),
+                IfAddr::V6(v6) => (IpAddr::V6(v6.ip), v6.netmask.to_bits().count_ones()),
+            };
+            NetworkInterface {
+                name: iface.name,
+                index: iface.index.unwrap_or(0),
+                ip,
+                prefix_len: netmask as u8,
+            }
+        })
+        .collect()
+}
+
+fn same_subnet(network: IpAddr, ip: IpAddr, prefix_len: u8) -> bool {
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            network.to_bits() & mask == ip.to_bits() & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            network.to_bits() & mask == ip.to_bits() & mask
+        }
+        _ => false,
+    }
+}
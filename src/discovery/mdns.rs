@@ -0,0 +1,351 @@
+use crate::discovery::interfaces::{self, InterfaceFilter};
+use crate::discovery::{record_peer, Discovery, NetworkGroup, SharedProfile};
+use crate::error::{P2PError, P2PResult};
+use crate::protocol::discovery::MDNS_SERVICE_TYPE;
+use crate::{P2PEvent, PeerInfo};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent, ServiceInfo, VERIFY_TIMEOUT_DEFAULT};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+/// DNS-SD discovery: advertises this peer as `_archsockrust._tcp.local` with
+/// its id and name in TXT records, and browses for other peers.
+///
+/// Standard tools (`avahi-browse`, `dns-sd`, python-zeroconf) can see peers
+/// advertised this way.
+pub struct MdnsDiscovery {
+    peer_id: String,
+    peer_name: String,
+    // Set once the listener is bound, which may be on an ephemeral port
+    tcp_port: AtomicU16,
+    daemon: ServiceDaemon,
+    peers: Arc<Mutex<HashMap<String, PeerInfo>>>,
+    // Instance fullname -> peer ID, so removals and reconfirmations can be
+    // matched up
+    instances: Arc<Mutex<HashMap<String, String>>>,
+    is_running: Arc<Mutex<bool>>,
+    profile: SharedProfile,
+    group: NetworkGroup,
+    event_sender: Option<broadcast::Sender<P2PEvent>>,
+}
+
+// TXT keys for app metadata are prefixed so they can't clash with ours
+const METADATA_PREFIX: &str = "meta.";
+
+impl MdnsDiscovery {
+    pub fn new(peer_id: String, peer_name: String, tcp_port: u16) -> P2PResult<Self> {
+        let daemon = ServiceDaemon::new().map_err(|e| P2PError::Mdns(e.to_string()))?;
+
+        Ok(Self {
+            peer_id,
+            peer_name,
+            tcp_port: AtomicU16::new(tcp_port),
+            daemon,
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            instances: Arc::new(Mutex::new(HashMap::new())),
+            is_running: Arc::new(Mutex::new(false)),
+            profile: SharedProfile::default(),
+            group: NetworkGroup::default(),
+            event_sender: None,
+        })
+    }
+
+    /// Set event sender for sending peer discovery events
+    pub fn set_event_sender(&mut self, sender: broadcast::Sender<P2PEvent>) {
+        self.event_sender = Some(sender);
+    }
+
+    /// Use `profile` for the advertised TXT records
+    pub fn set_profile(&mut self, profile: SharedProfile) {
+        self.profile = profile;
+    }
+
+    /// Only report services advertising `group`. TXT records can't carry the
+    /// secret, so a group secret is enforced at the connection handshake.
+    pub fn set_group(&mut self, group: NetworkGroup) {
+        self.group = group;
+    }
+
+    /// Advertise `port` for connections; takes effect on the next `start`
+    pub fn set_tcp_port(&self, port: u16) {
+        self.tcp_port.store(port, Ordering::Relaxed);
+    }
+
+    /// Only advertise and browse on interfaces `filter` allows.
+    ///
+    /// Applies to the interfaces present now; the daemon follows later
+    /// changes on its own. Loopback keeps the daemon's default.
+    pub fn set_interface_filter(&self, filter: &InterfaceFilter) {
+        for interface in interfaces::local_interfaces() {
+            if interface.ip.is_loopback() {
+                continue;
+            }
+            let kind = IfKind::Addr(interface.ip);
+            let _ = if filter.allows(&interface) {
+                self.daemon.enable_interface(kind)
+            } else {
+                self.daemon.disable_interface(kind)
+            };
+        }
+    }
+
+    /// Re-register the service so TXT record changes are advertised
+    pub fn refresh(&self) -> P2PResult<()> {
+        if !*self.is_running.lock().unwrap() {
+            return Ok(());
+        }
+        self.daemon
+            .register(self.service_info()?)
+            .map_err(|e| P2PError::Mdns(e.to_string()))
+    }
+
+    pub async fn start(&self) -> P2PResult<()> {
+        self.spawn_browser()
+    }
+
+    fn spawn_browser(&self) -> P2PResult<()> {
+        {
+            let mut running = self.is_running.lock().unwrap();
+            if *running {
+                return Ok(());
+            }
+            *running = true;
+        }
+
+        self.daemon
+            .register(self.service_info()?)
+            .map_err(|e| P2PError::Mdns(e.to_string()))?;
+
+        let receiver = self
+            .daemon
+            .browse(MDNS_SERVICE_TYPE)
+            .map_err(|e| P2PError::Mdns(e.to_string()))?;
+
+        let our_peer_id = self.peer_id.clone();
+        let group = self.group.name().to_string();
+        let peers = self.peers.clone();
+        let instances = self.instances.clone();
+        let event_sender = self.event_sender.clone();
+
+        tokio::spawn(async move {
+            while let Ok(event) = receiver.recv_async().await {
+                match event {
+                    ServiceEvent::ServiceResolved(info) => {
+                        if info.get_property_val_str("group").unwrap_or_default() != group {
+                            continue;
+                        }
+                        let Some(peer_info) = Self::peer_from_service(&info) else {
+                            continue;
+                        };
+                        if peer_info.id == our_peer_id {
+                            continue;
+                        }
+
+                        instances.lock().unwrap().insert(info.get_fullname().to_string(), peer_info.id.clone());
+                        record_peer(&mut peers.lock().unwrap(), peer_info, event_sender.as_ref());
+                    }
+                    // The daemon only reports instances it hears from; any
+                    // sighting of a known one keeps the peer fresh
+                    ServiceEvent::ServiceFound(_, fullname) => {
+                        let Some(peer_id) = instances.lock().unwrap().get(&fullname).cloned() else {
+                            continue;
+                        };
+                        if let Some(peer) = peers.lock().unwrap().get_mut(&peer_id) {
+                            peer.last_seen = now_secs();
+                        }
+                    }
+                    ServiceEvent::ServiceRemoved(_, fullname) => {
+                        let Some(peer_id) = instances.lock().unwrap().remove(&fullname) else {
+                            continue;
+                        };
+                        let lost = peers.lock().unwrap().remove(&peer_id);
+                        if let (Some(peer), Some(sender)) = (lost, &event_sender) {
+                            let _ = sender.send(P2PEvent::PeerLost(peer));
+                        }
+                    }
+                    ServiceEvent::SearchStopped(_) => break,
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        {
+            let mut running = self.is_running.lock().unwrap();
+            if !*running {
+                return;
+            }
+            *running = false;
+        }
+
+        let _ = self.daemon.stop_browse(MDNS_SERVICE_TYPE);
+        let _ = self.daemon.unregister(&self.fullname());
+        self.peers.lock().unwrap().clear();
+        self.instances.lock().unwrap().clear();
+    }
+
+    pub fn get_peers(&self) -> Vec<PeerInfo> {
+        let peers = self.peers.lock().unwrap();
+        peers.values().cloned().collect()
+    }
+
+    fn fullname(&self) -> String {
+        format!("{}.{}", self.peer_id, MDNS_SERVICE_TYPE)
+    }
+
+    fn service_info(&self) -> P2PResult<ServiceInfo> {
+        let host_name = format!("{}.local.", self.peer_id);
+        let profile = self.profile.read().unwrap();
+        let mut properties: Vec<(String, String)> = vec![
+            ("id".to_string(), self.peer_id.clone()),
+            ("name".to_string(), self.peer_name.clone()),
+            ("version".to_string(), profile.version.clone()),
+            ("files".to_string(), if profile.accepts_files { "1" } else { "0" }.to_string()),
+            ("os".to_string(), profile.os.clone()),
+            ("host".to_string(), profile.hostname.clone()),
+            ("status".to_string(), profile.status.clone()),
+            ("group".to_string(), self.group.name().to_string()),
+        ];
+        properties.extend(
+            profile
+                .metadata
+                .iter()
+                .map(|(key, value)| (format!("{}{}", METADATA_PREFIX, key), value.clone())),
+        );
+
+        // Addresses are filled in per interface by the daemon
+        ServiceInfo::new(
+            MDNS_SERVICE_TYPE,
+            &self.peer_id,
+            &host_name,
+            "",
+            self.tcp_port.load(Ordering::Relaxed),
+            &properties[..],
+        )
+        .map(ServiceInfo::enable_addr_auto)
+        .map_err(|e| P2PError::Mdns(e.to_string()))
+    }
+
+    fn peer_from_service(info: &ServiceInfo) -> Option<PeerInfo> {
+        let id = info.get_property_val_str("id")?.to_string();
+        let name = info
+            .get_property_val_str("name")
+            .unwrap_or(&id)
+            .to_string();
+
+        // Prefer IPv4 since that is what the TCP listener binds
+        let addresses = info.get_addresses();
+        let ip = addresses
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .or_else(|| addresses.iter().next())
+            .map(IpAddr::to_string)?;
+
+        let property = |key: &str| info.get_property_val_str(key).unwrap_or_default().to_string();
+        let metadata = info
+            .get_properties()
+            .iter()
+            .filter_map(|prop| {
+                let key = prop.key().strip_prefix(METADATA_PREFIX)?;
+                Some((key.to_string(), prop.val_str().to_string()))
+            })
+            .collect();
+
+        Some(PeerInfo {
+            id,
+            name,
+            ip,
+            port: info.get_port() as u32,
+            last_seen: now_secs(),
+            version: property("version"),
+            accepts_files: property("files") == "1",
+            os: property("os"),
+            hostname: property("host"),
+            status: property("status"),
+            metadata,
+            // TXT records are unsigned; a group secret is checked at the handshake
+            ..Default::default()
+        })
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl Discovery for MdnsDiscovery {
+    fn name(&self) -> &str {
+        "mdns"
+    }
+
+    fn start(&self) -> P2PResult<()> {
+        self.spawn_browser()
+    }
+
+    fn stop(&self) {
+        MdnsDiscovery::stop(self);
+    }
+
+    fn peers(&self) -> Vec<PeerInfo> {
+        self.get_peers()
+    }
+
+    fn set_event_sender(&mut self, sender: broadcast::Sender<P2PEvent>) {
+        MdnsDiscovery::set_event_sender(self, sender);
+    }
+
+    fn set_profile(&mut self, profile: SharedProfile) {
+        MdnsDiscovery::set_profile(self, profile);
+    }
+
+    fn profile_changed(&self) {
+        let _ = self.refresh();
+    }
+
+    fn set_tcp_port(&self, port: u16) {
+        MdnsDiscovery::set_tcp_port(self, port);
+    }
+
+    fn set_group(&mut self, group: NetworkGroup) {
+        MdnsDiscovery::set_group(self, group);
+    }
+
+    fn set_interface_filter(&self, filter: InterfaceFilter) {
+        MdnsDiscovery::set_interface_filter(self, &filter);
+    }
+
+    // The daemon only reports changes, so a peer that keeps answering with
+    // the same records isn't heard from again after it resolves. Quiet peers
+    // are reconfirmed instead of dropped: the daemon flushes any that don't
+    // answer, which reports them as lost, and the rest get another timeout.
+    fn cleanup_stale_peers(&self, timeout_secs: u64) {
+        let now = now_secs();
+        let instances = self.instances.lock().unwrap();
+        let mut peers = self.peers.lock().unwrap();
+        for (fullname, peer_id) in instances.iter() {
+            let Some(peer) = peers.get_mut(peer_id) else {
+                continue;
+            };
+            if now.saturating_sub(peer.last_seen) >= timeout_secs {
+                let _ = self.daemon.verify(fullname.clone(), VERIFY_TIMEOUT_DEFAULT);
+                peer.last_seen = now;
+            }
+        }
+    }
+}
+
+impl Drop for MdnsDiscovery {
+    fn drop(&mut self) {
+        self.stop();
+        let _ = self.daemon.shutdown();
+    }
+}
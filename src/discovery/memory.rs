@@ -0,0 +1,104 @@
+use crate::discovery::{expire_peers, record_peer, Discovery, InterfaceFilter};
+use crate::error::P2PResult;
+use crate::{P2PEvent, PeerInfo};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// In-process backend whose peers are added and removed by hand.
+///
+/// Clones share state, so tests can keep a handle after giving the backend
+/// to a messenger and inject peers without touching the network.
+#[derive(Clone, Default)]
+pub struct MemoryDiscovery {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    peers: HashMap<String, PeerInfo>,
+    is_running: bool,
+    event_sender: Option<broadcast::Sender<P2PEvent>>,
+    interface_filter: InterfaceFilter,
+}
+
+impl MemoryDiscovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace a peer, announcing it if the backend is running
+    pub fn add_peer(&self, peer: PeerInfo) {
+        let mut state = self.state.lock().unwrap();
+        let sender = state.running_sender();
+        record_peer(&mut state.peers, peer, sender.as_ref());
+    }
+
+    /// Remove a peer, reporting it as lost if the backend is running
+    pub fn remove_peer(&self, peer_id: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(peer) = state.peers.remove(peer_id) else {
+            return false;
+        };
+        if let Some(sender) = state.running_sender() {
+            let _ = sender.send(P2PEvent::PeerLost(peer));
+        }
+        true
+    }
+
+    /// The interface filter last handed to this backend
+    pub fn interface_filter(&self) -> InterfaceFilter {
+        self.state.lock().unwrap().interface_filter.clone()
+    }
+}
+
+impl MemoryState {
+    // Events are only reported between start and stop
+    fn running_sender(&self) -> Option<broadcast::Sender<P2PEvent>> {
+        self.event_sender.clone().filter(|_| self.is_running)
+    }
+}
+
+impl Discovery for MemoryDiscovery {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn start(&self) -> P2PResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.is_running {
+            return Ok(());
+        }
+        state.is_running = true;
+
+        // Announce peers added before start
+        if let Some(sender) = &state.event_sender {
+            for peer in state.peers.values() {
+                let _ = sender.send(P2PEvent::PeerDiscovered(peer.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().is_running = false;
+    }
+
+    fn peers(&self) -> Vec<PeerInfo> {
+        self.state.lock().unwrap().peers.values().cloned().collect()
+    }
+
+    fn set_event_sender(&mut self, sender: broadcast::Sender<P2PEvent>) {
+        self.state.lock().unwrap().event_sender = Some(sender);
+    }
+
+    fn set_interface_filter(&self, filter: InterfaceFilter) {
+        self.state.lock().unwrap().interface_filter = filter;
+    }
+
+    fn cleanup_stale_peers(&self, timeout_secs: u64) {
+        let mut state = self.state.lock().unwrap();
+        let sender = state.running_sender();
+        expire_peers(&mut state.peers, timeout_secs, sender.as_ref());
+    }
+}
@@ -1,25 +1,207 @@
-use crate::error::P2PResult;
-use crate::protocol::discovery::{DISCOVERY_PORT, MULTICAST_ADDR};
-use crate::{PeerInfo, DiscoveryMessage, PeerAnnouncement, PeerRequest, discovery_message, P2PEvent};
-use prost::Message;
+pub mod group;
+pub mod identity;
+pub mod interfaces;
+pub mod mdns;
+pub mod memory;
+pub mod profile;
+pub mod schedule;
+pub mod static_list;
+
+pub use group::NetworkGroup;
+pub use identity::PeerIdentity;
+pub use interfaces::{InterfaceFilter, InterfaceRule, NetworkInterface};
+pub use profile::{PeerProfile, SharedProfile};
+pub use schedule::AnnounceSchedule;
+
+use crate::discovery::identity::Verification;
+use crate::discovery::schedule::AnnounceScheduler;
+use crate::error::{P2PError, P2PResult};
+use crate::protocol::address;
+use crate::protocol::discovery::{
+    DISCOVERY_BUFFER_SIZE, INTERFACE_POLL_INTERVAL_SECS, MULTICAST_ADDR, MULTICAST_ADDR_V6, PEER_EXPIRY_INTERVAL_SECS,
+    PEER_TIMEOUT_SECS, REQUEST_RESPONSE_JITTER_MS,
+};
+use crate::{PeerInfo, DiscoveryMessage, PeerAnnouncement, PeerGoodbye, PeerRequest, discovery_message, P2PEvent};
+use rand::Rng;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
 use std::collections::HashMap;
-use std::net::{SocketAddr, UdpSocket, Ipv4Addr};
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::sync::mpsc;
+use std::io;
+use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket as TokioUdpSocket;
+use tokio::sync::{broadcast, Notify};
+use tokio::task::JoinHandle;
 use tokio::time::interval;
 use uuid::Uuid;
 use if_addrs::get_if_addrs;
 
+/// A source of peers that a messenger can run alongside others.
+///
+/// Backends report peers through the event sender as
+/// `P2PEvent::PeerDiscovered`, `PeerUpdated` and `PeerLost`; the messenger
+/// merges `peers()` from all of them, keeping one entry per peer id.
+pub trait Discovery: Send + Sync {
+    /// Short label for logs and diagnostics
+    fn name(&self) -> &str;
+
+    /// Begin discovering. Called from within the tokio runtime.
+    fn start(&self) -> P2PResult<()>;
+
+    fn stop(&self);
+
+    fn peers(&self) -> Vec<PeerInfo>;
+
+    fn set_event_sender(&mut self, sender: broadcast::Sender<P2PEvent>);
+
+    /// Actively probe for peers. Passive backends need not override this.
+    fn request_peers(&self) -> P2PResult<()> {
+        Ok(())
+    }
+
+    /// Use `profile` for what this peer advertises. Backends that don't
+    /// advertise need not override this.
+    fn set_profile(&mut self, _profile: SharedProfile) {}
+
+    /// The shared profile changed; re-advertise if running
+    fn profile_changed(&self) {}
+
+    /// The TCP port this peer accepts connections on, once it is bound.
+    /// Called before `start`; backends that don't advertise need not override this.
+    fn set_tcp_port(&self, _port: u16) {}
+
+    /// Address this backend receives discovery traffic on, if it has a socket of its own
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Only see peers in `group`. Called before `start`; backends whose
+    /// peers are configured by hand need not override this.
+    fn set_group(&mut self, _group: NetworkGroup) {}
+
+    /// Only announce and listen on interfaces `filter` allows. May be called
+    /// while running; backends that don't use the network need not override this.
+    fn set_interface_filter(&self, _filter: InterfaceFilter) {}
+
+    /// Bounds for how often to announce. May be called while running;
+    /// backends that don't send announcements need not override this.
+    fn set_announce_schedule(&self, _schedule: AnnounceSchedule) {}
+
+    /// Claims to a known peer's ID that didn't match its identity key
+    fn conflicting_claims(&self) -> Vec<PeerInfo> {
+        Vec::new()
+    }
+
+    /// Forget peers not heard from within `timeout_secs`, reporting them as lost
+    fn cleanup_stale_peers(&self, _timeout_secs: u64) {}
+}
+
+// Stores a sighting of `peer`, reporting it as discovered when new or
+// updated when anything but `last_seen` changed
+pub(crate) fn record_peer(
+    peers: &mut HashMap<String, PeerInfo>,
+    peer: PeerInfo,
+    event_sender: Option<&broadcast::Sender<P2PEvent>>,
+) {
+    let event = match peers.insert(peer.id.clone(), peer.clone()) {
+        None => Some(P2PEvent::PeerDiscovered(peer)),
+        Some(mut previous) => {
+            previous.last_seen = peer.last_seen;
+            (previous != peer).then_some(P2PEvent::PeerUpdated(peer))
+        }
+    };
+
+    if let (Some(event), Some(sender)) = (event, event_sender) {
+        let _ = sender.send(event);
+    }
+}
+
+// Drops peers not seen within `timeout_secs`, reporting each as lost
+pub(crate) fn expire_peers(
+    peers: &mut HashMap<String, PeerInfo>,
+    timeout_secs: u64,
+    event_sender: Option<&broadcast::Sender<P2PEvent>>,
+) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let stale: Vec<String> = peers
+        .values()
+        .filter(|peer| now.saturating_sub(peer.last_seen) >= timeout_secs)
+        .map(|peer| peer.id.clone())
+        .collect();
+
+    for peer_id in stale {
+        if let (Some(peer), Some(sender)) = (peers.remove(&peer_id), event_sender) {
+            let _ = sender.send(P2PEvent::PeerLost(peer));
+        }
+    }
+}
+
+/// Which discovery mechanisms a messenger runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiscoveryBackend {
+    /// Protobuf announcements over the UDP multicast group
+    #[default]
+    Multicast,
+    /// DNS-SD service advertisement and browsing
+    Mdns,
+    /// Both of the above
+    Both,
+    /// No built-in backend; add your own with `P2PMessenger::add_discovery_backend`
+    None,
+}
+
+impl DiscoveryBackend {
+    pub fn uses_multicast(self) -> bool {
+        matches!(self, Self::Multicast | Self::Both)
+    }
+
+    pub fn uses_mdns(self) -> bool {
+        matches!(self, Self::Mdns | Self::Both)
+    }
+}
+
 pub struct DiscoveryService {
     pub peer_id: String,
     peer_name: String,
-    tcp_port: u16,
+    // Set once the listener is bound, which may be on an ephemeral port
+    tcp_port: AtomicU16,
     discovery_port: u16,
-    socket: UdpSocket,
+    multicast_group: Ipv4Addr,
+    // Keeps discovery to the interface with this address
+    bind_address: Option<IpAddr>,
+    // Shared discovery port, joined to the multicast group on every interface;
+    // `None` when bound to an IPv6 address
+    socket: Option<UdpSocket>,
+    // Ephemeral port for sending, so direct replies reach this instance only
+    unicast_socket: Option<UdpSocket>,
+    // IPv6 counterparts on the link-local group; `None` where IPv6 is unavailable
+    multicast_group_v6: Ipv6Addr,
+    socket_v6: Option<UdpSocket>,
+    unicast_socket_v6: Option<UdpSocket>,
+    // Which interfaces to use, and the ones the groups are currently joined on
+    interface_filter: Arc<RwLock<InterfaceFilter>>,
+    // `None` until the first interface scan
+    active_interfaces: Arc<Mutex<Option<Vec<NetworkInterface>>>>,
     peers: Arc<Mutex<HashMap<String, PeerInfo>>>,
-    is_running: Arc<Mutex<bool>>,
-    event_sender: Option<mpsc::UnboundedSender<P2PEvent>>,
+    // Signs our announcements; peers' keys are pinned in `trust`
+    identity: PeerIdentity,
+    trust: Arc<Mutex<TrustState>>,
+    // When to announce next; notified to restart the announcement loop early
+    scheduler: Arc<Mutex<AnnounceScheduler>>,
+    schedule_changed: Arc<Notify>,
+    // Peers not heard from for this long are dropped
+    peer_timeout: Duration,
+    is_running: Mutex<bool>,
+    // Receive and announce loops, aborted on stop
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    profile: SharedProfile,
+    group: NetworkGroup,
+    event_sender: Option<broadcast::Sender<P2PEvent>>,
 }
 
 impl DiscoveryService {
@@ -67,29 +249,337 @@ impl DiscoveryService {
         addresses
     }
 
+    /// Get the IPv4 addresses of all local interfaces, loopback included
+    pub fn get_multicast_interfaces() -> Vec<Ipv4Addr> {
+        match get_if_addrs() {
+            Ok(interfaces) => interfaces
+                .into_iter()
+                .filter_map(|iface| match iface.addr {
+                    if_addrs::IfAddr::V4(ifv4) => Some(ifv4.ip),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Get the indexes of all interfaces with an IPv6 address
+    pub fn get_multicast_interfaces_v6() -> Vec<u32> {
+        let mut indexes: Vec<u32> = match get_if_addrs() {
+            Ok(interfaces) => interfaces
+                .into_iter()
+                .filter_map(|iface| match iface.addr {
+                    if_addrs::IfAddr::V6(_) => iface.index,
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        indexes.sort_unstable();
+        indexes.dedup();
+        indexes
+    }
+
     pub fn new(peer_name: String, tcp_port: u16, discovery_port: u16) -> P2PResult<Self> {
-        let socket = UdpSocket::bind(format!("0.0.0.0:{}", discovery_port))?;
-        socket.set_broadcast(true)?;
-        socket.set_nonblocking(true)?;
+        let group = MULTICAST_ADDR.parse().expect("MULTICAST_ADDR is a valid IPv4 address");
+        Self::with_multicast_group(peer_name, tcp_port, discovery_port, group)
+    }
+
+    /// Create a discovery service announcing on a custom multicast group.
+    ///
+    /// Instances that should find each other must share both the group and
+    /// the discovery port; the port can be shared by many local instances.
+    pub fn with_multicast_group(
+        peer_name: String,
+        tcp_port: u16,
+        discovery_port: u16,
+        multicast_group: Ipv4Addr,
+    ) -> P2PResult<Self> {
+        if !multicast_group.is_multicast() {
+            return Err(P2PError::Network(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a multicast address", multicast_group),
+            )));
+        }
+
+        // With port 0 the OS picks one, and the IPv6 socket shares it
+        let socket = Self::bind_multicast_socket(Ipv4Addr::UNSPECIFIED, discovery_port)?;
+        let discovery_port = socket.local_addr()?.port();
+        let unicast_socket = Self::bind_unicast_socket(Ipv4Addr::UNSPECIFIED)?;
+
+        // IPv6 is best effort so IPv4-only hosts keep working
+        let multicast_group_v6 = MULTICAST_ADDR_V6.parse().expect("MULTICAST_ADDR_V6 is a valid IPv6 address");
+        let socket_v6 = Self::bind_multicast_socket_v6(Ipv6Addr::UNSPECIFIED, discovery_port, 0).ok();
+        let unicast_socket_v6 = socket_v6
+            .as_ref()
+            .and_then(|_| Self::bind_unicast_socket_v6(Ipv6Addr::UNSPECIFIED, 0).ok());
 
         Ok(Self {
             peer_id: Uuid::new_v4().to_string(),
             peer_name,
-            tcp_port,
+            tcp_port: AtomicU16::new(tcp_port),
             discovery_port,
-            socket,
+            multicast_group,
+            bind_address: None,
+            socket: Some(socket),
+            unicast_socket: Some(unicast_socket),
+            multicast_group_v6,
+            socket_v6,
+            unicast_socket_v6,
+            interface_filter: Arc::new(RwLock::new(InterfaceFilter::default())),
+            active_interfaces: Arc::new(Mutex::new(None)),
             peers: Arc::new(Mutex::new(HashMap::new())),
-            is_running: Arc::new(Mutex::new(false)),
+            identity: PeerIdentity::generate(),
+            trust: Arc::new(Mutex::new(TrustState::default())),
+            scheduler: Arc::new(Mutex::new(AnnounceScheduler::new(AnnounceSchedule::default()))),
+            schedule_changed: Arc::new(Notify::new()),
+            peer_timeout: Duration::from_secs(PEER_TIMEOUT_SECS),
+            is_running: Mutex::new(false),
+            tasks: Mutex::new(Vec::new()),
+            profile: SharedProfile::default(),
+            group: NetworkGroup::default(),
             event_sender: None,
         })
     }
     
+    pub fn multicast_group(&self) -> Ipv4Addr {
+        self.multicast_group
+    }
+
+    pub fn multicast_group_v6(&self) -> Ipv6Addr {
+        self.multicast_group_v6
+    }
+
+    /// Port discovery listens on; differs from the requested one if that was 0
+    pub fn discovery_port(&self) -> u16 {
+        self.discovery_port
+    }
+
+    /// Address and port discovery receives on; unspecified unless bound
+    /// with `with_bind_address`
+    pub fn local_addr(&self) -> SocketAddr {
+        SocketAddr::from((self.bind_address.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)), self.discovery_port))
+    }
+
+    /// Announce `port` for connections instead of the one given at creation.
+    ///
+    /// Takes effect on the next `start`.
+    pub fn set_tcp_port(&self, port: u16) {
+        self.tcp_port.store(port, Ordering::Relaxed);
+    }
+
+    /// Whether IPv6 discovery sockets could be opened on this host
+    pub fn has_ipv6(&self) -> bool {
+        self.socket_v6.is_some() && self.unicast_socket_v6.is_some()
+    }
+
+    /// Keep discovery on the interface with `address`, e.g. off the public
+    /// side of a multi-homed host.
+    ///
+    /// Announcements and replies leave from `address` and the group is only
+    /// joined there. Discovery over the other address family is turned off;
+    /// an IPv6 address needs IPv6 discovery to be available on this host.
+    pub fn with_bind_address(mut self, address: IpAddr) -> P2PResult<Self> {
+        if address.is_unspecified() {
+            return Ok(self);
+        }
+
+        // Linux only hands group traffic to sockets bound to the group or the
+        // wildcard address; Windows wants the interface address instead
+        match address {
+            IpAddr::V4(address) => {
+                let receive_address = if cfg!(windows) { address } else { self.multicast_group };
+                self.socket = Some(Self::bind_multicast_socket(receive_address, self.discovery_port)?);
+                self.unicast_socket = Some(Self::bind_unicast_socket(address)?);
+                self.socket_v6 = None;
+                self.unicast_socket_v6 = None;
+            }
+            IpAddr::V6(address) => {
+                if !self.has_ipv6() {
+                    return Err(P2PError::InvalidConfig(format!(
+                        "cannot bind discovery to {}: IPv6 discovery is unavailable on this host",
+                        address
+                    )));
+                }
+                // Link-local addresses are only unique together with their interface
+                let scope_id = InterfaceFilter::default()
+                    .with_address(IpAddr::V6(address))
+                    .interfaces()
+                    .first()
+                    .map_or(0, |interface| interface.index);
+                let receive_address = if cfg!(windows) { address } else { self.multicast_group_v6 };
+                self.socket_v6 = Some(Self::bind_multicast_socket_v6(receive_address, self.discovery_port, scope_id)?);
+                self.unicast_socket_v6 = Some(Self::bind_unicast_socket_v6(address, scope_id)?);
+                self.socket = None;
+                self.unicast_socket = None;
+            }
+        }
+        self.bind_address = Some(address);
+
+        let filter = self.interface_filter.read().unwrap().clone();
+        *self.interface_filter.write().unwrap() = filter.with_address(address);
+        Ok(self)
+    }
+
+    pub fn bind_address(&self) -> Option<IpAddr> {
+        self.bind_address
+    }
+
+    fn bind_multicast_socket(address: Ipv4Addr, port: u16) -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.bind(&SocketAddrV4::new(address, port).into())?;
+
+        // Groups are joined per interface on start, once the filter is known
+        socket.set_nonblocking(true)?;
+        Ok(socket.into())
+    }
+
+    fn bind_multicast_socket_v6(address: Ipv6Addr, port: u16, scope_id: u32) -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+        // Leave IPv4 to the other socket on the same port
+        socket.set_only_v6(true)?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.bind(&SocketAddrV6::new(address, port, 0, scope_id).into())?;
+        socket.set_nonblocking(true)?;
+        Ok(socket.into())
+    }
+
+    fn bind_unicast_socket(address: Ipv4Addr) -> io::Result<UdpSocket> {
+        let socket = UdpSocket::bind(SocketAddrV4::new(address, 0))?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+
+    fn bind_unicast_socket_v6(address: Ipv6Addr, scope_id: u32) -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_only_v6(true)?;
+        socket.bind(&SocketAddrV6::new(address, 0, 0, scope_id).into())?;
+        socket.set_multicast_loop_v6(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket.into())
+    }
+
+    fn multicast_sender(&self) -> io::Result<MulticastSender> {
+        Ok(MulticastSender {
+            socket: match &self.unicast_socket {
+                Some(socket) => Some(socket.try_clone()?),
+                None => None,
+            },
+            socket_v6: match &self.unicast_socket_v6 {
+                Some(socket) => Some(socket.try_clone()?),
+                None => None,
+            },
+            group: self.multicast_group,
+            group_v6: self.multicast_group_v6,
+            port: self.discovery_port,
+            interfaces: self.active_interfaces.clone(),
+        })
+    }
+
+    fn interface_monitor(&self) -> io::Result<InterfaceMonitor> {
+        Ok(InterfaceMonitor {
+            filter: self.interface_filter.clone(),
+            active: self.active_interfaces.clone(),
+            socket: match &self.socket {
+                Some(socket) => Some(socket.try_clone()?),
+                None => None,
+            },
+            socket_v6: match &self.socket_v6 {
+                Some(socket) => Some(socket.try_clone()?),
+                None => None,
+            },
+            group: self.multicast_group,
+            group_v6: self.multicast_group_v6,
+        })
+    }
+
+    /// Only announce and listen on interfaces `filter` allows.
+    ///
+    /// Applied straight away if the service is running, re-announcing and
+    /// reporting `P2PEvent::NetworkChanged` when the interfaces in use change.
+    pub fn set_interface_filter(&self, filter: InterfaceFilter) {
+        *self.interface_filter.write().unwrap() = match self.bind_address {
+            Some(address) => filter.with_address(address),
+            None => filter,
+        };
+        if !*self.is_running.lock().unwrap() {
+            return;
+        }
+
+        if let Some(event) = self.interface_monitor().ok().and_then(|monitor| monitor.refresh()) {
+            let _ = self.announce_now();
+            self.restart_announcements();
+            if let Some(sender) = &self.event_sender {
+                let _ = sender.send(event);
+            }
+        }
+    }
+
+    /// Interfaces discovery is currently joined to the groups on
+    pub fn active_interfaces(&self) -> Vec<NetworkInterface> {
+        self.active_interfaces.lock().unwrap().clone().unwrap_or_default()
+    }
+
+    /// Announce within `schedule`'s bounds instead of the defaults
+    pub fn with_announce_schedule(self, schedule: AnnounceSchedule) -> Self {
+        self.set_announce_schedule(schedule);
+        self
+    }
+
+    /// Change how often to announce, starting over with fast announcements
+    pub fn set_announce_schedule(&self, schedule: AnnounceSchedule) {
+        self.scheduler.lock().unwrap().set_schedule(schedule);
+        self.schedule_changed.notify_one();
+    }
+
+    pub fn announce_schedule(&self) -> AnnounceSchedule {
+        self.scheduler.lock().unwrap().schedule()
+    }
+
+    // Back to fast announcements so peers on a changed network find us soon
+    fn restart_announcements(&self) {
+        self.scheduler.lock().unwrap().restart();
+        self.schedule_changed.notify_one();
+    }
+
+    /// Drop peers not heard from for `timeout` instead of `PEER_TIMEOUT_SECS`
+    pub fn with_peer_timeout(mut self, timeout: Duration) -> Self {
+        self.peer_timeout = timeout;
+        self
+    }
+
+    /// Use an existing peer ID instead of the generated one
+    pub fn with_peer_id(mut self, peer_id: String) -> Self {
+        self.peer_id = peer_id;
+        self
+    }
+
+    /// Sign announcements with `identity` instead of a generated key
+    pub fn with_identity(mut self, identity: PeerIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    pub fn public_key(&self) -> Vec<u8> {
+        self.identity.public_key()
+    }
+
     /// Set event sender for sending peer discovery events
-    pub fn set_event_sender(&mut self, sender: mpsc::UnboundedSender<P2PEvent>) {
+    pub fn set_event_sender(&mut self, sender: broadcast::Sender<P2PEvent>) {
         self.event_sender = Some(sender);
     }
 
     pub async fn start(&self) -> P2PResult<()> {
+        self.spawn_tasks()
+    }
+
+    fn spawn_tasks(&self) -> P2PResult<()> {
         {
             let mut running = self.is_running.lock().unwrap();
             if *running {
@@ -98,118 +588,336 @@ impl DiscoveryService {
             *running = true;
         }
 
-        let peers_clone = self.peers.clone();
-        let socket = self.socket.try_clone()?;
-        let is_running_clone = self.is_running.clone();
-        let event_sender_clone = self.event_sender.clone();
+        // Group traffic arrives on the shared ports, direct replies on our own;
+        // each is paired with the socket that answers requests of its family
+        let mut receivers = Vec::new();
+        if let (Some(socket), Some(unicast_socket)) = (&self.socket, &self.unicast_socket) {
+            let socket = Arc::new(TokioUdpSocket::from_std(socket.try_clone()?)?);
+            let unicast_socket = Arc::new(TokioUdpSocket::from_std(unicast_socket.try_clone()?)?);
+            receivers.push((socket, unicast_socket.clone()));
+            receivers.push((unicast_socket.clone(), unicast_socket));
+        }
+        if let (Some(socket_v6), Some(unicast_socket_v6)) = (&self.socket_v6, &self.unicast_socket_v6) {
+            let socket_v6 = Arc::new(TokioUdpSocket::from_std(socket_v6.try_clone()?)?);
+            let unicast_socket_v6 = Arc::new(TokioUdpSocket::from_std(unicast_socket_v6.try_clone()?)?);
+            receivers.push((socket_v6, unicast_socket_v6.clone()));
+            receivers.push((unicast_socket_v6.clone(), unicast_socket_v6));
+        }
 
-        tokio::spawn(async move {
-            let mut buffer = [0u8; 1024];
-            
-            loop {
-                if !*is_running_clone.lock().unwrap() {
-                    break;
-                }
+        // Join the groups on the allowed interfaces before anything is sent
+        let monitor = self.interface_monitor()?;
+        monitor.refresh();
 
-                match socket.recv_from(&mut buffer) {
-                    Ok((size, src)) => {
-                        if let Ok(msg) = DiscoveryMessage::decode(&buffer[..size]) {
-                            Self::handle_discovery_message(msg, src, &peers_clone, &event_sender_clone);
-                        }
+        let state = ReceiveState {
+            announcer: self.announcer(),
+            peers: self.peers.clone(),
+            trust: self.trust.clone(),
+            scheduler: self.scheduler.clone(),
+            addresses: Arc::new(Mutex::new(HashMap::new())),
+            event_sender: self.event_sender.clone(),
+        };
+        self.scheduler.lock().unwrap().restart();
+        let mut tasks = Vec::new();
+        for (receiver, reply_socket) in receivers {
+            tasks.push(tokio::spawn(Self::receive_loop(receiver, reply_socket, state.clone())));
+        }
+        tasks.push(self.start_announcement_loop()?);
+        tasks.push(self.start_expiry_loop());
+        tasks.push(self.start_interface_loop(monitor)?);
+
+        self.tasks.lock().unwrap().extend(tasks);
+        Ok(())
+    }
+
+    // Handles datagrams as they arrive until the task is aborted
+    async fn receive_loop(
+        socket: Arc<TokioUdpSocket>,
+        reply_socket: Arc<TokioUdpSocket>,
+        state: ReceiveState,
+    ) {
+        let mut buffer = vec![0u8; DISCOVERY_BUFFER_SIZE];
+
+        loop {
+            match socket.recv_from(&mut buffer).await {
+                Ok((size, src)) => {
+                    // Traffic from other groups, or failing their secret, is ignored
+                    if let Some(msg) = state.announcer.group.open(&buffer[..size]) {
+                        Self::handle_discovery_message(msg, src, &reply_socket, &state);
                     }
-                    Err(_) => {}
                 }
-
-                tokio::time::sleep(Duration::from_millis(100)).await;
+                // Windows reports ICMP port unreachable from earlier sends here
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(_) => break,
             }
-        });
+        }
+    }
 
-        self.start_announcement_loop().await;
-        Ok(())
+    // Announces on the adaptive schedule: quickly after start, then less
+    // often the longer the network stays stable and the more peers share it
+    fn start_announcement_loop(&self) -> P2PResult<JoinHandle<()>> {
+        let sender = self.multicast_sender()?;
+        let announcer = self.announcer();
+        let peers = self.peers.clone();
+        let scheduler = self.scheduler.clone();
+        let schedule_changed = self.schedule_changed.clone();
+
+        Ok(tokio::spawn(async move {
+            loop {
+                let peer_count = peers.lock().unwrap().len();
+                let delay = scheduler.lock().unwrap().next_delay(peer_count);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    // Restarted; work out the delay again from the new state
+                    _ = schedule_changed.notified() => continue,
+                }
+
+                let now = Instant::now();
+                if scheduler.lock().unwrap().is_redundant(now) {
+                    continue;
+                }
+                sender.send(&announcer.datagram());
+                scheduler.lock().unwrap().record_sent(now);
+            }
+        }))
     }
 
-    async fn start_announcement_loop(&self) {
-        let socket = self.socket.try_clone().unwrap();
-        let peer_id = self.peer_id.clone();
-        let peer_name = self.peer_name.clone();
-        let tcp_port = self.tcp_port;
-        let _discovery_port = self.discovery_port;
-        let is_running = self.is_running.clone();
+    fn start_expiry_loop(&self) -> JoinHandle<()> {
+        let peers = self.peers.clone();
+        let trust = self.trust.clone();
+        let timeout_secs = self.peer_timeout.as_secs();
+        let event_sender = self.event_sender.clone();
 
         tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(5));
-            
+            let mut interval = interval(Duration::from_secs(PEER_EXPIRY_INTERVAL_SECS));
+
             loop {
                 interval.tick().await;
-                
-                if !*is_running.lock().unwrap() {
-                    break;
-                }
+                expire_peers(&mut peers.lock().unwrap(), timeout_secs, event_sender.as_ref());
+                trust.lock().unwrap().expire(timeout_secs);
+            }
+        })
+    }
 
-                let announce = DiscoveryMessage {
-                    message: Some(discovery_message::Message::Announce(PeerAnnouncement {
-                        peer_name: peer_name.clone(),
-                        peer_id: peer_id.clone(),
-                        tcp_port: tcp_port as u32,
-                    })),
-                };
-
-                let mut buf = Vec::new();
-                if announce.encode(&mut buf).is_ok() {
-                    // Get dynamic broadcast addresses
-                    let broadcast_addresses = Self::get_broadcast_addresses();
-                    
-                    // Send to each broadcast address on multiple discovery ports
-                    // to support multiple instances with different discovery ports
-                    let discovery_ports = [DISCOVERY_PORT, 6970, 6972, 6974, 6976, 6978, 7001, 7003];
-                    
-                    for addr in broadcast_addresses {
-                        for port in discovery_ports {
-                            let target = format!("{}:{}", addr, port);
-                            if let Err(_e) = socket.send_to(&buf, &target) {
-                                // Silently ignore errors to avoid spam
-                                // Most ports won't be listening anyway
-                            }
-                        }
+    // Follows interfaces coming and going, e.g. a laptop switching networks,
+    // and re-announces on the new set so peers there find us straight away
+    fn start_interface_loop(&self, monitor: InterfaceMonitor) -> P2PResult<JoinHandle<()>> {
+        let sender = self.multicast_sender()?;
+        let announcer = self.announcer();
+        let scheduler = self.scheduler.clone();
+        let schedule_changed = self.schedule_changed.clone();
+        let event_sender = self.event_sender.clone();
+
+        Ok(tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(INTERFACE_POLL_INTERVAL_SECS));
+
+            loop {
+                interval.tick().await;
+                if let Some(event) = monitor.refresh() {
+                    sender.send(&announcer.datagram());
+                    {
+                        let mut scheduler = scheduler.lock().unwrap();
+                        scheduler.record_sent(Instant::now());
+                        scheduler.restart();
+                    }
+                    schedule_changed.notify_one();
+                    if let Some(event_sender) = &event_sender {
+                        let _ = event_sender.send(event);
                     }
                 }
             }
-        });
+        }))
+    }
+
+    fn announcer(&self) -> Announcer {
+        Announcer {
+            peer_id: self.peer_id.clone(),
+            peer_name: self.peer_name.clone(),
+            tcp_port: self.tcp_port.load(Ordering::Relaxed),
+            profile: self.profile.clone(),
+            group: self.group.clone(),
+            identity: self.identity.clone(),
+        }
+    }
+
+    /// Use `profile` for what this service announces
+    pub fn set_profile(&mut self, profile: SharedProfile) {
+        self.profile = profile;
+    }
+
+    /// Only see peers in `group`, signing our messages if it has a secret.
+    ///
+    /// Takes effect on the next `start`.
+    pub fn set_group(&mut self, group: NetworkGroup) {
+        self.group = group;
+    }
+
+    pub fn group(&self) -> &NetworkGroup {
+        &self.group
+    }
+
+    /// Announce now so a profile change shows up without waiting for the next round
+    pub fn announce_now(&self) -> P2PResult<()> {
+        if !*self.is_running.lock().unwrap() {
+            return Ok(());
+        }
+        self.multicast_sender()?.send(&self.announcer().datagram());
+        self.scheduler.lock().unwrap().record_sent(Instant::now());
+        Ok(())
     }
 
     fn handle_discovery_message(
         msg: DiscoveryMessage,
         src: SocketAddr,
-        peers: &Arc<Mutex<HashMap<String, PeerInfo>>>,
-        event_sender: &Option<mpsc::UnboundedSender<P2PEvent>>,
+        socket: &Arc<TokioUdpSocket>,
+        state: &ReceiveState,
     ) {
-        if let Some(discovery_message::Message::Announce(announce)) = msg.message {
-            let mut peers_map = peers.lock().unwrap();
-            
-            // Check if this is a new peer
-            let is_new_peer = !peers_map.contains_key(&announce.peer_id);
-            
-            let peer_info = PeerInfo {
-                id: announce.peer_id.clone(),
-                name: announce.peer_name.clone(),
-                ip: src.ip().to_string(),
-                port: announce.tcp_port,
-                last_seen: SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-            };
-            
-            peers_map.insert(announce.peer_id.clone(), peer_info.clone());
-            
-            // Send event for newly discovered peer
-            if is_new_peer {
-                if let Some(sender) = event_sender {
-                    let _ = sender.send(P2PEvent::PeerDiscovered(peer_info));
+        match msg.message {
+            Some(discovery_message::Message::Announce(announce)) => {
+                Self::handle_announcement(announce, src, state);
+            }
+            Some(discovery_message::Message::Request(request)) => {
+                Self::handle_request(request, src, socket, &state.announcer, &state.scheduler);
+            }
+            Some(discovery_message::Message::Goodbye(goodbye)) => {
+                Self::handle_goodbye(goodbye, &state.peers, &state.trust, &state.event_sender);
+            }
+            None => {}
+        }
+    }
+
+    // Answers a probe with a unicast announcement after a random delay,
+    // so a request on a busy LAN doesn't trigger a burst of simultaneous replies
+    fn handle_request(
+        request: PeerRequest,
+        src: SocketAddr,
+        socket: &Arc<TokioUdpSocket>,
+        announcer: &Announcer,
+        scheduler: &Arc<Mutex<AnnounceScheduler>>,
+    ) {
+        if request.peer_id == announcer.peer_id {
+            return;
+        }
+        if !scheduler.lock().unwrap().should_reply(&request.peer_id, Instant::now()) {
+            return;
+        }
+
+        let buf = announcer.datagram();
+        let socket = socket.clone();
+
+        let jitter = rand::thread_rng().gen_range(0..=REQUEST_RESPONSE_JITTER_MS);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(jitter)).await;
+            let _ = socket.send_to(&buf, src).await;
+        });
+    }
+
+    fn handle_announcement(announce: PeerAnnouncement, src: SocketAddr, state: &ReceiveState) {
+        let ReceiveState { announcer, peers, trust, scheduler, addresses, event_sender } = state;
+        let verification = identity::verify_announcement(&announce);
+        if verification == Verification::Invalid {
+            return;
+        }
+        let timestamp = announce.timestamp;
+
+        let claim = PeerInfo {
+            id: announce.peer_id,
+            name: announce.peer_name,
+            ip: address::ip_to_string(&src),
+            port: announce.tcp_port,
+            last_seen: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            version: announce.version,
+            accepts_files: announce.accepts_files,
+            os: announce.os,
+            hostname: announce.hostname,
+            status: announce.status,
+            metadata: announce.metadata,
+            public_key: announce.public_key,
+        };
+
+        // The first key seen for an ID is pinned; claims under any other key,
+        // or unsigned ones once a key is known, are kept aside as conflicts
+        let mut trust = trust.lock().unwrap();
+        match (trust.pinned.get(&claim.id), verification) {
+            (None, Verification::Unsigned) => {}
+            (Some(pin), Verification::Valid) if pin.key == claim.public_key => {
+                // Copies of one announcement arrive once per interface, so
+                // anything not newer is a duplicate or a replay; neither may
+                // move the peer
+                if timestamp <= pin.newest {
+                    return;
                 }
+                trust.pin(&claim, timestamp);
+            }
+            (None, Verification::Valid) => {
+                trust.pin(&claim, timestamp);
             }
+            _ => {
+                trust.record_conflict(claim, event_sender.as_ref());
+                return;
+            }
+        }
+
+        // On a busy group our own rounds thin out; see `AnnounceScheduler::is_redundant`
+        let now = Instant::now();
+        let mut scheduler = scheduler.lock().unwrap();
+        if claim.id != announcer.peer_id {
+            scheduler.record_heard(&claim.id, now);
+        }
+        // A peer skips at most one round of its own, so two intervals
+        // without hearing it at an address means it has moved
+        let moved_after = scheduler.schedule().max_interval() * 2;
+        drop(scheduler);
+
+        let mut peers_map = peers.lock().unwrap();
+        
+        // Each announcement arrives once per interface and address family;
+        // settle on the most routable address instead of flipping between
+        // them, until the peer stops being heard there
+        let mut addresses = addresses.lock().unwrap();
+        addresses.retain(|_, heard| now.duration_since(*heard) < moved_after);
+        let ip = match peers_map.get(&claim.id) {
+            Some(known)
+                if known.ip != claim.ip
+                    && addresses.contains_key(&claim.id)
+                    && address::preference(&known.ip) >= address::preference(&claim.ip) =>
+            {
+                known.ip.clone()
+            }
+            _ => {
+                addresses.insert(claim.id.clone(), now);
+                claim.ip.clone()
+            }
+        };
+        
+        record_peer(&mut peers_map, PeerInfo { ip, ..claim }, event_sender.as_ref());
+    }
+
+    fn handle_goodbye(
+        goodbye: PeerGoodbye,
+        peers: &Arc<Mutex<HashMap<String, PeerInfo>>>,
+        trust: &Arc<Mutex<TrustState>>,
+        event_sender: &Option<broadcast::Sender<P2PEvent>>,
+    ) {
+        // Only the holder of the pinned key can say goodbye for a signed peer,
+        // and only after everything it announced, so captured goodbyes can't
+        // be replayed against a later session
+        if let Some(pin) = trust.lock().unwrap().pinned.get_mut(&goodbye.peer_id) {
+            if identity::verify_goodbye(&goodbye, &pin.key) != Verification::Valid || goodbye.timestamp <= pin.newest {
+                return;
+            }
+            // Announcements from before the goodbye are replays now too; the
+            // pin expires with the peer timeout like any other
+            pin.newest = goodbye.timestamp;
+        }
+
+        let lost = peers.lock().unwrap().remove(&goodbye.peer_id);
+        if let (Some(peer), Some(sender)) = (lost, event_sender) {
+            let _ = sender.send(P2PEvent::PeerLost(peer));
         }
-        // Handle Request case if needed in the future
     }
 
     pub fn get_peers(&self) -> Vec<PeerInfo> {
@@ -219,39 +927,390 @@ impl DiscoveryService {
 
     pub fn request_peers(&self) -> P2PResult<()> {
         let request = DiscoveryMessage {
-            message: Some(discovery_message::Message::Request(PeerRequest {})),
+            message: Some(discovery_message::Message::Request(PeerRequest {
+                peer_id: self.peer_id.clone(),
+            })),
+            ..Default::default()
         };
-        let mut buf = Vec::new();
-        request.encode(&mut buf)?;
-        
-        // Get dynamic broadcast addresses
-        let broadcast_addresses = Self::get_broadcast_addresses();
+        let buf = self.group.seal(request);
         
-        // Send to each broadcast address on multiple discovery ports
-        let discovery_ports = [DISCOVERY_PORT, 6970, 6972, 6974, 6976, 6978, 7001, 7003];
-        
-        for addr in broadcast_addresses {
-            for port in discovery_ports {
-                let target = format!("{}:{}", addr, port);
-                if let Err(_e) = self.socket.send_to(&buf, &target) {
-                    // Silently ignore errors to avoid spam
-                }
-            }
-        }
+        // Sent from the unicast sockets so replies come straight back to us
+        self.multicast_sender()?.send(&buf);
         Ok(())
     }
 
+    /// Probe the network and collect the peers that answer within `window`.
+    ///
+    /// Returns only peers heard from since the probe was sent.
+    pub async fn discover_peers_within(&self, window: Duration) -> P2PResult<Vec<PeerInfo>> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        self.request_peers()?;
+        tokio::time::sleep(window).await;
+
+        let peers = self.peers.lock().unwrap();
+        Ok(peers
+            .values()
+            .filter(|peer| peer.last_seen >= started)
+            .cloned()
+            .collect())
+    }
+
     pub fn stop(&self) {
-        *self.is_running.lock().unwrap() = false;
+        let was_running = std::mem::replace(&mut *self.is_running.lock().unwrap(), false);
+        if was_running {
+            self.send_goodbye();
+        }
+
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+        // Rediscovered from scratch if started again, keys included
+        self.peers.lock().unwrap().clear();
+        *self.trust.lock().unwrap() = TrustState::default();
+    }
+
+    fn send_goodbye(&self) {
+        let mut goodbye = PeerGoodbye {
+            peer_id: self.peer_id.clone(),
+            timestamp: signed_timestamp(),
+            ..Default::default()
+        };
+        self.identity.sign_goodbye(&mut goodbye);
+        let goodbye = DiscoveryMessage {
+            message: Some(discovery_message::Message::Goodbye(goodbye)),
+            ..Default::default()
+        };
+        if let Ok(sender) = self.multicast_sender() {
+            sender.send(&self.group.seal(goodbye));
+        }
     }
 
+    /// Drop peers not heard from within `timeout_secs`, reporting them as lost.
+    ///
+    /// Runs automatically with the service's peer timeout while it is started.
     pub fn cleanup_stale_peers(&self, timeout_secs: u64) {
+        expire_peers(&mut self.peers.lock().unwrap(), timeout_secs, self.event_sender.as_ref());
+        self.trust.lock().unwrap().expire(timeout_secs);
+    }
+
+    /// Claims to known peer IDs that didn't match the pinned identity key.
+    ///
+    /// The known entry is kept for connecting; these are only for display,
+    /// and are reported as `P2PEvent::PeerConflict` when first seen.
+    pub fn conflicting_claims(&self) -> Vec<PeerInfo> {
+        self.trust.lock().unwrap().conflicts.clone()
+    }
+}
+
+impl Drop for DiscoveryService {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Discovery for DiscoveryService {
+    fn name(&self) -> &str {
+        "multicast"
+    }
+
+    fn start(&self) -> P2PResult<()> {
+        self.spawn_tasks()
+    }
+
+    fn stop(&self) {
+        DiscoveryService::stop(self);
+    }
+
+    fn peers(&self) -> Vec<PeerInfo> {
+        self.get_peers()
+    }
+
+    fn set_event_sender(&mut self, sender: broadcast::Sender<P2PEvent>) {
+        DiscoveryService::set_event_sender(self, sender);
+    }
+
+    fn request_peers(&self) -> P2PResult<()> {
+        DiscoveryService::request_peers(self)
+    }
+
+    fn cleanup_stale_peers(&self, timeout_secs: u64) {
+        DiscoveryService::cleanup_stale_peers(self, timeout_secs);
+    }
+
+    fn set_profile(&mut self, profile: SharedProfile) {
+        DiscoveryService::set_profile(self, profile);
+    }
+
+    fn profile_changed(&self) {
+        let _ = self.announce_now();
+    }
+
+    fn set_tcp_port(&self, port: u16) {
+        DiscoveryService::set_tcp_port(self, port);
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        Some(DiscoveryService::local_addr(self))
+    }
+
+    fn set_group(&mut self, group: NetworkGroup) {
+        DiscoveryService::set_group(self, group);
+    }
+
+    fn set_interface_filter(&self, filter: InterfaceFilter) {
+        DiscoveryService::set_interface_filter(self, filter);
+    }
+
+    fn set_announce_schedule(&self, schedule: AnnounceSchedule) {
+        DiscoveryService::set_announce_schedule(self, schedule);
+    }
+
+    fn conflicting_claims(&self) -> Vec<PeerInfo> {
+        DiscoveryService::conflicting_claims(self)
+    }
+}
+
+// Identity keys pinned per peer ID, and the claims that contradicted them
+#[derive(Default)]
+struct TrustState {
+    pinned: HashMap<String, Pin>,
+    conflicts: Vec<PeerInfo>,
+}
+
+// The key a peer ID announced under, and how far its signed messages got
+struct Pin {
+    key: Vec<u8>,
+    // Newest signed timestamp accepted, in the peer's Unix milliseconds
+    newest: u64,
+    // Our Unix seconds when it last announced, for expiring with the peer
+    last_seen: u64,
+}
+
+impl TrustState {
+    fn pin(&mut self, claim: &PeerInfo, timestamp: u64) {
+        let pin = Pin {
+            key: claim.public_key.clone(),
+            newest: timestamp,
+            last_seen: claim.last_seen,
+        };
+        self.pinned.insert(claim.id.clone(), pin);
+    }
+
+    fn record_conflict(&mut self, claim: PeerInfo, event_sender: Option<&broadcast::Sender<P2PEvent>>) {
+        let known = self.conflicts.iter_mut().find(|conflict| {
+            conflict.id == claim.id
+                && conflict.ip == claim.ip
+                && conflict.port == claim.port
+                && conflict.public_key == claim.public_key
+        });
+
+        match known {
+            Some(conflict) => conflict.last_seen = claim.last_seen,
+            None => {
+                if let Some(sender) = event_sender {
+                    let _ = sender.send(P2PEvent::PeerConflict(claim.clone()));
+                }
+                self.conflicts.push(claim);
+            }
+        }
+    }
+
+    // Forget pins and conflicts not heard from within `timeout_secs`, so a
+    // peer ID that went away can be claimed afresh
+    fn expire(&mut self, timeout_secs: u64) {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
+        self.pinned
+            .retain(|_, pin| now.saturating_sub(pin.last_seen) < timeout_secs);
+        self.conflicts
+            .retain(|conflict| now.saturating_sub(conflict.last_seen) < timeout_secs);
+    }
+}
+
+// What the receive loops share with the service
+#[derive(Clone)]
+struct ReceiveState {
+    announcer: Announcer,
+    peers: Arc<Mutex<HashMap<String, PeerInfo>>>,
+    trust: Arc<Mutex<TrustState>>,
+    scheduler: Arc<Mutex<AnnounceScheduler>>,
+    // When each peer was last heard from at its stored address
+    addresses: Arc<Mutex<HashMap<String, Instant>>>,
+    event_sender: Option<broadcast::Sender<P2PEvent>>,
+}
+
+// Builds our announcement from the current profile
+#[derive(Clone)]
+struct Announcer {
+    peer_id: String,
+    peer_name: String,
+    tcp_port: u16,
+    profile: SharedProfile,
+    group: NetworkGroup,
+    identity: PeerIdentity,
+}
+
+impl Announcer {
+    fn message(&self) -> DiscoveryMessage {
+        let profile = self.profile.read().unwrap();
+        let mut announce = PeerAnnouncement {
+            peer_name: self.peer_name.clone(),
+            peer_id: self.peer_id.clone(),
+            tcp_port: self.tcp_port as u32,
+            version: profile.version.clone(),
+            accepts_files: profile.accepts_files,
+            os: profile.os.clone(),
+            hostname: profile.hostname.clone(),
+            status: profile.status.clone(),
+            metadata: profile.metadata.clone(),
+            timestamp: signed_timestamp(),
+            ..Default::default()
+        };
+        self.identity.sign_announcement(&mut announce);
 
-        let mut peers = self.peers.lock().unwrap();
-        peers.retain(|_, peer| now - peer.last_seen < timeout_secs);
+        DiscoveryMessage {
+            message: Some(discovery_message::Message::Announce(announce)),
+            ..Default::default()
+        }
+    }
+
+    // The announcement as sent, sealed for our group
+    fn datagram(&self) -> Vec<u8> {
+        self.group.seal(self.message())
+    }
+}
+
+// Sends datagrams to the IPv4 and IPv6 groups out of every active interface
+struct MulticastSender {
+    socket: Option<UdpSocket>,
+    socket_v6: Option<UdpSocket>,
+    group: Ipv4Addr,
+    group_v6: Ipv6Addr,
+    port: u16,
+    interfaces: Arc<Mutex<Option<Vec<NetworkInterface>>>>,
+}
+
+impl MulticastSender {
+    fn send(&self, buf: &[u8]) {
+        // Held for the whole send: every sender shares the sockets, and one
+        // choosing its interface between our set_multicast_if and send_to
+        // would send our datagram out of the wrong one
+        let interfaces = self.interfaces.lock().unwrap();
+
+        // Before the first scan there is no interface list yet; let the OS
+        // pick one. After it, no allowed interfaces means nothing is sent.
+        let Some(interfaces) = interfaces.as_deref() else {
+            if let Some(socket) = &self.socket {
+                let _ = socket.send_to(buf, SocketAddrV4::new(self.group, self.port));
+            }
+            return;
+        };
+
+        if let Some(socket) = &self.socket {
+            let target = SocketAddrV4::new(self.group, self.port);
+            let sock_ref = SockRef::from(socket);
+            for interface in ipv4_addresses(interfaces) {
+                if sock_ref.set_multicast_if_v4(&interface).is_ok() {
+                    let _ = socket.send_to(buf, target);
+                }
+            }
+        }
+
+        // A link-local group is only meaningful with the interface as scope
+        if let Some(socket_v6) = &self.socket_v6 {
+            let sock_ref = SockRef::from(socket_v6);
+            for index in ipv6_indexes(interfaces) {
+                if sock_ref.set_multicast_if_v6(index).is_ok() {
+                    let _ = socket_v6.send_to(buf, SocketAddrV6::new(self.group_v6, self.port, 0, index));
+                }
+            }
+        }
+    }
+}
+
+// Keeps the group memberships, and the interfaces senders use, in line with
+// the interfaces that are up and allowed by the filter
+struct InterfaceMonitor {
+    filter: Arc<RwLock<InterfaceFilter>>,
+    active: Arc<Mutex<Option<Vec<NetworkInterface>>>>,
+    socket: Option<UdpSocket>,
+    socket_v6: Option<UdpSocket>,
+    group: Ipv4Addr,
+    group_v6: Ipv6Addr,
+}
+
+impl InterfaceMonitor {
+    // Rejoins the groups if the interfaces changed, returning what changed
+    fn refresh(&self) -> Option<P2PEvent> {
+        let mut scanned = self.active.lock().unwrap();
+        let current = self.filter.read().unwrap().interfaces();
+        if scanned.as_ref() == Some(&current) {
+            return None;
+        }
+        let active = scanned.take().unwrap_or_default();
+
+        if let Some(socket) = &self.socket {
+            let sock_ref = SockRef::from(socket);
+            let (before, after) = (ipv4_addresses(&active), ipv4_addresses(&current));
+            for interface in before.iter().filter(|interface| !after.contains(interface)) {
+                let _ = sock_ref.leave_multicast_v4(&self.group, interface);
+            }
+            for interface in after.iter().filter(|interface| !before.contains(interface)) {
+                let _ = sock_ref.join_multicast_v4(&self.group, interface);
+            }
+        }
+
+        if let Some(socket_v6) = &self.socket_v6 {
+            let sock_ref = SockRef::from(socket_v6);
+            let (before, after) = (ipv6_indexes(&active), ipv6_indexes(&current));
+            for index in before.iter().filter(|index| !after.contains(index)) {
+                let _ = sock_ref.leave_multicast_v6(&self.group_v6, *index);
+            }
+            for index in after.iter().filter(|index| !before.contains(index)) {
+                let _ = sock_ref.join_multicast_v6(&self.group_v6, *index);
+            }
+        }
+
+        let added = current.iter().filter(|interface| !active.contains(interface)).cloned().collect();
+        let removed = active.iter().filter(|interface| !current.contains(interface)).cloned().collect();
+        *scanned = Some(current);
+        Some(P2PEvent::NetworkChanged { added, removed })
     }
-}
\ No newline at end of file
+}
+
+// Unix milliseconds for signed messages; finer than `last_seen` so a goodbye
+// sent right after an announcement still orders after it
+fn signed_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn ipv4_addresses(interfaces: &[NetworkInterface]) -> Vec<Ipv4Addr> {
+    interfaces
+        .iter()
+        .filter_map(|interface| match interface.ip {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        })
+        .collect()
+}
+
+// IPv6 groups are joined per interface, however many addresses it has
+fn ipv6_indexes(interfaces: &[NetworkInterface]) -> Vec<u32> {
+    let mut indexes: Vec<u32> = interfaces
+        .iter()
+        .filter(|interface| interface.ip.is_ipv6())
+        .map(|interface| interface.index)
+        .collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    indexes
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+/// What this peer tells others about itself when it announces
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerProfile {
+    pub version: String,
+    pub accepts_files: bool,
+    pub os: String,
+    pub hostname: String,
+    /// Free-form presence, e.g. "online", "away", "busy"
+    pub status: String,
+    /// App-defined key/value pairs, e.g. team or role
+    pub metadata: HashMap<String, String>,
+}
+
+impl Default for PeerProfile {
+    fn default() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            accepts_files: true,
+            os: env::consts::OS.to_string(),
+            hostname: local_hostname(),
+            status: "online".to_string(),
+            metadata: HashMap::new(),
+        }
+    }
+}
+
+/// Profile shared between the messenger and its discovery backends, so
+/// changes made at runtime go out with the next announcement
+pub type SharedProfile = Arc<RwLock<PeerProfile>>;
+
+fn local_hostname() -> String {
+    // Set by most shells on Unix and always on Windows
+    for var in ["HOSTNAME", "COMPUTERNAME"] {
+        if let Ok(name) = env::var(var) {
+            if !name.is_empty() {
+                return name;
+            }
+        }
+    }
+
+    fs::read_to_string("/etc/hostname")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
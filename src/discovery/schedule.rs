@@ -0,0 +1,190 @@
+use crate::error::{P2PError, P2PResult};
+use crate::protocol::discovery::{
+    ANNOUNCE_BUDGET_PER_SEC, ANNOUNCE_MAX_INTERVAL_SECS, ANNOUNCE_MIN_INTERVAL_MS, ANNOUNCE_REDUNDANCY,
+    REQUEST_REPLY_WINDOW_MS, STARTUP_ANNOUNCEMENTS,
+};
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Bounds for how often discovery announces this peer.
+///
+/// Announcements go out every `min_interval` for the first few rounds after
+/// starting or a network change, then back off exponentially up to
+/// `max_interval`. The budget spreads announcements out further on busy
+/// networks: with `n` known peers each waits at least `n / budget` seconds,
+/// so the whole LAN stays near `budget` announcements per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnnounceSchedule {
+    min_interval: Duration,
+    max_interval: Duration,
+    startup_announcements: u32,
+    budget_per_sec: u32,
+}
+
+impl Default for AnnounceSchedule {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_millis(ANNOUNCE_MIN_INTERVAL_MS),
+            max_interval: Duration::from_secs(ANNOUNCE_MAX_INTERVAL_SECS),
+            startup_announcements: STARTUP_ANNOUNCEMENTS,
+            budget_per_sec: ANNOUNCE_BUDGET_PER_SEC,
+        }
+    }
+}
+
+impl AnnounceSchedule {
+    /// Announce between every `min_interval` and every `max_interval`.
+    ///
+    /// `max_interval` may be at most a third of the peer timeout, so peers
+    /// survive two lost announcements in a row; that is checked where the
+    /// schedule meets the timeout, when the config is built or the schedule
+    /// is changed on a messenger.
+    pub fn new(min_interval: Duration, max_interval: Duration) -> P2PResult<Self> {
+        if min_interval.is_zero() || min_interval > max_interval {
+            return Err(P2PError::InvalidConfig(format!(
+                "announce interval must satisfy 0 < {:?} <= {:?}",
+                min_interval, max_interval
+            )));
+        }
+
+        Ok(Self {
+            min_interval,
+            max_interval,
+            ..Self::default()
+        })
+    }
+
+    /// Announce this many times at `min_interval` before backing off
+    pub fn with_startup_announcements(mut self, count: u32) -> Self {
+        self.startup_announcements = count;
+        self
+    }
+
+    /// Aim for at most `per_sec` announcements per second across the LAN; 0 for no limit
+    pub fn with_budget(mut self, per_sec: u32) -> Self {
+        self.budget_per_sec = per_sec;
+        self
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    pub fn max_interval(&self) -> Duration {
+        self.max_interval
+    }
+
+    pub fn startup_announcements(&self) -> u32 {
+        self.startup_announcements
+    }
+
+    pub fn budget_per_sec(&self) -> u32 {
+        self.budget_per_sec
+    }
+}
+
+// Decides when the next announcement is due, and which ones would be redundant
+pub(crate) struct AnnounceScheduler {
+    schedule: AnnounceSchedule,
+    interval: Duration,
+    fast_remaining: u32,
+    last_sent: Option<Instant>,
+    // Other peers by when we last heard them announce
+    heard: HashMap<String, Instant>,
+    // Requesters answered recently, so copies of one request get one reply
+    replied: HashMap<String, Instant>,
+}
+
+impl AnnounceScheduler {
+    pub(crate) fn new(schedule: AnnounceSchedule) -> Self {
+        let mut scheduler = Self {
+            schedule,
+            interval: schedule.min_interval,
+            fast_remaining: 0,
+            last_sent: None,
+            heard: HashMap::new(),
+            replied: HashMap::new(),
+        };
+        scheduler.restart();
+        scheduler
+    }
+
+    pub(crate) fn schedule(&self) -> AnnounceSchedule {
+        self.schedule
+    }
+
+    pub(crate) fn set_schedule(&mut self, schedule: AnnounceSchedule) {
+        self.schedule = schedule;
+        self.restart();
+    }
+
+    /// Go back to fast announcements, e.g. after a network change
+    pub(crate) fn restart(&mut self) {
+        self.interval = self.schedule.min_interval;
+        self.fast_remaining = self.schedule.startup_announcements;
+    }
+
+    /// Delay before the next round, backing off one step.
+    ///
+    /// Randomised within the upper half of the interval so peers that
+    /// started together drift apart instead of announcing in lockstep.
+    pub(crate) fn next_delay(&mut self, peer_count: usize) -> Duration {
+        let interval = self.interval.max(self.budget_floor(peer_count)).min(self.schedule.max_interval);
+
+        if self.fast_remaining > 0 {
+            self.fast_remaining -= 1;
+        } else {
+            self.interval = (self.interval * 2).min(self.schedule.max_interval);
+        }
+
+        interval.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// Whether a scheduled round can be skipped: we announced recently
+    /// anyway, e.g. after a profile change, or enough other peers announced
+    /// this interval that the group is busy and our last round is still
+    /// within one interval. Fast rounds after a restart are never skipped.
+    pub(crate) fn is_redundant(&mut self, now: Instant) -> bool {
+        let Some(sent) = self.last_sent else {
+            return false;
+        };
+        let since = now.duration_since(sent);
+        if since < self.interval / 2 {
+            return true;
+        }
+
+        let interval = self.interval;
+        self.heard.retain(|_, heard| now.duration_since(*heard) < interval);
+        self.fast_remaining == 0 && since < interval && self.heard.len() >= ANNOUNCE_REDUNDANCY
+    }
+
+    pub(crate) fn record_sent(&mut self, now: Instant) {
+        self.last_sent = Some(now);
+    }
+
+    /// Note an announcement from another peer. Copies arriving per
+    /// interface count once.
+    pub(crate) fn record_heard(&mut self, peer_id: &str, now: Instant) {
+        self.heard.insert(peer_id.to_string(), now);
+    }
+
+    /// Whether to answer a request from `requester`. A request arrives once
+    /// per interface and address family; only the first copy is answered.
+    pub(crate) fn should_reply(&mut self, requester: &str, now: Instant) -> bool {
+        let window = Duration::from_millis(REQUEST_REPLY_WINDOW_MS);
+        self.replied.retain(|_, answered| now.duration_since(*answered) < window);
+        if self.replied.contains_key(requester) {
+            return false;
+        }
+        self.replied.insert(requester.to_string(), now);
+        true
+    }
+
+    fn budget_floor(&self, peer_count: usize) -> Duration {
+        match self.schedule.budget_per_sec {
+            0 => Duration::ZERO,
+            budget => Duration::from_secs_f64(peer_count as f64 / budget as f64),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::discovery::Discovery;
+use crate::error::{P2PError, P2PResult};
+use crate::protocol::address;
+use crate::{P2PEvent, PeerInfo};
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+/// Peers listed in a file, for networks where multicast doesn't reach.
+///
+/// Each non-empty line is `host:port [name]`; `#` starts a comment. Peer IDs
+/// are not known until connecting, so entries are keyed as `static:host:port`.
+pub struct StaticDiscovery {
+    peers: Vec<PeerInfo>,
+    is_running: Arc<Mutex<bool>>,
+    event_sender: Option<broadcast::Sender<P2PEvent>>,
+}
+
+impl StaticDiscovery {
+    pub fn new(peers: Vec<PeerInfo>) -> Self {
+        Self {
+            peers,
+            is_running: Arc::new(Mutex::new(false)),
+            event_sender: None,
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> P2PResult<Self> {
+        let contents = fs::read_to_string(path)?;
+        Ok(Self::new(Self::parse(&contents)?))
+    }
+
+    pub fn parse(contents: &str) -> P2PResult<Vec<PeerInfo>> {
+        let mut peers = Vec::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (address, name) = match line.split_once(char::is_whitespace) {
+                Some((address, name)) => (address, name.trim()),
+                None => (line, line),
+            };
+
+            let addr = Self::resolve(address).ok_or_else(|| {
+                P2PError::Network(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: invalid peer address '{}'", index + 1, address),
+                ))
+            })?;
+
+            peers.push(PeerInfo {
+                id: format!("static:{}", address),
+                name: name.to_string(),
+                ip: address::ip_to_string(&addr),
+                port: addr.port() as u32,
+                last_seen: 0,
+                ..Default::default()
+            });
+        }
+
+        Ok(peers)
+    }
+
+    fn resolve(address: &str) -> Option<SocketAddr> {
+        address.to_socket_addrs().ok()?.next()
+    }
+}
+
+impl Discovery for StaticDiscovery {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    fn start(&self) -> P2PResult<()> {
+        {
+            let mut running = self.is_running.lock().unwrap();
+            if *running {
+                return Ok(());
+            }
+            *running = true;
+        }
+
+        if let Some(sender) = &self.event_sender {
+            for peer in self.peers() {
+                let _ = sender.send(P2PEvent::PeerDiscovered(peer));
+            }
+        }
+        Ok(())
+    }
+
+    fn stop(&self) {
+        *self.is_running.lock().unwrap() = false;
+    }
+
+    fn peers(&self) -> Vec<PeerInfo> {
+        // Listed peers never go stale
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        self.peers
+            .iter()
+            .cloned()
+            .map(|peer| PeerInfo { last_seen: now, ..peer })
+            .collect()
+    }
+
+    fn set_event_sender(&mut self, sender: broadcast::Sender<P2PEvent>) {
+        self.event_sender = Some(sender);
+    }
+}
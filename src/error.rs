@@ -14,6 +14,9 @@ pub enum P2PError {
     #[error("Peer not found: {peer_id}")]
     PeerNotFound { peer_id: String },
     
+    #[error("mDNS error: {0}")]
+    Mdns(String),
+    
     #[error("Discovery timeout")]
     DiscoveryTimeout,
     
@@ -22,6 +25,21 @@ pub enum P2PError {
     
     #[error("Connection refused by peer")]
     ConnectionRefused,
+    
+    #[error("Peer is not in this network group")]
+    NotInGroup,
+    
+    #[error("Request timed out")]
+    Timeout,
+    
+    #[error("Remote call failed: {0}")]
+    Rpc(String),
+    
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+    
+    #[error("Messenger is {0}")]
+    InvalidState(String),
 }
 
 pub type P2PResult<T> = Result<T, P2PError>;
\ No newline at end of file
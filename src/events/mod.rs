@@ -1,13 +1,50 @@
+use crate::discovery::NetworkInterface;
+use crate::protocol::custom::CustomPayload;
+use crate::protocol::message::EVENT_BUFFER_SIZE;
 use crate::{P2pMessage as Message, PeerInfo};
-use tokio::sync::mpsc;
+use std::collections::HashSet;
+use tokio::sync::broadcast::{self, error::{RecvError, TryRecvError}};
+
+/// Where a `P2PMessenger` is in its start/stop cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessengerState {
+    /// Not listening or discovering; `start` brings it online
+    #[default]
+    Stopped,
+    Starting,
+    Running,
+    Stopping,
+}
+
+impl MessengerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessengerState::Stopped => "stopped",
+            MessengerState::Starting => "starting",
+            MessengerState::Running => "running",
+            MessengerState::Stopping => "stopping",
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum P2PEvent {
     PeerDiscovered(PeerInfo),
+    /// A discovered peer changed its name, address or port
+    PeerUpdated(PeerInfo),
+    /// A discovered peer said goodbye or stopped announcing
+    PeerLost(PeerInfo),
+    /// Someone claimed a known peer's ID without its identity key; the known
+    /// entry is kept and this claim is only listed as a conflict
+    PeerConflict(PeerInfo),
     PeerConnected(PeerInfo),
     PeerDisconnected(PeerInfo),
     MessageReceived(Message),
     MessageSent(Message),
+    CustomMessage {
+        message: Message,
+        payload: CustomPayload,
+    },
     FileTransferStarted { 
         peer_id: String, 
         filename: String,
@@ -28,30 +65,146 @@ pub enum P2PEvent {
         filename: String,
         error: String,
     },
+    /// Interfaces used for discovery came, went or changed address
+    NetworkChanged {
+        added: Vec<NetworkInterface>,
+        removed: Vec<NetworkInterface>,
+    },
+    /// The messenger started or stopped, or is on its way to either
+    StateChanged(MessengerState),
+    /// This subscriber fell behind and `missed` events were dropped for it
+    Lagged {
+        missed: u64,
+    },
     Error(String),
 }
 
+/// The kind of a `P2PEvent`, without its data; used to filter subscriptions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    PeerDiscovered,
+    PeerUpdated,
+    PeerLost,
+    PeerConflict,
+    PeerConnected,
+    PeerDisconnected,
+    MessageReceived,
+    MessageSent,
+    CustomMessage,
+    FileTransferStarted,
+    FileTransferProgress,
+    FileTransferCompleted,
+    FileTransferFailed,
+    NetworkChanged,
+    StateChanged,
+    Lagged,
+    Error,
+}
+
+impl P2PEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            P2PEvent::PeerDiscovered(_) => EventKind::PeerDiscovered,
+            P2PEvent::PeerUpdated(_) => EventKind::PeerUpdated,
+            P2PEvent::PeerLost(_) => EventKind::PeerLost,
+            P2PEvent::PeerConflict(_) => EventKind::PeerConflict,
+            P2PEvent::PeerConnected(_) => EventKind::PeerConnected,
+            P2PEvent::PeerDisconnected(_) => EventKind::PeerDisconnected,
+            P2PEvent::MessageReceived(_) => EventKind::MessageReceived,
+            P2PEvent::MessageSent(_) => EventKind::MessageSent,
+            P2PEvent::CustomMessage { .. } => EventKind::CustomMessage,
+            P2PEvent::FileTransferStarted { .. } => EventKind::FileTransferStarted,
+            P2PEvent::FileTransferProgress { .. } => EventKind::FileTransferProgress,
+            P2PEvent::FileTransferCompleted { .. } => EventKind::FileTransferCompleted,
+            P2PEvent::FileTransferFailed { .. } => EventKind::FileTransferFailed,
+            P2PEvent::NetworkChanged { .. } => EventKind::NetworkChanged,
+            P2PEvent::StateChanged(_) => EventKind::StateChanged,
+            P2PEvent::Lagged { .. } => EventKind::Lagged,
+            P2PEvent::Error(_) => EventKind::Error,
+        }
+    }
+}
+
+/// One consumer's stream of events.
+///
+/// Every subscription sees every event emitted after it was created,
+/// independently of the others. A subscriber that falls more than the
+/// configured buffer size (`EVENT_BUFFER_SIZE` by default) behind loses the
+/// oldest and is told how many with `P2PEvent::Lagged`, which is delivered
+/// whatever the filter.
+pub struct EventSubscription {
+    receiver: broadcast::Receiver<P2PEvent>,
+    kinds: Option<HashSet<EventKind>>,
+}
+
+impl EventSubscription {
+    fn new(receiver: broadcast::Receiver<P2PEvent>) -> Self {
+        Self {
+            receiver,
+            kinds: None,
+        }
+    }
+
+    /// Only deliver events of these kinds
+    pub fn with_kinds(mut self, kinds: &[EventKind]) -> Self {
+        self.kinds = Some(kinds.iter().copied().collect());
+        self
+    }
+
+    /// Wait for the next event; `None` once the messenger is gone
+    pub async fn recv(&mut self) -> Option<P2PEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if self.wants(&event) => return Some(event),
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => return Some(P2PEvent::Lagged { missed }),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// The next event if one is already waiting
+    pub fn try_recv(&mut self) -> Option<P2PEvent> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) if self.wants(&event) => return Some(event),
+                Ok(_) => {}
+                Err(TryRecvError::Lagged(missed)) => return Some(P2PEvent::Lagged { missed }),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+            }
+        }
+    }
+
+    fn wants(&self, event: &P2PEvent) -> bool {
+        self.kinds.as_ref().is_none_or(|kinds| kinds.contains(&event.kind()))
+    }
+}
+
 pub struct EventManager {
-    event_sender: mpsc::UnboundedSender<P2PEvent>,
-    event_receiver: Option<mpsc::UnboundedReceiver<P2PEvent>>,
+    event_sender: broadcast::Sender<P2PEvent>,
 }
 
 impl EventManager {
     pub fn new() -> Self {
-        let (sender, receiver) = mpsc::unbounded_channel();
+        Self::with_capacity(EVENT_BUFFER_SIZE)
+    }
+
+    /// Buffer `capacity` events per subscriber instead of the default
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
         
         Self {
             event_sender: sender,
-            event_receiver: Some(receiver),
         }
     }
 
-    pub fn get_sender(&self) -> mpsc::UnboundedSender<P2PEvent> {
+    pub fn get_sender(&self) -> broadcast::Sender<P2PEvent> {
         self.event_sender.clone()
     }
 
-    pub fn take_receiver(&mut self) -> Option<mpsc::UnboundedReceiver<P2PEvent>> {
-        self.event_receiver.take()
+    /// A new, independent stream of every event from now on
+    pub fn subscribe(&self) -> EventSubscription {
+        EventSubscription::new(self.event_sender.subscribe())
     }
 
     pub fn emit_event(&self, event: P2PEvent) {
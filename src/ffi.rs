@@ -1,7 +1,9 @@
 use std::ffi::{CStr, CString, c_char};
 use std::ptr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use crate::discovery::{interfaces, AnnounceSchedule, InterfaceFilter, NetworkGroup};
 use crate::{P2PMessenger, P2PEvent};
 
 // Opaque handle for C# interop
@@ -30,6 +32,11 @@ pub const EVENT_PEER_DISCONNECTED: i32 = 3;
 pub const EVENT_MESSAGE_RECEIVED: i32 = 4;
 pub const EVENT_FILE_RECEIVED: i32 = 5;
 pub const EVENT_ERROR: i32 = 6;
+pub const EVENT_PEER_UPDATED: i32 = 7;
+pub const EVENT_PEER_LOST: i32 = 8;
+pub const EVENT_NETWORK_CHANGED: i32 = 9;
+pub const EVENT_PEER_CONFLICT: i32 = 10;
+pub const EVENT_STATE_CHANGED: i32 = 11;
 
 // Helper functions for string conversion
 fn cstr_to_string(cstr: *const c_char) -> Result<String, i32> {
@@ -83,10 +90,19 @@ pub extern "C" fn p2p_create_messenger_with_ports(
     let messenger = match runtime.block_on(async {
         P2PMessenger::with_ports(name_str, tcp_port, discovery_port)
     }) {
-        Ok(m) => Arc::new(Mutex::new(m)),
+        Ok(m) => m,
         Err(_) => return ptr::null_mut(),
     };
 
+    // Forward events to the callback for the life of the handle, across restarts
+    let mut events = messenger.subscribe_events();
+    runtime.spawn(async move {
+        while let Some(event) = events.recv().await {
+            emit_event_to_callback(&event);
+        }
+    });
+    let messenger = Arc::new(Mutex::new(messenger));
+
     let handle = Box::new(P2PHandle {
         messenger,
         runtime,
@@ -105,17 +121,7 @@ pub extern "C" fn p2p_start(handle: *mut P2PHandle) -> i32 {
     let handle = unsafe { &*handle };
     
     match handle.runtime.block_on(async {
-        let mut messenger = handle.messenger.lock().await;
-        
-        // Setup event receiver and spawn background task
-        if let Some(mut event_receiver) = messenger.get_event_receiver() {
-            tokio::spawn(async move {
-                while let Some(event) = event_receiver.recv().await {
-                    emit_event_to_callback(&event);
-                }
-            });
-        }
-        
+        let messenger = handle.messenger.lock().await;
         messenger.start().await
     }) {
         Ok(_) => FFI_SUCCESS,
@@ -191,6 +197,21 @@ pub extern "C" fn p2p_get_local_ip(handle: *mut P2PHandle) -> *mut c_char {
     string_to_cstring(&ip)
 }
 
+/// Get the TCP port the messenger listens on; 0 until started
+#[no_mangle]
+pub extern "C" fn p2p_get_tcp_port(handle: *mut P2PHandle) -> u16 {
+    if handle.is_null() {
+        return 0;
+    }
+
+    let handle = unsafe { &*handle };
+    
+    handle.runtime.block_on(async {
+        let messenger = handle.messenger.lock().await;
+        messenger.listen_addresses().map_or(0, |addresses| addresses.tcp.port())
+    })
+}
+
 /// Discover peers on the network
 #[no_mangle]
 pub extern "C" fn p2p_discover_peers(handle: *mut P2PHandle) -> i32 {
@@ -243,6 +264,196 @@ pub extern "C" fn p2p_get_connected_peers_count(handle: *mut P2PHandle) -> i32 {
     count as i32
 }
 
+/// Get an announced attribute of a discovered peer
+/// `attribute` is one of "version", "os", "hostname", "status", "accepts_files"
+/// ("true"/"false"), or any app metadata key. Returns null if the peer or key is unknown.
+#[no_mangle]
+pub extern "C" fn p2p_get_peer_attribute(
+    handle: *mut P2PHandle,
+    peer_id: *const c_char,
+    attribute: *const c_char,
+) -> *mut c_char {
+    if handle.is_null() {
+        return ptr::null_mut();
+    }
+
+    let (peer_id_str, attribute_str) = match (cstr_to_string(peer_id), cstr_to_string(attribute)) {
+        (Ok(peer_id), Ok(attribute)) => (peer_id, attribute),
+        _ => return ptr::null_mut(),
+    };
+
+    let handle = unsafe { &*handle };
+    
+    let value = handle.runtime.block_on(async {
+        let messenger = handle.messenger.lock().await;
+        let peer = messenger
+            .get_discovered_peers()
+            .into_iter()
+            .find(|p| p.id == peer_id_str)?;
+
+        match attribute_str.as_str() {
+            "version" => Some(peer.version),
+            "os" => Some(peer.os),
+            "hostname" => Some(peer.hostname),
+            "status" => Some(peer.status),
+            "accepts_files" => Some(peer.accepts_files.to_string()),
+            key => peer.metadata.get(key).cloned(),
+        }
+    });
+
+    match value {
+        Some(value) => string_to_cstring(&value),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Set the status announced to other peers
+#[no_mangle]
+pub extern "C" fn p2p_set_status(handle: *mut P2PHandle, status: *const c_char) -> i32 {
+    if handle.is_null() {
+        return FFI_ERROR_INVALID_HANDLE;
+    }
+
+    let status_str = match cstr_to_string(status) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let handle = unsafe { &*handle };
+    
+    handle.runtime.block_on(async {
+        let messenger = handle.messenger.lock().await;
+        messenger.set_status(&status_str);
+    });
+
+    FFI_SUCCESS
+}
+
+/// Set an app metadata key announced to other peers; a null value removes the key
+#[no_mangle]
+pub extern "C" fn p2p_set_metadata(handle: *mut P2PHandle, key: *const c_char, value: *const c_char) -> i32 {
+    if handle.is_null() {
+        return FFI_ERROR_INVALID_HANDLE;
+    }
+
+    let key_str = match cstr_to_string(key) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let value_str = if value.is_null() {
+        None
+    } else {
+        match cstr_to_string(value) {
+            Ok(s) => Some(s),
+            Err(e) => return e,
+        }
+    };
+
+    let handle = unsafe { &*handle };
+    
+    handle.runtime.block_on(async {
+        let messenger = handle.messenger.lock().await;
+        match value_str {
+            Some(value) => messenger.set_metadata(&key_str, &value),
+            None => messenger.remove_metadata(&key_str),
+        }
+    });
+
+    FFI_SUCCESS
+}
+
+/// Restrict discovery to some interfaces. Each list is comma separated names
+/// (`docker*` wildcards allowed) or subnets (`10.8.0.0/16`); null means none.
+#[no_mangle]
+pub extern "C" fn p2p_set_interface_filter(handle: *mut P2PHandle, include: *const c_char, exclude: *const c_char) -> i32 {
+    if handle.is_null() {
+        return FFI_ERROR_INVALID_HANDLE;
+    }
+
+    let mut filter = InterfaceFilter::new();
+    for (list, is_include) in [(include, true), (exclude, false)] {
+        if list.is_null() {
+            continue;
+        }
+        let list_str = match cstr_to_string(list) {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+        for rule in list_str.split(',').filter(|rule| !rule.trim().is_empty()) {
+            let result = if is_include { filter.include(rule) } else { filter.exclude(rule) };
+            filter = match result {
+                Ok(filter) => filter,
+                Err(_) => return FFI_ERROR_INVALID_PARAMETER,
+            };
+        }
+    }
+
+    let handle = unsafe { &*handle };
+    
+    handle.runtime.block_on(async {
+        let messenger = handle.messenger.lock().await;
+        messenger.set_interface_filter(filter);
+    });
+
+    FFI_SUCCESS
+}
+
+/// Announce between every `min_ms` and every `max_ms` milliseconds, backing
+/// off from the minimum while the network is stable.
+#[no_mangle]
+pub extern "C" fn p2p_set_announce_interval(handle: *mut P2PHandle, min_ms: u32, max_ms: u32) -> i32 {
+    if handle.is_null() {
+        return FFI_ERROR_INVALID_HANDLE;
+    }
+
+    let schedule = match AnnounceSchedule::new(Duration::from_millis(min_ms as u64), Duration::from_millis(max_ms as u64)) {
+        Ok(schedule) => schedule,
+        Err(_) => return FFI_ERROR_INVALID_PARAMETER,
+    };
+
+    let handle = unsafe { &*handle };
+    
+    let result = handle.runtime.block_on(async {
+        let messenger = handle.messenger.lock().await;
+        messenger.set_announce_schedule(schedule)
+    });
+
+    match result {
+        Ok(()) => FFI_SUCCESS,
+        Err(_) => FFI_ERROR_INVALID_PARAMETER,
+    }
+}
+
+/// Only discover and connect to peers in the named group; call before `p2p_start`.
+/// A null secret means the group is not authenticated.
+#[no_mangle]
+pub extern "C" fn p2p_set_network_group(handle: *mut P2PHandle, name: *const c_char, secret: *const c_char) -> i32 {
+    if handle.is_null() {
+        return FFI_ERROR_INVALID_HANDLE;
+    }
+
+    let name_str = match cstr_to_string(name) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+    let mut group = NetworkGroup::new(&name_str);
+    if !secret.is_null() {
+        match cstr_to_string(secret) {
+            Ok(s) => group = group.with_secret(s.as_bytes()),
+            Err(e) => return e,
+        }
+    }
+
+    let handle = unsafe { &*handle };
+    
+    handle.runtime.block_on(async {
+        let mut messenger = handle.messenger.lock().await;
+        messenger.set_network_group(group);
+    });
+
+    FFI_SUCCESS
+}
+
 /// Connect to a peer by ID
 #[no_mangle]
 pub extern "C" fn p2p_connect_to_peer(handle: *mut P2PHandle, peer_id: *const c_char) -> i32 {
@@ -279,6 +490,29 @@ pub extern "C" fn p2p_connect_to_peer(handle: *mut P2PHandle, peer_id: *const c_
     }
 }
 
+/// Connect to a peer by address ("host:port") without knowing its ID
+#[no_mangle]
+pub extern "C" fn p2p_connect_to_address(handle: *mut P2PHandle, address: *const c_char) -> i32 {
+    if handle.is_null() {
+        return FFI_ERROR_INVALID_HANDLE;
+    }
+
+    let address_str = match cstr_to_string(address) {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let handle = unsafe { &*handle };
+    
+    match handle.runtime.block_on(async {
+        let messenger = handle.messenger.lock().await;
+        messenger.connect_to_address(&address_str).await
+    }) {
+        Ok(_) => FFI_SUCCESS,
+        Err(_) => FFI_ERROR_NETWORK,
+    }
+}
+
 /// Disconnect from a peer
 #[no_mangle]
 pub extern "C" fn p2p_disconnect_peer(handle: *mut P2PHandle, peer_id: *const c_char) -> i32 {
@@ -417,6 +651,27 @@ pub(crate) fn emit_event_to_callback(event: &P2PEvent) {
                     if !peer_id.is_null() { p2p_free_string(peer_id); }
                     if !peer_name.is_null() { p2p_free_string(peer_name); }
                 }
+                P2PEvent::PeerUpdated(peer) => {
+                    let peer_id = string_to_cstring(&peer.id);
+                    let peer_name = string_to_cstring(&peer.name);
+                    callback(EVENT_PEER_UPDATED, peer_id, peer_name, ptr::null());
+                    if !peer_id.is_null() { p2p_free_string(peer_id); }
+                    if !peer_name.is_null() { p2p_free_string(peer_name); }
+                }
+                P2PEvent::PeerLost(peer) => {
+                    let peer_id = string_to_cstring(&peer.id);
+                    let peer_name = string_to_cstring(&peer.name);
+                    callback(EVENT_PEER_LOST, peer_id, peer_name, ptr::null());
+                    if !peer_id.is_null() { p2p_free_string(peer_id); }
+                    if !peer_name.is_null() { p2p_free_string(peer_name); }
+                }
+                P2PEvent::PeerConflict(peer) => {
+                    let peer_id = string_to_cstring(&peer.id);
+                    let address = string_to_cstring(&format!("{}:{}", peer.ip, peer.port));
+                    callback(EVENT_PEER_CONFLICT, peer_id, ptr::null(), address);
+                    if !peer_id.is_null() { p2p_free_string(peer_id); }
+                    if !address.is_null() { p2p_free_string(address); }
+                }
                 P2PEvent::PeerConnected(peer) => {
                     let peer_id = string_to_cstring(&peer.id);
                     let peer_name = string_to_cstring(&peer.name);
@@ -446,6 +701,21 @@ pub(crate) fn emit_event_to_callback(event: &P2PEvent) {
                     if !peer_id.is_null() { p2p_free_string(peer_id); }
                     if !peer_name.is_null() { p2p_free_string(peer_name); }
                 }
+                P2PEvent::NetworkChanged { added, removed } => {
+                    let summary = string_to_cstring(&interfaces::describe_changes(added, removed));
+                    callback(EVENT_NETWORK_CHANGED, ptr::null(), ptr::null(), summary);
+                    if !summary.is_null() { p2p_free_string(summary); }
+                }
+                P2PEvent::StateChanged(state) => {
+                    let state_name = string_to_cstring(state.as_str());
+                    callback(EVENT_STATE_CHANGED, ptr::null(), ptr::null(), state_name);
+                    if !state_name.is_null() { p2p_free_string(state_name); }
+                }
+                P2PEvent::Lagged { missed } => {
+                    let error_msg = string_to_cstring(&format!("Missed {} events", missed));
+                    callback(EVENT_ERROR, ptr::null(), ptr::null(), error_msg);
+                    if !error_msg.is_null() { p2p_free_string(error_msg); }
+                }
                 P2PEvent::Error(error) => {
                     let error_msg = string_to_cstring(&error);
                     callback(EVENT_ERROR, ptr::null(), ptr::null(), error_msg);
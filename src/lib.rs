@@ -1,3 +1,4 @@
+pub mod config;
 pub mod discovery;
 pub mod events;
 pub mod peer;
@@ -6,106 +7,490 @@ pub mod error;
 pub mod app;
 pub mod cli;
 pub mod ffi;
+pub mod pubsub;
+pub mod rpc;
 
-use crate::discovery::DiscoveryService;
+use crate::discovery::mdns::MdnsDiscovery;
+use crate::discovery::{
+    AnnounceSchedule, Discovery, DiscoveryBackend, DiscoveryService, InterfaceFilter, NetworkGroup, PeerIdentity,
+    PeerProfile, SharedProfile,
+};
 use crate::events::EventManager;
-use crate::peer::PeerManager;
+use crate::peer::{BroadcastResults, PeerManager};
+use crate::protocol::custom::CustomRegistry;
+use crate::pubsub::TopicReceiver;
+use crate::rpc::{RpcRegistry, RpcRequest};
 
 // Note: modules are already declared as pub mod above
 // Include generated protobuf code
 include!(concat!(env!("OUT_DIR"), "/archsockrust.rs"));
 use crate::error::{P2PError, P2PResult};
 
+use std::collections::HashMap;
 use std::fs;
-use tokio::sync::mpsc;
-use local_ip_address;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Where a started messenger is listening
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenAddresses {
+    /// TCP listener for peer connections
+    pub tcp: SocketAddr,
+    /// Multicast discovery socket, if that backend runs
+    pub discovery: Option<SocketAddr>,
+}
 
 pub struct P2PMessenger {
     peer_name: String,
     peer_id: String,
     tcp_port: u16,
-    discovery: DiscoveryService,
+    listen_address: Option<IpAddr>,
+    discovery_bind_address: Option<IpAddr>,
+    // Narrowed to the bind address; also given to backends added later
+    interface_filter: Mutex<InterfaceFilter>,
+    listen_addresses: Mutex<Option<ListenAddresses>>,
+    state: Mutex<MessengerState>,
+    identity: PeerIdentity,
+    discovery_backends: Vec<Box<dyn Discovery>>,
+    profile: SharedProfile,
+    group: NetworkGroup,
+    bootstrap_peers: Vec<String>,
+    download_dir: PathBuf,
+    peer_timeout: Duration,
     peer_manager: PeerManager,
     event_manager: EventManager,
+    rpc_registry: RpcRegistry,
+    custom_registry: CustomRegistry,
 }
 
 impl P2PMessenger {
     pub fn new(peer_name: String) -> P2PResult<Self> {
-        Self::with_ports(peer_name, 6969, 6968)
+        Self::with_config(P2PConfig::builder(&peer_name).build()?)
     }
 
     pub fn with_ports(peer_name: String, tcp_port: u16, discovery_port: u16) -> P2PResult<Self> {
-        let mut discovery = DiscoveryService::new(peer_name.clone(), tcp_port, discovery_port)?;
+        Self::with_discovery_backend(peer_name, tcp_port, discovery_port, DiscoveryBackend::default())
+    }
+
+    /// Create a messenger that discovers peers with the given backend(s).
+    ///
+    /// `DiscoveryBackend::Mdns` advertises the peer over DNS-SD so standard
+    /// mDNS tooling can find it; `Both` runs it alongside multicast discovery.
+    /// With `None`, add backends yourself with `add_discovery_backend`.
+    pub fn with_discovery_backend(
+        peer_name: String,
+        tcp_port: u16,
+        discovery_port: u16,
+        discovery_backend: DiscoveryBackend,
+    ) -> P2PResult<Self> {
+        let config = P2PConfig::builder(&peer_name)
+            .tcp_port(tcp_port)
+            .discovery_port(discovery_port)
+            .discovery_backend(discovery_backend)
+            .build()?;
+        Self::with_config(config)
+    }
+
+    /// Create a messenger from a validated `P2PConfig`
+    pub fn with_config(config: P2PConfig) -> P2PResult<Self> {
+        let peer_name = config.peer_name.clone();
+        let peer_id = config.peer_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let identity = config.identity.clone().unwrap_or_else(PeerIdentity::generate);
+        let tcp_port = config.tcp_port;
         
-        let event_manager = EventManager::new();
+        let event_manager = EventManager::with_capacity(config.event_buffer_size);
         let event_sender = event_manager.get_sender();
         
-        // Give discovery service access to event sender
-        discovery.set_event_sender(event_sender.clone());
+        let mut discovery_backends: Vec<Box<dyn Discovery>> = Vec::new();
+        if config.discovery_backend.uses_multicast() {
+            let discovery = DiscoveryService::with_multicast_group(
+                peer_name.clone(),
+                tcp_port,
+                config.discovery_port,
+                config.multicast_group,
+            )?
+            .with_peer_id(peer_id.clone())
+            .with_identity(identity.clone())
+            .with_peer_timeout(config.peer_timeout)
+            .with_announce_schedule(config.announce_schedule);
+            let discovery = match config.discovery_bind_address {
+                Some(address) => discovery.with_bind_address(address)?,
+                None => discovery,
+            };
+            discovery_backends.push(Box::new(discovery));
+        }
+        if config.discovery_backend.uses_mdns() {
+            let mdns = MdnsDiscovery::new(peer_id.clone(), peer_name.clone(), tcp_port)?;
+            discovery_backends.push(Box::new(mdns));
+        }
+        
+        // Give discovery backends access to event sender, our profile and settings
+        let profile = SharedProfile::default();
+        let interface_filter = bind_filter(config.interface_filter.clone(), config.discovery_bind_address);
+        for backend in &mut discovery_backends {
+            backend.set_event_sender(event_sender.clone());
+            backend.set_profile(profile.clone());
+            backend.set_group(config.network_group.clone());
+            if interface_filter != InterfaceFilter::default() {
+                backend.set_interface_filter(interface_filter.clone());
+            }
+        }
+        
+        let rpc_registry = RpcRegistry::new();
+        let custom_registry = CustomRegistry::new();
         
         let peer_manager = PeerManager::new(
             event_sender,
-            discovery.peer_id.clone(),
+            peer_id.clone(),
             peer_name.clone(),
             tcp_port,
+            rpc_registry.clone(),
+            custom_registry.clone(),
         );
+        peer_manager.set_network_group(config.network_group.clone());
+        peer_manager.set_relay_enabled(config.relay_enabled);
+        peer_manager.set_handshake_timeout(config.handshake_timeout);
         
         Ok(Self {
-            peer_id: discovery.peer_id.clone(),
+            peer_id,
             peer_name,
             tcp_port,
-            discovery,
+            listen_address: config.listen_address,
+            discovery_bind_address: config.discovery_bind_address,
+            interface_filter: Mutex::new(interface_filter),
+            listen_addresses: Mutex::new(None),
+            state: Mutex::new(MessengerState::Stopped),
+            identity,
+            discovery_backends,
+            profile,
+            group: config.network_group,
+            bootstrap_peers: config.bootstrap_peers,
+            download_dir: config.download_dir,
+            peer_timeout: config.peer_timeout,
             peer_manager,
             event_manager,
+            rpc_registry,
+            custom_registry,
         })
     }
 
-    pub async fn start(&self) -> P2PResult<()> {
-        self.discovery.start().await?;
-        self.peer_manager.start_listening(self.tcp_port).await?;
+    /// Run an additional discovery backend alongside the built-in ones.
+    ///
+    /// Must be called before `start`. Peers found by several backends are
+    /// merged by id.
+    pub fn add_discovery_backend<D: Discovery + 'static>(&mut self, mut backend: D) {
+        backend.set_event_sender(self.event_manager.get_sender());
+        backend.set_profile(self.profile.clone());
+        backend.set_group(self.group.clone());
+        let interface_filter = self.interface_filter.lock().unwrap().clone();
+        if interface_filter != InterfaceFilter::default() {
+            backend.set_interface_filter(interface_filter);
+        }
+        self.discovery_backends.push(Box::new(backend));
+    }
+
+    /// Only discover and connect to peers in `group`.
+    ///
+    /// Must be called before `start`. Instances in different groups ignore
+    /// each other's announcements and refuse each other's handshakes; with a
+    /// secret, peers must also prove they hold it.
+    pub fn set_network_group(&mut self, group: NetworkGroup) {
+        for backend in &mut self.discovery_backends {
+            backend.set_group(group.clone());
+        }
+        self.peer_manager.set_network_group(group.clone());
+        self.group = group;
+    }
+
+    pub fn network_group(&self) -> &NetworkGroup {
+        &self.group
+    }
+
+    /// Only announce and listen on the interfaces `filter` allows, e.g. to
+    /// keep out of Docker bridges and VPN tunnels.
+    ///
+    /// Can be changed while running; discovery rejoins and re-announces, and
+    /// `P2PEvent::NetworkChanged` reports the interfaces now in use.
+    pub fn set_interface_filter(&self, filter: InterfaceFilter) {
+        let filter = bind_filter(filter, self.discovery_bind_address);
+        for backend in &self.discovery_backends {
+            backend.set_interface_filter(filter.clone());
+        }
+        *self.interface_filter.lock().unwrap() = filter;
+    }
+
+    /// Bounds for how often discovery announces this peer.
+    ///
+    /// Can be changed while running. Announcements start fast, back off
+    /// while the network is stable and spread out as more peers join; see
+    /// `AnnounceSchedule`. Fails if peers could time us out between two
+    /// announcements at the longest interval.
+    pub fn set_announce_schedule(&self, schedule: AnnounceSchedule) -> P2PResult<()> {
+        config::check_announce_schedule(&schedule, self.peer_timeout)?;
+        for backend in &self.discovery_backends {
+            backend.set_announce_schedule(schedule);
+        }
         Ok(())
     }
 
+    /// What this peer currently announces about itself
+    pub fn profile(&self) -> PeerProfile {
+        self.profile.read().unwrap().clone()
+    }
+
+    /// Set the presence shown to other peers, e.g. "away"
+    pub fn set_status(&self, status: &str) {
+        self.update_profile(|profile| profile.status = status.to_string());
+    }
+
+    pub fn set_accepts_files(&self, accepts_files: bool) {
+        self.update_profile(|profile| profile.accepts_files = accepts_files);
+    }
+
+    /// Announce an app-defined key/value pair, e.g. `("team", "blue")`
+    pub fn set_metadata(&self, key: &str, value: &str) {
+        self.update_profile(|profile| {
+            profile.metadata.insert(key.to_string(), value.to_string());
+        });
+    }
+
+    pub fn remove_metadata(&self, key: &str) {
+        self.update_profile(|profile| {
+            profile.metadata.remove(key);
+        });
+    }
+
+    // Applies `change` and re-announces right away if anything changed
+    fn update_profile(&self, change: impl FnOnce(&mut PeerProfile)) {
+        let changed = {
+            let mut profile = self.profile.write().unwrap();
+            let before = profile.clone();
+            change(&mut profile);
+            *profile != before
+        };
+
+        if changed {
+            for backend in &self.discovery_backends {
+                backend.profile_changed();
+            }
+        }
+    }
+
+    /// Dial `address` (`host:port`) automatically on `start`.
+    ///
+    /// Useful where discovery traffic is blocked. Failures are reported as
+    /// `P2PEvent::Error` rather than failing `start`.
+    pub fn add_bootstrap_peer(&mut self, address: &str) {
+        self.bootstrap_peers.push(address.to_string());
+    }
+
+    /// Start listening and discovering, returning the addresses bound.
+    ///
+    /// Ports configured as 0 are picked by the OS; discovery announces the
+    /// TCP port actually bound. May be called again after `stop`; starting a
+    /// running messenger just returns its addresses.
+    pub async fn start(&self) -> P2PResult<ListenAddresses> {
+        {
+            let mut state = self.state.lock().unwrap();
+            match *state {
+                MessengerState::Stopped => *state = MessengerState::Starting,
+                MessengerState::Running => {
+                    return self.listen_addresses().ok_or_else(|| {
+                        P2PError::InvalidState(MessengerState::Running.as_str().to_string())
+                    });
+                }
+                busy => return Err(P2PError::InvalidState(busy.as_str().to_string())),
+            }
+        }
+        self.emit_state(MessengerState::Starting);
+
+        match self.bring_up().await {
+            Ok(addresses) => {
+                *self.listen_addresses.lock().unwrap() = Some(addresses);
+                self.set_state(MessengerState::Running);
+                self.dial_bootstrap_peers();
+                Ok(addresses)
+            }
+            Err(e) => {
+                // Undo whatever did start so the next attempt begins clean
+                self.shut_down().await;
+                self.set_state(MessengerState::Stopped);
+                Err(e)
+            }
+        }
+    }
+
+    async fn bring_up(&self) -> P2PResult<ListenAddresses> {
+        let tcp = self.peer_manager.start_listening(self.listen_address, self.tcp_port).await?;
+        for backend in &self.discovery_backends {
+            backend.set_tcp_port(tcp.port());
+            backend.start()?;
+        }
+
+        Ok(ListenAddresses {
+            tcp,
+            discovery: self.discovery_backends.iter().find_map(|backend| backend.local_addr()),
+        })
+    }
+
+    /// The addresses bound by the current run, or `None` while stopped
+    pub fn listen_addresses(&self) -> Option<ListenAddresses> {
+        *self.listen_addresses.lock().unwrap()
+    }
+
+    /// Whether the messenger is online; changes are also reported as
+    /// `P2PEvent::StateChanged`
+    pub fn state(&self) -> MessengerState {
+        *self.state.lock().unwrap()
+    }
+
+    fn set_state(&self, state: MessengerState) {
+        *self.state.lock().unwrap() = state;
+        self.emit_state(state);
+    }
+
+    fn emit_state(&self, state: MessengerState) {
+        self.event_manager.emit_event(P2PEvent::StateChanged(state));
+    }
+
+    fn dial_bootstrap_peers(&self) {
+        for address in &self.bootstrap_peers {
+            let address = address.clone();
+            let peer_manager = self.peer_manager.clone();
+            let event_sender = self.event_manager.get_sender();
+
+            tokio::spawn(async move {
+                if let Err(e) = peer_manager.connect_to_address(&address).await {
+                    let _ = event_sender.send(P2PEvent::Error(format!(
+                        "Failed to connect to bootstrap peer {}: {}",
+                        address, e
+                    )));
+                }
+            });
+        }
+    }
+
+    /// Go offline: leave discovery, stop accepting connections, tell
+    /// connected peers we are closing and give queued messages a moment to
+    /// go out. Returns once the listening socket and connections are closed.
+    ///
+    /// The messenger keeps its identity, settings and event receiver, so
+    /// `start` can bring it back online. Does nothing unless it is running.
     pub async fn stop(&self) {
-        self.discovery.stop();
-        self.peer_manager.stop_listening().await;
+        {
+            let mut state = self.state.lock().unwrap();
+            if *state != MessengerState::Running {
+                return;
+            }
+            *state = MessengerState::Stopping;
+        }
+        self.emit_state(MessengerState::Stopping);
+
+        self.shut_down().await;
+        *self.listen_addresses.lock().unwrap() = None;
+        self.set_state(MessengerState::Stopped);
+    }
+
+    async fn shut_down(&self) {
+        for backend in &self.discovery_backends {
+            backend.stop();
+        }
+        self.peer_manager.shutdown().await;
     }
 
-    pub fn get_event_receiver(&mut self) -> Option<mpsc::UnboundedReceiver<crate::events::P2PEvent>> {
-        self.event_manager.take_receiver()
+    /// A new stream of events for one consumer.
+    ///
+    /// Call as often as needed, e.g. once for the UI and once for a logger;
+    /// each subscription gets every event emitted after it was made. Narrow
+    /// one down with `EventSubscription::with_kinds`.
+    pub fn subscribe_events(&self) -> EventSubscription {
+        self.event_manager.subscribe()
     }
 
     pub fn discover_peers(&self) -> P2PResult<Vec<PeerInfo>> {
-        self.discovery.request_peers()?;
-        Ok(self.discovery.get_peers())
+        for backend in &self.discovery_backends {
+            backend.request_peers()?;
+        }
+        Ok(self.get_discovered_peers())
+    }
+
+    /// Probe the network and wait `window` for peers to answer.
+    ///
+    /// Peers reply to the probe directly instead of waiting for their next
+    /// periodic announcement. Returns the peers heard from during the window.
+    pub async fn discover_peers_within(&self, window: Duration) -> P2PResult<Vec<PeerInfo>> {
+        let started = get_current_timestamp();
+        
+        for backend in &self.discovery_backends {
+            backend.request_peers()?;
+        }
+        tokio::time::sleep(window).await;
+        
+        Ok(self
+            .get_discovered_peers()
+            .into_iter()
+            .filter(|peer| peer.last_seen >= started)
+            .collect())
     }
 
     pub fn get_discovered_peers(&self) -> Vec<PeerInfo> {
-        self.discovery.get_peers()
+        // A peer seen by several backends is listed once, with its freshest entry
+        let mut peers: HashMap<String, PeerInfo> = HashMap::new();
+        for peer in self.discovery_backends.iter().flat_map(|backend| backend.peers()) {
+            match peers.get(&peer.id) {
+                Some(known) if known.last_seen >= peer.last_seen => {}
+                _ => {
+                    peers.insert(peer.id.clone(), peer);
+                }
+            }
+        }
+        peers.into_values().collect()
+    }
+
+    /// Announcements that claimed a known peer's ID under a different
+    /// identity key. The known address is kept; these are reported with
+    /// `P2PEvent::PeerConflict` and listed here until they go stale.
+    pub fn get_conflicting_claims(&self) -> Vec<PeerInfo> {
+        self.discovery_backends
+            .iter()
+            .flat_map(|backend| backend.conflicting_claims())
+            .collect()
     }
 
     pub async fn get_connected_peers(&self) -> Vec<PeerInfo> {
         self.peer_manager.get_connected_peers().await
     }
 
+    /// Connect to a discovered peer. Resolves once its handshake arrives, so
+    /// messages can be sent straight away.
     pub async fn connect_to_peer(&self, peer_info: &PeerInfo) -> P2PResult<()> {
         self.peer_manager.connect_to_peer(peer_info).await
     }
 
+    /// Connect to a peer by `host:port` without knowing its ID.
+    ///
+    /// Resolves once the peer's handshake arrives, returning its real details.
+    pub async fn connect_to_address(&self, address: &str) -> P2PResult<PeerInfo> {
+        self.peer_manager.connect_to_address(address).await
+    }
+
     pub async fn disconnect_peer(&self, peer_id: &str) -> P2PResult<()> {
         self.peer_manager.disconnect_peer(peer_id).await
     }
 
+    /// Allow this peer to forward messages addressed to others across the mesh.
+    ///
+    /// With relaying enabled, messages to peers without a direct connection are
+    /// flooded to neighbours, which pass them on until the hop limit runs out.
+    pub fn set_relay_enabled(&self, enabled: bool) {
+        self.peer_manager.set_relay_enabled(enabled);
+    }
+
     pub async fn send_text_message(&self, peer_id: &str, text: String) -> P2PResult<()> {
-        let message = P2pMessage {
-            id: uuid::Uuid::new_v4().to_string(),
-            sender_id: self.peer_id.clone(),
-            sender_name: self.peer_name.clone(),
-            timestamp: get_current_timestamp(),
-            content: Some(MessageContent {
-                content: Some(message_content::Content::Text(TextMessage { text })),
-            }),
-        };
+        let message = self.build_message(message_content::Content::Text(TextMessage { text }));
 
         self.peer_manager
             .send_message_to_peer(peer_id, &message)
@@ -117,6 +502,53 @@ impl P2PMessenger {
         Ok(())
     }
 
+    /// Send a text message to every connected peer.
+    ///
+    /// The message is encoded once and queued on all connections concurrently.
+    /// Returns the outcome for each peer so callers can handle partial failures.
+    pub async fn broadcast_text_message(&self, text: String) -> P2PResult<BroadcastResults> {
+        let message = self.build_message(message_content::Content::Text(TextMessage { text }));
+
+        let results = self.peer_manager.broadcast_message(&message).await?;
+
+        if results.values().any(|result| result.is_ok()) {
+            self.event_manager
+                .emit_event(crate::events::P2PEvent::MessageSent(message));
+        }
+
+        Ok(results)
+    }
+
+    /// Send an application-defined protobuf message to a peer.
+    ///
+    /// Receivers that registered `T` get it as `P2PEvent::CustomMessage`;
+    /// others see a plain `MessageReceived` carrying the raw `Any`.
+    pub async fn send_custom_message<T: prost::Name>(&self, peer_id: &str, payload: &T) -> P2PResult<()> {
+        let any = prost_types::Any::from_msg(payload)?;
+        let message = self.build_message(message_content::Content::Custom(any));
+
+        self.peer_manager
+            .send_message_to_peer(peer_id, &message)
+            .await?;
+
+        self.event_manager
+            .emit_event(crate::events::P2PEvent::MessageSent(message));
+
+        Ok(())
+    }
+
+    /// Decode incoming custom messages of type `T` into `P2PEvent::CustomMessage`.
+    pub fn register_custom_type<T>(&self)
+    where
+        T: prost::Message + prost::Name + Default + Send + Sync + 'static,
+    {
+        self.custom_registry.register::<T>();
+    }
+
+    pub fn unregister_custom_type<T: prost::Name>(&self) -> bool {
+        self.custom_registry.unregister::<T>()
+    }
+
     pub async fn send_file(&self, peer_id: &str, file_path: &str) -> P2PResult<()> {
         let file_data = fs::read(file_path).map_err(P2PError::Network)?;
         let filename = std::path::Path::new(file_path)
@@ -125,18 +557,10 @@ impl P2PMessenger {
             .to_string_lossy()
             .to_string();
 
-        let message = P2pMessage {
-            id: uuid::Uuid::new_v4().to_string(),
-            sender_id: self.peer_id.clone(),
-            sender_name: self.peer_name.clone(),
-            timestamp: get_current_timestamp(),
-            content: Some(MessageContent {
-                content: Some(message_content::Content::File(FileMessage { 
-                    filename: filename.clone(), 
-                    data: file_data 
-                })),
-            }),
-        };
+        let message = self.build_message(message_content::Content::File(FileMessage { 
+            filename: filename.clone(), 
+            data: file_data 
+        }));
 
         self.event_manager.emit_event(crate::events::P2PEvent::FileTransferStarted {
             peer_id: peer_id.to_string(),
@@ -170,19 +594,84 @@ impl P2PMessenger {
         }
     }
 
+    /// Receive publications on `topic` from connected peers.
+    ///
+    /// Peers are told about the subscription so publishers only send to
+    /// interested peers. Dropping the receiver ends the subscription.
+    pub async fn subscribe(&self, topic: &str) -> P2PResult<TopicReceiver> {
+        self.peer_manager.subscribe(topic).await
+    }
+
+    /// Close all local streams for `topic` and tell peers to stop sending it.
+    pub fn unsubscribe(&self, topic: &str) {
+        self.peer_manager.unsubscribe(topic);
+    }
+
+    /// Publish `payload` to every connected peer subscribed to `topic`.
+    ///
+    /// Returns how many peers the publication was queued for.
+    pub async fn publish(&self, topic: &str, payload: Vec<u8>) -> P2PResult<usize> {
+        let message = self.build_message(message_content::Content::Publish(PublishMessage {
+            topic: topic.to_string(),
+            payload,
+        }));
+
+        self.peer_manager.publish(topic, message).await
+    }
+
+    /// Call `method` on a connected peer and wait for its reply.
+    ///
+    /// Fails with `P2PError::Timeout` if no reply arrives within `timeout`, or
+    /// `P2PError::Rpc` if the remote handler returned an error.
+    pub async fn call(
+        &self,
+        peer_id: &str,
+        method: &str,
+        payload: Vec<u8>,
+        timeout: Duration,
+    ) -> P2PResult<Vec<u8>> {
+        let correlation_id = uuid::Uuid::new_v4().to_string();
+        let message = self.build_message(message_content::Content::Rpc(rpc::rpc_request(
+            correlation_id.clone(),
+            method.to_string(),
+            payload,
+        )));
+
+        self.peer_manager
+            .call(peer_id, message, correlation_id, timeout)
+            .await
+    }
+
+    /// Serve `method` for remote peers. Replaces any existing handler.
+    ///
+    /// The handler's `Err` string is sent back to the caller as an error reply.
+    pub fn register_rpc_handler<F, Fut>(&self, method: &str, handler: F)
+    where
+        F: Fn(RpcRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<u8>, String>> + Send + 'static,
+    {
+        self.rpc_registry.register(method, handler);
+    }
+
+    pub fn unregister_rpc_handler(&self, method: &str) -> bool {
+        self.rpc_registry.unregister(method)
+    }
+
+    fn build_message(&self, content: message_content::Content) -> P2pMessage {
+        new_message(&self.peer_id, &self.peer_name, content)
+    }
+
     pub fn save_received_file(&self, message: &P2pMessage) -> P2PResult<String> {
         if let Some(content) = &message.content {
             if let Some(message_content::Content::File(file_msg)) = &content.content {
-                let save_dir = "recibidos";
-                
-                if !std::path::Path::new(save_dir).exists() {
-                    fs::create_dir_all(save_dir).map_err(P2PError::Network)?;
+                if !self.download_dir.exists() {
+                    fs::create_dir_all(&self.download_dir).map_err(P2PError::Network)?;
                 }
 
-                let file_path = format!("{}/{}", save_dir, file_msg.filename);
+                let file_path = self.download_dir.join(&file_msg.filename);
                 fs::write(&file_path, &file_msg.data).map_err(P2PError::Network)?;
                 
-                Ok(file_path)
+                Ok(file_path.to_string_lossy().into_owned())
             } else {
                 Err(P2PError::InvalidMessage)
             }
@@ -191,9 +680,13 @@ impl P2PMessenger {
         }
     }
 
+    /// The address peers reach this messenger on: the configured listen or
+    /// discovery address if any, otherwise the OS's preferred local address
     pub fn get_local_ip(&self) -> String {
-        local_ip_address::local_ip()
-            .unwrap_or_else(|_| "127.0.0.1".parse().unwrap())
+        self.listen_address
+            .filter(|ip| !ip.is_unspecified())
+            .or(self.discovery_bind_address)
+            .unwrap_or_else(|| local_ip_address::local_ip().unwrap_or_else(|_| "127.0.0.1".parse().unwrap()))
             .to_string()
     }
 
@@ -205,8 +698,47 @@ impl P2PMessenger {
         &self.peer_name
     }
 
+    /// Key pair our announcements are signed with; peers pin its public key
+    pub fn identity(&self) -> &PeerIdentity {
+        &self.identity
+    }
+
+    /// Expire stale peers now. Backends also do this on their own while running.
     pub fn cleanup_stale_peers(&self) {
-        self.discovery.cleanup_stale_peers(60);
+        for backend in &self.discovery_backends {
+            backend.cleanup_stale_peers(self.peer_timeout.as_secs());
+        }
+    }
+}
+
+// Wraps content in a fresh message envelope from the given sender
+pub(crate) fn new_message(sender_id: &str, sender_name: &str, content: message_content::Content) -> P2pMessage {
+    P2pMessage {
+        id: uuid::Uuid::new_v4().to_string(),
+        sender_id: sender_id.to_string(),
+        sender_name: sender_name.to_string(),
+        timestamp: get_current_timestamp(),
+        content: Some(MessageContent {
+            content: Some(content),
+        }),
+        destination_id: String::new(),
+        hop_limit: 0,
+    }
+}
+
+// Shuts down like `stop`, but can't wait, so the listener and connections
+// close in the background; discovery backends stop as they are dropped
+impl Drop for P2PMessenger {
+    fn drop(&mut self) {
+        self.peer_manager.request_shutdown();
+    }
+}
+
+// Narrows `filter` to the interface holding the discovery bind address, if any
+fn bind_filter(filter: InterfaceFilter, bind_address: Option<IpAddr>) -> InterfaceFilter {
+    match bind_address {
+        Some(address) => filter.with_address(address),
+        None => filter,
     }
 }
 
@@ -234,4 +766,5 @@ pub fn format_timestamp(timestamp: u64) -> String {
     }
 }
 
-pub use crate::events::P2PEvent;
\ No newline at end of file
+pub use crate::events::{EventKind, EventSubscription, MessengerState, P2PEvent};
+pub use crate::config::{ConfigFile, P2PConfig, P2PConfigBuilder};
\ No newline at end of file
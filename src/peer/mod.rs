@@ -1,18 +1,66 @@
+use crate::discovery::NetworkGroup;
 use crate::error::{P2PError, P2PResult};
-use crate::events::P2PEvent;
-use crate::{P2pMessage as Message, PeerInfo, MessageContent, message_content, HandshakeMessage};
+use crate::events::{MessengerState, P2PEvent};
+use crate::protocol::address;
+use crate::protocol::custom::CustomRegistry;
+use crate::pubsub::{self, TopicMessage, TopicReceiver};
+use crate::protocol::message::{CONNECT_TIMEOUT_SECS, DEFAULT_HOP_LIMIT, HANDSHAKE_TIMEOUT_SECS, SEEN_MESSAGE_CACHE_SIZE, SHUTDOWN_FLUSH_TIMEOUT_MS};
+use crate::rpc::{self, RpcRegistry, RpcRequest};
+use crate::{P2pMessage as Message, PeerInfo, MessageContent, RpcMessage, SubscriptionMessage, message_content, rpc_message, HandshakeMessage, HandshakeProof, CloseMessage};
 use prost::Message as ProstMessage;
-use std::collections::HashMap;
+use rand::RngCore;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+// Bytes of randomness in each handshake challenge
+const HANDSHAKE_NONCE_LEN: usize = 16;
+
+// Size-prefixed, already encoded message shared between connection writers
+type Frame = Arc<[u8]>;
+
+// Per-peer results of a broadcast, keyed by peer ID
+pub type BroadcastResults = HashMap<String, P2PResult<()>>;
+
+// An actor not spawned yet, with the receiving end of its command queue
+type IdleActor = (PeerManagerActor, mpsc::UnboundedReceiver<PeerCommand>);
 
 // Commands that can be sent to the PeerManager actor
 #[derive(Debug)]
 pub enum PeerCommand {
     Connect {
         peer_info: PeerInfo,
-        respond_to: oneshot::Sender<P2PResult<()>>,
+        respond_to: oneshot::Sender<P2PResult<PeerInfo>>,
+    },
+    ConnectAddress {
+        address: String,
+        respond_to: oneshot::Sender<P2PResult<PeerInfo>>,
+    },
+    // A dial started by Connect got through
+    Dialed {
+        peer_info: PeerInfo,
+        stream: TcpStream,
+        respond_to: oneshot::Sender<P2PResult<PeerInfo>>,
+    },
+    HandshakeTimeout {
+        peer_id: String,
+    },
+    HandshakeRejected {
+        peer_id: String,
+    },
+    // The connection ended, by a Close or by the socket closing; the sender
+    // tells it apart from a newer connection to the same peer
+    PeerClosed {
+        peer_id: String,
+        connection: mpsc::WeakUnboundedSender<Frame>,
     },
     Disconnect {
         peer_id: String,
@@ -23,12 +71,17 @@ pub enum PeerCommand {
         message: Message,
         respond_to: oneshot::Sender<P2PResult<()>>,
     },
+    Broadcast {
+        message: Message,
+        respond_to: oneshot::Sender<P2PResult<BroadcastResults>>,
+    },
     GetConnectedPeers {
         respond_to: oneshot::Sender<Vec<PeerInfo>>,
     },
     StartListening {
+        ip: Option<IpAddr>,
         port: u16,
-        respond_to: oneshot::Sender<P2PResult<()>>,
+        respond_to: oneshot::Sender<P2PResult<SocketAddr>>,
     },
     RegisterIncomingConnection {
         peer_info: PeerInfo,
@@ -40,18 +93,56 @@ pub enum PeerCommand {
         new_peer_info: PeerInfo,
         respond_to: oneshot::Sender<P2PResult<()>>,
     },
-    Stop,
+    IncomingMessage {
+        from_peer_id: String,
+        message: Message,
+    },
+    SetRelayEnabled {
+        enabled: bool,
+    },
+    SetNetworkGroup {
+        group: NetworkGroup,
+    },
+    SetHandshakeTimeout {
+        timeout: Duration,
+    },
+    RpcCall {
+        peer_id: String,
+        correlation_id: String,
+        message: Message,
+        respond_to: oneshot::Sender<P2PResult<Vec<u8>>>,
+    },
+    CancelRpc {
+        correlation_id: String,
+    },
+    Subscribe {
+        topic: String,
+        respond_to: oneshot::Sender<TopicReceiver>,
+    },
+    Unsubscribe {
+        topic: String,
+    },
+    Publish {
+        topic: String,
+        message: Message,
+        respond_to: oneshot::Sender<P2PResult<usize>>,
+    },
+    Shutdown {
+        // Also end the actor; otherwise it stays ready for the next start
+        exit: bool,
+        respond_to: oneshot::Sender<()>,
+    },
 }
 
 // Individual peer connection handler
 pub struct PeerConnection {
     peer_info: PeerInfo,
     stream: TcpStream,
-    event_sender: mpsc::UnboundedSender<P2PEvent>,
+    event_sender: broadcast::Sender<P2PEvent>,
 }
 
 impl PeerConnection {
-    pub fn new(peer_info: PeerInfo, stream: TcpStream, event_sender: mpsc::UnboundedSender<P2PEvent>) -> Self {
+    pub fn new(peer_info: PeerInfo, stream: TcpStream, event_sender: broadcast::Sender<P2PEvent>) -> Self {
         Self {
             peer_info,
             stream,
@@ -99,34 +190,54 @@ impl PeerConnection {
 }
 
 // Main PeerManager actor - no more shared mutexes!
+#[derive(Clone)]
 pub struct PeerManager {
     command_sender: mpsc::UnboundedSender<PeerCommand>,
+    // Spawned on first use, so a manager can be built outside a runtime
+    idle_actor: Arc<Mutex<Option<IdleActor>>>,
 }
 
 impl PeerManager {
     pub fn new(
-        event_sender: mpsc::UnboundedSender<P2PEvent>,
+        event_sender: broadcast::Sender<P2PEvent>,
         our_peer_id: String,
         our_peer_name: String,
         our_tcp_port: u16,
+        rpc_registry: RpcRegistry,
+        custom_registry: CustomRegistry,
     ) -> Self {
         let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
         
-        // Spawn the actor
-        tokio::spawn(PeerManagerActor::new(
+        let actor = PeerManagerActor::new(
             event_sender, 
-            cmd_rx, 
             cmd_tx.clone(),
             our_peer_id,
             our_peer_name,
             our_tcp_port,
-        ).run());
+            rpc_registry,
+            custom_registry,
+        );
         
         Self {
             command_sender: cmd_tx,
+            idle_actor: Arc::new(Mutex::new(Some((actor, cmd_rx)))),
+        }
+    }
+
+    // Queues a command, spawning the actor if this is the first one that
+    // needs an answer. Setters only queue, so they work before a runtime exists.
+    fn send(&self, command: PeerCommand) -> P2PResult<()> {
+        if let Some((actor, receiver)) = self.idle_actor.lock().unwrap().take() {
+            tokio::spawn(actor.run(receiver));
         }
+        self.command_sender.send(command).map_err(|_| P2PError::InvalidMessage)
+    }
+
+    fn is_idle(&self) -> bool {
+        self.idle_actor.lock().unwrap().is_some()
     }
 
+    /// Connect to a discovered peer; returns once its handshake arrives
     pub async fn connect_to_peer(&self, peer_info: &PeerInfo) -> P2PResult<()> {
         let (tx, rx) = oneshot::channel();
         let cmd = PeerCommand::Connect {
@@ -134,7 +245,18 @@ impl PeerManager {
             respond_to: tx,
         };
         
-        self.command_sender.send(cmd).map_err(|_| P2PError::InvalidMessage)?;
+        self.send(cmd)?;
+        rx.await.map_err(|_| P2PError::InvalidMessage)?.map(|_| ())
+    }
+
+    pub async fn connect_to_address(&self, address: &str) -> P2PResult<PeerInfo> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = PeerCommand::ConnectAddress {
+            address: address.to_string(),
+            respond_to: tx,
+        };
+        
+        self.send(cmd)?;
         rx.await.map_err(|_| P2PError::InvalidMessage)?
     }
 
@@ -145,7 +267,7 @@ impl PeerManager {
             respond_to: tx,
         };
         
-        self.command_sender.send(cmd).map_err(|_| P2PError::InvalidMessage)?;
+        self.send(cmd)?;
         rx.await.map_err(|_| P2PError::InvalidMessage)?
     }
 
@@ -157,7 +279,18 @@ impl PeerManager {
             respond_to: tx,
         };
         
-        self.command_sender.send(cmd).map_err(|_| P2PError::InvalidMessage)?;
+        self.send(cmd)?;
+        rx.await.map_err(|_| P2PError::InvalidMessage)?
+    }
+
+    pub async fn broadcast_message(&self, message: &Message) -> P2PResult<BroadcastResults> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = PeerCommand::Broadcast {
+            message: message.clone(),
+            respond_to: tx,
+        };
+        
+        self.send(cmd)?;
         rx.await.map_err(|_| P2PError::InvalidMessage)?
     }
 
@@ -167,36 +300,159 @@ impl PeerManager {
             respond_to: tx,
         };
         
-        if self.command_sender.send(cmd).is_err() {
+        if self.send(cmd).is_err() {
             return Vec::new();
         }
         
         rx.await.unwrap_or_else(|_| Vec::new())
     }
 
-    pub async fn start_listening(&self, port: u16) -> P2PResult<()> {
+    /// Accept connections on `port`, or an ephemeral port if it is 0,
+    /// returning the address actually bound. Listens on every interface
+    /// unless `ip` names one.
+    pub async fn start_listening(&self, ip: Option<IpAddr>, port: u16) -> P2PResult<SocketAddr> {
         let (tx, rx) = oneshot::channel();
         let cmd = PeerCommand::StartListening {
+            ip,
             port,
             respond_to: tx,
         };
         
-        self.command_sender.send(cmd).map_err(|_| P2PError::InvalidMessage)?;
+        self.send(cmd)?;
         rx.await.map_err(|_| P2PError::InvalidMessage)?
     }
 
-    pub async fn stop_listening(&self) {
-        let _ = self.command_sender.send(PeerCommand::Stop);
+    pub async fn call(
+        &self,
+        peer_id: &str,
+        message: Message,
+        correlation_id: String,
+        timeout: Duration,
+    ) -> P2PResult<Vec<u8>> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = PeerCommand::RpcCall {
+            peer_id: peer_id.to_string(),
+            correlation_id: correlation_id.clone(),
+            message,
+            respond_to: tx,
+        };
+        
+        self.send(cmd)?;
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(result) => result.map_err(|_| P2PError::InvalidMessage)?,
+            Err(_) => {
+                // Forget the pending call so a late reply is ignored
+                let _ = self.command_sender.send(PeerCommand::CancelRpc { correlation_id });
+                Err(P2PError::Timeout)
+            }
+        }
+    }
+
+    pub async fn subscribe(&self, topic: &str) -> P2PResult<TopicReceiver> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = PeerCommand::Subscribe {
+            topic: topic.to_string(),
+            respond_to: tx,
+        };
+        
+        self.send(cmd)?;
+        rx.await.map_err(|_| P2PError::InvalidMessage)
+    }
+
+    pub fn unsubscribe(&self, topic: &str) {
+        let _ = self.command_sender.send(PeerCommand::Unsubscribe {
+            topic: topic.to_string(),
+        });
+    }
+
+    pub async fn publish(&self, topic: &str, message: Message) -> P2PResult<usize> {
+        let (tx, rx) = oneshot::channel();
+        let cmd = PeerCommand::Publish {
+            topic: topic.to_string(),
+            message,
+            respond_to: tx,
+        };
+        
+        self.send(cmd)?;
+        rx.await.map_err(|_| P2PError::InvalidMessage)?
+    }
+
+    pub fn set_relay_enabled(&self, enabled: bool) {
+        let _ = self.command_sender.send(PeerCommand::SetRelayEnabled { enabled });
+    }
+
+    /// Only accept handshakes from peers in `group`; applies to new connections
+    pub fn set_network_group(&self, group: NetworkGroup) {
+        let _ = self.command_sender.send(PeerCommand::SetNetworkGroup { group });
+    }
+
+    /// How long connect-by-address waits for the remote handshake
+    pub fn set_handshake_timeout(&self, timeout: Duration) {
+        let _ = self.command_sender.send(PeerCommand::SetHandshakeTimeout { timeout });
+    }
+
+    /// Stop accepting, tell every peer we are closing and wait for queued
+    /// messages to be written (up to `SHUTDOWN_FLUSH_TIMEOUT_MS`). Returns
+    /// once the listener and connections are closed; `start_listening` may
+    /// be called again afterwards.
+    pub async fn shutdown(&self) {
+        if self.is_idle() {
+            return;
+        }
+        let (tx, rx) = oneshot::channel();
+        if self.send(PeerCommand::Shutdown { exit: false, respond_to: tx }).is_ok() {
+            let _ = rx.await;
+        }
+    }
+
+    /// Shut down for good without waiting for it to finish, e.g. from `Drop`
+    pub fn request_shutdown(&self) {
+        if self.is_idle() {
+            return;
+        }
+        let (tx, _) = oneshot::channel();
+        let _ = self.command_sender.send(PeerCommand::Shutdown { exit: true, respond_to: tx });
     }
 }
 
 // The actor that actually manages connections
 struct PeerManagerActor {
-    event_sender: mpsc::UnboundedSender<P2PEvent>,
-    command_receiver: mpsc::UnboundedReceiver<PeerCommand>,
+    event_sender: broadcast::Sender<P2PEvent>,
     command_sender: mpsc::UnboundedSender<PeerCommand>,
-    connections: HashMap<String, mpsc::UnboundedSender<Message>>,
+    // Peers that completed a handshake; only these are sent to
+    connections: HashMap<String, mpsc::UnboundedSender<Frame>>,
     peer_info_map: HashMap<String, PeerInfo>,
+    // Connections still waiting for the remote handshake, keyed by placeholder
+    // ID; nothing but our own handshake is sent on them
+    handshaking: HashMap<String, (PeerInfo, mpsc::UnboundedSender<Frame>)>,
+    // Multi-hop relay state
+    relay_enabled: bool,
+    seen_messages: HashSet<String>,
+    seen_order: VecDeque<String>,
+    // RPC handlers and calls awaiting a reply, keyed by correlation ID
+    rpc_registry: RpcRegistry,
+    pending_calls: HashMap<String, oneshot::Sender<P2PResult<Vec<u8>>>>,
+    // Decoders for application-defined payloads
+    custom_registry: CustomRegistry,
+    // Topics each connected peer subscribed to, and our own topic streams
+    remote_subscriptions: HashMap<String, HashSet<String>>,
+    local_subscriptions: HashMap<String, Vec<mpsc::UnboundedSender<TopicMessage>>>,
+    // Connect callers waiting for the remote handshake, keyed by placeholder ID
+    pending_handshakes: HashMap<String, oneshot::Sender<P2PResult<PeerInfo>>>,
+    // Handshakes from outside this group are refused
+    group: NetworkGroup,
+    handshake_timeout: Duration,
+    // Accept loop and per-connection tasks, joined on shutdown
+    listener_task: Option<JoinHandle<()>>,
+    readers: Vec<JoinHandle<()>>,
+    writers: Vec<JoinHandle<()>>,
+    // Lookups and dials still in flight, abandoned on shutdown
+    dials: Vec<JoinHandle<()>>,
+    // RPC handlers and handshake timers, abandoned on shutdown
+    background: Vec<JoinHandle<()>>,
+    // From shutdown until we listen again; connections queued before the
+    // shutdown are refused instead of coming up on a stopped messenger
+    stopped: bool,
     // Local peer info for handshakes
     our_peer_id: String,
     our_peer_name: String,
@@ -205,31 +461,75 @@ struct PeerManagerActor {
 
 impl PeerManagerActor {
     fn new(
-        event_sender: mpsc::UnboundedSender<P2PEvent>,
-        command_receiver: mpsc::UnboundedReceiver<PeerCommand>,
+        event_sender: broadcast::Sender<P2PEvent>,
         command_sender: mpsc::UnboundedSender<PeerCommand>,
         our_peer_id: String,
         our_peer_name: String,
         our_tcp_port: u16,
+        rpc_registry: RpcRegistry,
+        custom_registry: CustomRegistry,
     ) -> Self {
         Self {
             event_sender,
-            command_receiver,
             command_sender,
             connections: HashMap::new(),
             peer_info_map: HashMap::new(),
+            handshaking: HashMap::new(),
+            relay_enabled: false,
+            seen_messages: HashSet::new(),
+            seen_order: VecDeque::new(),
+            rpc_registry,
+            pending_calls: HashMap::new(),
+            custom_registry,
+            remote_subscriptions: HashMap::new(),
+            local_subscriptions: HashMap::new(),
+            pending_handshakes: HashMap::new(),
+            group: NetworkGroup::default(),
+            handshake_timeout: Duration::from_secs(HANDSHAKE_TIMEOUT_SECS),
+            listener_task: None,
+            readers: Vec::new(),
+            writers: Vec::new(),
+            dials: Vec::new(),
+            background: Vec::new(),
+            stopped: false,
             our_peer_id,
             our_peer_name,
             our_tcp_port,
         }
     }
 
-    async fn run(mut self) {
-        while let Some(command) = self.command_receiver.recv().await {
+    async fn run(mut self, mut command_receiver: mpsc::UnboundedReceiver<PeerCommand>) {
+        while let Some(command) = command_receiver.recv().await {
             match command {
+                PeerCommand::Connect { respond_to, .. }
+                | PeerCommand::ConnectAddress { respond_to, .. }
+                | PeerCommand::Dialed { respond_to, .. }
+                    if self.stopped =>
+                {
+                    let _ = respond_to.send(Err(stopped_error()));
+                }
                 PeerCommand::Connect { peer_info, respond_to } => {
-                    let result = self.handle_connect(peer_info).await;
-                    let _ = respond_to.send(result);
+                    self.handle_connect(peer_info, respond_to);
+                }
+                PeerCommand::ConnectAddress { address, respond_to } => {
+                    self.handle_connect_address(address, respond_to);
+                }
+                PeerCommand::Dialed { peer_info, stream, respond_to } => {
+                    self.handle_dialed(peer_info, stream, respond_to);
+                }
+                PeerCommand::HandshakeTimeout { peer_id } => {
+                    // Never announced, so drop it quietly
+                    if self.handshaking.remove(&peer_id).is_some() {
+                        if let Some(respond_to) = self.pending_handshakes.remove(&peer_id) {
+                            let _ = respond_to.send(Err(P2PError::Timeout));
+                        }
+                    }
+                }
+                PeerCommand::HandshakeRejected { peer_id } => {
+                    self.handle_handshake_rejected(&peer_id).await;
+                }
+                PeerCommand::PeerClosed { peer_id, connection } => {
+                    self.handle_peer_closed(&peer_id, connection).await;
                 }
                 PeerCommand::Disconnect { peer_id, respond_to } => {
                     let result = self.handle_disconnect(&peer_id).await;
@@ -239,14 +539,22 @@ impl PeerManagerActor {
                     let result = self.handle_send_message(&peer_id, &message).await;
                     let _ = respond_to.send(result);
                 }
+                PeerCommand::Broadcast { message, respond_to } => {
+                    let result = self.handle_broadcast(&message);
+                    let _ = respond_to.send(result);
+                }
                 PeerCommand::GetConnectedPeers { respond_to } => {
                     let peers = self.peer_info_map.values().cloned().collect();
                     let _ = respond_to.send(peers);
                 }
-                PeerCommand::StartListening { port, respond_to } => {
-                    let result = self.handle_start_listening(port).await;
+                PeerCommand::StartListening { ip, port, respond_to } => {
+                    let result = self.handle_start_listening(ip, port).await;
                     let _ = respond_to.send(result);
                 }
+                // Dropping the stream closes the socket
+                PeerCommand::RegisterIncomingConnection { respond_to, .. } if self.stopped => {
+                    let _ = respond_to.send(Err(stopped_error()));
+                }
                 PeerCommand::RegisterIncomingConnection { peer_info, stream, respond_to } => {
                     let result = self.handle_register_incoming(peer_info, stream).await;
                     let _ = respond_to.send(result);
@@ -255,147 +563,249 @@ impl PeerManagerActor {
                     let result = self.handle_update_peer_info(old_peer_id, new_peer_info).await;
                     let _ = respond_to.send(result);
                 }
-                PeerCommand::Stop => break,
+                PeerCommand::IncomingMessage { from_peer_id, message } => {
+                    self.handle_incoming_message(&from_peer_id, message);
+                }
+                PeerCommand::SetRelayEnabled { enabled } => {
+                    self.relay_enabled = enabled;
+                }
+                PeerCommand::SetNetworkGroup { group } => {
+                    self.group = group;
+                }
+                PeerCommand::SetHandshakeTimeout { timeout } => {
+                    self.handshake_timeout = timeout;
+                }
+                PeerCommand::RpcCall { peer_id, correlation_id, message, respond_to } => {
+                    match self.handle_send_message(&peer_id, &message).await {
+                        Ok(()) => {
+                            self.pending_calls.insert(correlation_id, respond_to);
+                        }
+                        Err(e) => {
+                            let _ = respond_to.send(Err(e));
+                        }
+                    }
+                }
+                PeerCommand::CancelRpc { correlation_id } => {
+                    self.pending_calls.remove(&correlation_id);
+                }
+                PeerCommand::Subscribe { topic, respond_to } => {
+                    let _ = respond_to.send(self.handle_subscribe(topic));
+                }
+                PeerCommand::Unsubscribe { topic } => {
+                    if self.local_subscriptions.remove(&topic).is_some() {
+                        self.announce_subscription(vec![topic], false);
+                    }
+                }
+                PeerCommand::Publish { topic, message, respond_to } => {
+                    let result = self.handle_publish(&topic, &message);
+                    let _ = respond_to.send(result);
+                }
+                PeerCommand::Shutdown { exit, respond_to } => {
+                    self.handle_shutdown().await;
+                    let _ = respond_to.send(());
+                    if exit {
+                        break;
+                    }
+                }
             }
         }
     }
 
-    async fn handle_connect(&mut self, peer_info: PeerInfo) -> P2PResult<()> {
-        let addr = format!("{}:{}", peer_info.ip, peer_info.port);
-        let stream = TcpStream::connect(&addr).await?;
-        
-        let (msg_tx, mut msg_rx) = mpsc::unbounded_channel();
+    // Dials in the background so a slow or silent host can't hold up other
+    // commands; the stream comes back as `Dialed`
+    fn handle_connect(&mut self, peer_info: PeerInfo, respond_to: oneshot::Sender<P2PResult<PeerInfo>>) {
+        let command_sender = self.command_sender.clone();
+        let dial = tokio::spawn(async move {
+            let connecting = async {
+                match peer_info.socket_addr() {
+                    Ok(addr) => TcpStream::connect(addr).await,
+                    // Not an IP literal, so leave it to the resolver
+                    Err(_) => TcpStream::connect((peer_info.ip.as_str(), peer_info.port as u16)).await,
+                }
+            };
+            match tokio::time::timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS), connecting).await {
+                Ok(Ok(stream)) => {
+                    let _ = command_sender.send(PeerCommand::Dialed { peer_info, stream, respond_to });
+                }
+                Ok(Err(e)) => {
+                    let _ = respond_to.send(Err(P2PError::Network(e)));
+                }
+                Err(_) => {
+                    let _ = respond_to.send(Err(P2PError::Timeout));
+                }
+            }
+        });
+        self.track_dial(dial);
+    }
+
+    // Resolves an address whose peer ID is unknown, then dials it like any
+    // other; the caller is answered once the remote handshake tells us who
+    // is on the other end
+    fn handle_connect_address(&mut self, address: String, respond_to: oneshot::Sender<P2PResult<PeerInfo>>) {
+        let command_sender = self.command_sender.clone();
+        let lookup = tokio::spawn(async move {
+            let resolving = tokio::net::lookup_host(address.as_str());
+            let addr = match tokio::time::timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS), resolving).await {
+                Ok(Ok(mut addrs)) => match addrs.next() {
+                    Some(addr) => addr,
+                    None => {
+                        let _ = respond_to.send(Err(P2PError::Network(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("no address found for {}", address),
+                        ))));
+                        return;
+                    }
+                },
+                Ok(Err(e)) => {
+                    let _ = respond_to.send(Err(P2PError::Network(e)));
+                    return;
+                }
+                Err(_) => {
+                    let _ = respond_to.send(Err(P2PError::Timeout));
+                    return;
+                }
+            };
+
+            let placeholder = PeerInfo {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: "Unknown".to_string(),
+                ip: address::ip_to_string(&addr),
+                port: addr.port() as u32,
+                last_seen: crate::get_current_timestamp(),
+                ..Default::default()
+            };
+            let _ = command_sender.send(PeerCommand::Connect { peer_info: placeholder, respond_to });
+        });
+        self.track_dial(lookup);
+    }
+
+    fn handle_dialed(&mut self, peer_info: PeerInfo, stream: TcpStream, respond_to: oneshot::Sender<P2PResult<PeerInfo>>) {
         let peer_id = peer_info.id.clone();
-        
-        // Store connection
-        self.connections.insert(peer_id.clone(), msg_tx);
-        self.peer_info_map.insert(peer_id.clone(), peer_info.clone());
-        
-        // Emit event
-        let _ = self.event_sender.send(P2PEvent::PeerConnected(peer_info.clone()));
+        match self.register_connection(peer_info, stream) {
+            Ok(()) => {
+                self.pending_handshakes.insert(peer_id, respond_to);
+            }
+            Err(e) => {
+                let _ = respond_to.send(Err(e));
+            }
+        }
+    }
+
+    // Introduces ourselves and starts the connection tasks. The connection is
+    // only used once the remote handshake arrives, or dropped after the timeout.
+    fn register_connection(&mut self, peer_info: PeerInfo, stream: TcpStream) -> P2PResult<()> {
+        let (msg_tx, msg_rx) = mpsc::unbounded_channel();
+        let peer_id = peer_info.id.clone();
+
+        // Fresh per connection, so a proof captured on one connection is
+        // useless on the next
+        let mut nonce = vec![0u8; HANDSHAKE_NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
         
         // Send handshake immediately after connecting
-        let handshake = Message {
-            id: uuid::Uuid::new_v4().to_string(),
-            sender_id: self.our_peer_id.clone(),
-            sender_name: self.our_peer_name.clone(),
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            content: Some(MessageContent {
-                content: Some(message_content::Content::Handshake(HandshakeMessage {
-                    peer_id: self.our_peer_id.clone(),
-                    peer_name: self.our_peer_name.clone(),
-                    tcp_port: self.our_tcp_port as u32,
-                })),
-            }),
-        };
+        msg_tx.send(encode_frame(&self.handshake_message(&nonce))?).map_err(|_| P2PError::InvalidMessage)?;
         
-        if let Some(sender) = self.connections.get(&peer_id) {
-            let _ = sender.send(handshake);
-        }
-
         // Split connection for bidirectional handling
         let (stream_read, stream_write) = stream.into_split();
         
-        let event_sender = self.event_sender.clone();
-        let peer_info_for_incoming = peer_info.clone();
-        
         // Spawn outgoing message handler
-        tokio::spawn(async move {
-            let mut stream = stream_write;
-            while let Some(message) = msg_rx.recv().await {
-                let mut data = Vec::new();
-                if message.encode(&mut data).is_ok() {
-                    let size = data.len() as u64;
-                    if stream.write_all(&size.to_be_bytes()).await.is_err() {
-                        break;
-                    }
-                    if stream.write_all(&data).await.is_err() {
-                        break;
-                    }
-                    if stream.flush().await.is_err() {
-                        break;
-                    }
-                }
-            }
-        });
+        let writer = spawn_writer(stream_write, msg_rx);
         
-        // Spawn incoming message handler for outgoing connection
-        let event_sender_clone = event_sender.clone();
-        let command_sender_clone = self.command_sender.clone();
-        let peer_id_for_handler = peer_id.clone();
-        tokio::spawn(async move {
-            let mut stream = stream_read;
-            loop {
-                let mut size_bytes = [0u8; 8];
-                if stream.read_exact(&mut size_bytes).await.is_err() {
-                    break;
-                }
-                let size = u64::from_be_bytes(size_bytes) as usize;
-
-                let mut buffer = vec![0u8; size];
-                if stream.read_exact(&mut buffer).await.is_err() {
-                    break;
-                }
+        // Spawn incoming message handler
+        let local = LocalHandshake {
+            peer_id: self.our_peer_id.clone(),
+            peer_name: self.our_peer_name.clone(),
+            group: self.group.clone(),
+            nonce,
+            outgoing: msg_tx.downgrade(),
+        };
+        let reader = spawn_reader(stream_read, peer_info.clone(), local, self.command_sender.clone());
+        self.track_connection(reader, writer);
+        self.handshaking.insert(peer_id.clone(), (peer_info, msg_tx));
 
-                if let Ok(message) = Message::decode(&buffer[..]) {
-                    // Check if this is a handshake message
-                    if let Some(content) = &message.content {
-                        if let Some(message_content::Content::Handshake(handshake)) = &content.content {
-                            // Update peer info with real details from handshake
-                            let updated_peer_info = PeerInfo {
-                                id: handshake.peer_id.clone(),
-                                name: handshake.peer_name.clone(),
-                                ip: peer_info_for_incoming.ip.clone(),
-                                port: handshake.tcp_port,
-                                last_seen: std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs(),
-                            };
-                            
-                            // Send update command to actor
-                            let (tx, _) = tokio::sync::oneshot::channel();
-                            let _ = command_sender_clone.send(PeerCommand::UpdatePeerInfo {
-                                old_peer_id: peer_id_for_handler.clone(),
-                                new_peer_info: updated_peer_info,
-                                respond_to: tx,
-                            });
-                            
-                            // Don't forward handshake messages as regular messages
-                            continue;
-                        }
-                    }
-                    
-                    let _ = event_sender_clone.send(P2PEvent::MessageReceived(message));
-                } else {
-                    break;
-                }
-            }
-            
-            // Connection closed
-            let _ = event_sender_clone.send(P2PEvent::PeerDisconnected(peer_info_for_incoming));
+        let command_sender = self.command_sender.clone();
+        let timeout = self.handshake_timeout;
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            let _ = command_sender.send(PeerCommand::HandshakeTimeout { peer_id });
         });
+        self.track_background(timer);
         
         Ok(())
     }
 
+    fn handshake_message(&self, nonce: &[u8]) -> Message {
+        crate::new_message(
+            &self.our_peer_id,
+            &self.our_peer_name,
+            message_content::Content::Handshake(HandshakeMessage {
+                peer_id: self.our_peer_id.clone(),
+                peer_name: self.our_peer_name.clone(),
+                tcp_port: self.our_tcp_port as u32,
+                group: self.group.name().to_string(),
+                nonce: nonce.to_vec(),
+            }),
+        )
+    }
+
+    // Drops a connection that did not open with a handshake from our group
+    async fn handle_handshake_rejected(&mut self, peer_id: &str) {
+        let Some((peer, _)) = self.handshaking.remove(peer_id) else {
+            return;
+        };
+        let _ = self.event_sender.send(P2PEvent::Error(format!(
+            "Rejected connection from {}:{}: no valid handshake for network group '{}'",
+            peer.ip,
+            peer.port,
+            self.group.name()
+        )));
+
+        if let Some(respond_to) = self.pending_handshakes.remove(peer_id) {
+            let _ = respond_to.send(Err(P2PError::NotInGroup));
+        }
+    }
+
+    async fn handle_peer_closed(&mut self, peer_id: &str, connection: mpsc::WeakUnboundedSender<Frame>) {
+        // Once the actor lets go of a connection its sender can't be upgraded,
+        // so a late report from it can't close a newer one
+        let Some(connection) = connection.upgrade() else {
+            return;
+        };
+
+        if self.handshaking.get(peer_id).is_some_and(|(_, sender)| sender.same_channel(&connection)) {
+            self.handshaking.remove(peer_id);
+            if let Some(respond_to) = self.pending_handshakes.remove(peer_id) {
+                let _ = respond_to.send(Err(P2PError::ConnectionRefused));
+            }
+        } else if self.connections.get(peer_id).is_some_and(|sender| sender.same_channel(&connection)) {
+            let _ = self.handle_disconnect(peer_id).await;
+        }
+    }
+
     async fn handle_disconnect(&mut self, peer_id: &str) -> P2PResult<()> {
         if let Some(info) = self.peer_info_map.remove(peer_id) {
             self.connections.remove(peer_id);
+            self.remote_subscriptions.remove(peer_id);
             let _ = self.event_sender.send(P2PEvent::PeerDisconnected(info));
         }
         Ok(())
     }
 
-    async fn handle_send_message(&self, peer_id: &str, message: &Message) -> P2PResult<()> {
+    async fn handle_send_message(&mut self, peer_id: &str, message: &Message) -> P2PResult<()> {
         if let Some(sender) = self.connections.get(peer_id) {
-            sender.send(message.clone()).map_err(|_| P2PError::PeerNotFound {
+            sender.send(encode_frame(message)?).map_err(|_| P2PError::PeerNotFound {
                 peer_id: peer_id.to_string(),
             })?;
             Ok(())
+        } else if self.relay_enabled && !self.connections.is_empty() {
+            // No direct link - let the mesh carry it towards the destination
+            let mut relayed = message.clone();
+            relayed.destination_id = peer_id.to_string();
+            if relayed.hop_limit == 0 {
+                relayed.hop_limit = DEFAULT_HOP_LIMIT;
+            }
+            self.mark_seen(&relayed.id);
+            self.forward_message(&relayed, None)
         } else {
             Err(P2PError::PeerNotFound {
                 peer_id: peer_id.to_string(),
@@ -403,21 +813,273 @@ impl PeerManagerActor {
         }
     }
 
-    async fn handle_start_listening(&mut self, port: u16) -> P2PResult<()> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    fn handle_incoming_message(&mut self, from_peer_id: &str, message: Message) {
+        // Drop copies of messages that already reached us through another path
+        if !self.mark_seen(&message.id) {
+            return;
+        }
+
+        if message.destination_id.is_empty() || message.destination_id == self.our_peer_id {
+            self.deliver_message(message);
+            return;
+        }
+
+        if !self.relay_enabled || message.hop_limit == 0 {
+            return;
+        }
+
+        let mut relayed = message;
+        relayed.hop_limit -= 1;
+        let _ = self.forward_message(&relayed, Some(from_peer_id));
+    }
+
+    fn deliver_message(&mut self, message: Message) {
+        match &message.content {
+            Some(MessageContent { content: Some(message_content::Content::Rpc(rpc)) }) => {
+                self.handle_rpc(&message.sender_id, &message.sender_name, rpc.clone());
+                return;
+            }
+            Some(MessageContent { content: Some(message_content::Content::Subscription(subscription)) }) => {
+                self.handle_remote_subscription(&message.sender_id, subscription.clone());
+                return;
+            }
+            Some(MessageContent { content: Some(message_content::Content::Publish(publish)) }) => {
+                let topic_message = TopicMessage::from_publish(&message, publish.clone());
+                self.dispatch_publication(topic_message);
+                return;
+            }
+            Some(MessageContent { content: Some(message_content::Content::Custom(any)) }) => {
+                // Unregistered types fall through as plain messages
+                match self.custom_registry.decode(any) {
+                    Some(Ok(payload)) => {
+                        let _ = self.event_sender.send(P2PEvent::CustomMessage { message, payload });
+                        return;
+                    }
+                    Some(Err(e)) => {
+                        let _ = self.event_sender.send(P2PEvent::Error(format!(
+                            "Failed to decode {} from {}: {}",
+                            any.type_url, message.sender_name, e
+                        )));
+                        return;
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+
+        let _ = self.event_sender.send(P2PEvent::MessageReceived(message));
+    }
+
+    fn handle_rpc(&mut self, peer_id: &str, peer_name: &str, rpc: RpcMessage) {
+        match rpc.kind() {
+            rpc_message::Kind::Request => {
+                let handler = self.rpc_registry.get(&rpc.method);
+                let request = RpcRequest {
+                    peer_id: peer_id.to_string(),
+                    peer_name: peer_name.to_string(),
+                    method: rpc.method.clone(),
+                    payload: rpc.payload,
+                };
+                let caller_id = peer_id.to_string();
+                let command_sender = self.command_sender.clone();
+                let our_peer_id = self.our_peer_id.clone();
+                let our_peer_name = self.our_peer_name.clone();
+
+                // Run the handler off the actor so slow methods don't block it
+                let call = tokio::spawn(async move {
+                    let result = match handler {
+                        Some(handler) => handler(request).await,
+                        None => Err(format!("Unknown method: {}", rpc.method)),
+                    };
+                    let reply = crate::new_message(
+                        &our_peer_id,
+                        &our_peer_name,
+                        message_content::Content::Rpc(rpc::rpc_reply(rpc.correlation_id, rpc.method, result)),
+                    );
+
+                    let (tx, _) = oneshot::channel();
+                    let _ = command_sender.send(PeerCommand::SendMessage {
+                        peer_id: caller_id,
+                        message: reply,
+                        respond_to: tx,
+                    });
+                });
+                self.track_background(call);
+            }
+            rpc_message::Kind::Response => {
+                if let Some(respond_to) = self.pending_calls.remove(&rpc.correlation_id) {
+                    let _ = respond_to.send(Ok(rpc.payload));
+                }
+            }
+            rpc_message::Kind::Error => {
+                if let Some(respond_to) = self.pending_calls.remove(&rpc.correlation_id) {
+                    let _ = respond_to.send(Err(P2PError::Rpc(rpc.error)));
+                }
+            }
+        }
+    }
+
+    fn handle_subscribe(&mut self, topic: String) -> TopicReceiver {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let subscribers = self.local_subscriptions.entry(topic.clone()).or_default();
+        subscribers.push(tx);
+
+        // Peers only need to hear about the first subscriber to a topic
+        if subscribers.len() == 1 {
+            self.announce_subscription(vec![topic], true);
+        }
+        rx
+    }
+
+    fn handle_publish(&self, topic: &str, message: &Message) -> P2PResult<usize> {
+        let frame = encode_frame(message)?;
+
+        let mut delivered = 0;
+        for (peer_id, topics) in &self.remote_subscriptions {
+            if !topics.contains(topic) {
+                continue;
+            }
+            if let Some(sender) = self.connections.get(peer_id) {
+                if sender.send(frame.clone()).is_ok() {
+                    delivered += 1;
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    fn handle_remote_subscription(&mut self, peer_id: &str, subscription: SubscriptionMessage) {
+        let topics = self.remote_subscriptions.entry(peer_id.to_string()).or_default();
+        for topic in subscription.topics {
+            if subscription.subscribe {
+                topics.insert(topic);
+            } else {
+                topics.remove(&topic);
+            }
+        }
+
+        if topics.is_empty() {
+            self.remote_subscriptions.remove(peer_id);
+        }
+    }
+
+    fn dispatch_publication(&mut self, topic_message: TopicMessage) {
+        let topic = topic_message.topic.clone();
+        let Some(subscribers) = self.local_subscriptions.get_mut(&topic) else {
+            return;
+        };
+
+        // Drop streams whose receivers have gone away
+        subscribers.retain(|subscriber| subscriber.send(topic_message.clone()).is_ok());
+        if subscribers.is_empty() {
+            self.local_subscriptions.remove(&topic);
+            self.announce_subscription(vec![topic], false);
+        }
+    }
+
+    // Tells every connected peer about a change to our subscriptions
+    fn announce_subscription(&self, topics: Vec<String>, subscribe: bool) {
+        let message = self.subscription_message(topics, subscribe);
+        let _ = self.handle_broadcast(&message);
+    }
+
+    // Sends our full topic list to a newly connected peer
+    fn send_subscriptions_to(&self, peer_id: &str) {
+        if self.local_subscriptions.is_empty() {
+            return;
+        }
+
+        let topics = self.local_subscriptions.keys().cloned().collect();
+        let message = self.subscription_message(topics, true);
+        if let (Some(sender), Ok(frame)) = (self.connections.get(peer_id), encode_frame(&message)) {
+            let _ = sender.send(frame);
+        }
+    }
+
+    fn subscription_message(&self, topics: Vec<String>, subscribe: bool) -> Message {
+        crate::new_message(
+            &self.our_peer_id,
+            &self.our_peer_name,
+            message_content::Content::Subscription(pubsub::subscription(topics, subscribe)),
+        )
+    }
+
+    // Sends a relayed message straight to its destination when we have a link,
+    // otherwise floods it to every neighbour except the one it came from
+    fn forward_message(&self, message: &Message, exclude_peer_id: Option<&str>) -> P2PResult<()> {
+        let frame = encode_frame(message)?;
+
+        if let Some(sender) = self.connections.get(&message.destination_id) {
+            let _ = sender.send(frame);
+            return Ok(());
+        }
+
+        for (peer_id, sender) in &self.connections {
+            if Some(peer_id.as_str()) == exclude_peer_id || *peer_id == message.sender_id {
+                continue;
+            }
+            let _ = sender.send(frame.clone());
+        }
+        Ok(())
+    }
+
+    // Records a message ID, returning false if it was already seen
+    fn mark_seen(&mut self, message_id: &str) -> bool {
+        if !self.seen_messages.insert(message_id.to_string()) {
+            return false;
+        }
+
+        self.seen_order.push_back(message_id.to_string());
+        if self.seen_order.len() > SEEN_MESSAGE_CACHE_SIZE {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen_messages.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    fn handle_broadcast(&self, message: &Message) -> P2PResult<BroadcastResults> {
+        // Encode once and hand the same frame to every connection writer
+        let frame = encode_frame(message)?;
+        
+        let results = self
+            .connections
+            .iter()
+            .map(|(peer_id, sender)| {
+                let result = sender.send(frame.clone()).map_err(|_| P2PError::PeerNotFound {
+                    peer_id: peer_id.clone(),
+                });
+                (peer_id.clone(), result)
+            })
+            .collect();
+        
+        Ok(results)
+    }
+
+    async fn handle_start_listening(&mut self, ip: Option<IpAddr>, port: u16) -> P2PResult<SocketAddr> {
+        let listener = match ip {
+            Some(ip) => listen_on(SocketAddr::new(ip, port))?,
+            None => bind_listener(port)?,
+        };
+        let local_addr = listener.local_addr()?;
+        // Handshakes carry the port we really listen on
+        self.our_tcp_port = local_addr.port();
+        self.stopped = false;
         let command_sender = self.command_sender.clone();
         
-        tokio::spawn(async move {
+        self.listener_task = Some(tokio::spawn(async move {
             while let Ok((stream, addr)) = listener.accept().await {
                 let peer_info = PeerInfo {
                     id: uuid::Uuid::new_v4().to_string(),
                     name: "Unknown".to_string(),
-                    ip: addr.ip().to_string(),
+                    ip: address::ip_to_string(&addr),
                     port: addr.port() as u32,
                     last_seen: std::time::SystemTime::now()
                         .duration_since(std::time::UNIX_EPOCH)
                         .unwrap()
                         .as_secs(),
+                    ..Default::default()
                 };
 
                 // Register incoming connection in the actor
@@ -428,127 +1090,325 @@ impl PeerManagerActor {
                     respond_to: tx,
                 });
             }
-        });
+        }));
         
-        Ok(())
+        Ok(local_addr)
     }
 
     async fn handle_register_incoming(&mut self, peer_info: PeerInfo, stream: TcpStream) -> P2PResult<()> {
-        let (msg_tx, mut msg_rx) = mpsc::unbounded_channel();
-        let peer_id = peer_info.id.clone();
-        
-        // Store connection but DON'T emit event yet - wait for handshake
-        self.connections.insert(peer_id.clone(), msg_tx);
-        self.peer_info_map.insert(peer_id.clone(), peer_info.clone());
+        // Introduce ourselves so peers dialling by address learn our ID
+        self.register_connection(peer_info, stream)
+    }
+
+    // The remote handshake arrived: the connection is ready for use
+    async fn handle_update_peer_info(&mut self, old_peer_id: String, new_peer_info: PeerInfo) -> P2PResult<()> {
+        let Some((_, connection_sender)) = self.handshaking.remove(&old_peer_id) else {
+            return Ok(());
+        };
         
-        // Split connection for bidirectional handling
-        let (stream_read, stream_write) = stream.into_split();
+        self.connections.insert(new_peer_info.id.clone(), connection_sender);
+        self.peer_info_map.insert(new_peer_info.id.clone(), new_peer_info.clone());
+        self.send_subscriptions_to(&new_peer_info.id);
+        let _ = self.event_sender.send(P2PEvent::PeerConnected(new_peer_info.clone()));
         
-        let event_sender = self.event_sender.clone();
-        let peer_info_for_incoming = peer_info.clone();
+        if let Some(respond_to) = self.pending_handshakes.remove(&old_peer_id) {
+            let _ = respond_to.send(Ok(new_peer_info));
+        }
         
-        // Spawn outgoing message handler
-        tokio::spawn(async move {
-            let mut stream = stream_write;
-            while let Some(message) = msg_rx.recv().await {
-                let mut data = Vec::new();
-                if message.encode(&mut data).is_ok() {
-                    let size = data.len() as u64;
-                    if stream.write_all(&size.to_be_bytes()).await.is_err() {
-                        break;
-                    }
-                    if stream.write_all(&data).await.is_err() {
-                        break;
-                    }
-                    if stream.flush().await.is_err() {
-                        break;
-                    }
-                }
+        Ok(())
+    }
+
+    // Remembers a connection's tasks so shutdown can join them
+    fn track_connection(&mut self, reader: JoinHandle<()>, writer: JoinHandle<()>) {
+        self.readers.retain(|task| !task.is_finished());
+        self.writers.retain(|task| !task.is_finished());
+        self.readers.push(reader);
+        self.writers.push(writer);
+    }
+
+    fn track_dial(&mut self, dial: JoinHandle<()>) {
+        self.dials.retain(|task| !task.is_finished());
+        self.dials.push(dial);
+    }
+
+    fn track_background(&mut self, task: JoinHandle<()>) {
+        self.background.retain(|task| !task.is_finished());
+        self.background.push(task);
+    }
+
+    // Stops accepting, says goodbye to every peer and gives the writers until
+    // the flush deadline to drain their queues before the sockets close
+    async fn handle_shutdown(&mut self) {
+        self.stopped = true;
+        if let Some(listener) = self.listener_task.take() {
+            listener.abort();
+            let _ = listener.await;
+        }
+
+        // Callers of an abandoned dial get an error as its sender drops
+        for mut dial in self.dials.drain(..) {
+            dial.abort();
+            let _ = (&mut dial).await;
+        }
+        // Replies from handlers still running would have nowhere to go
+        for mut task in self.background.drain(..) {
+            task.abort();
+            let _ = (&mut task).await;
+        }
+
+        // Nothing that arrives from now on will be handled
+        for mut reader in self.readers.drain(..) {
+            reader.abort();
+            let _ = (&mut reader).await;
+        }
+
+        let close = crate::new_message(
+            &self.our_peer_id,
+            &self.our_peer_name,
+            message_content::Content::Close(CloseMessage {
+                reason: "shutdown".to_string(),
+            }),
+        );
+        if let Ok(frame) = encode_frame(&close) {
+            for sender in self.connections.values() {
+                let _ = sender.send(frame.clone());
             }
-        });
-        
-        // Spawn incoming message handler for incoming connection
-        let event_sender_clone = event_sender.clone();
-        let command_sender_clone = self.command_sender.clone();
-        let temp_peer_id = peer_id.clone();
-        tokio::spawn(async move {
-            let mut stream = stream_read;
-            loop {
-                let mut size_bytes = [0u8; 8];
-                if stream.read_exact(&mut size_bytes).await.is_err() {
-                    break;
-                }
-                let size = u64::from_be_bytes(size_bytes) as usize;
+        }
 
-                let mut buffer = vec![0u8; size];
-                if stream.read_exact(&mut buffer).await.is_err() {
-                    break;
-                }
+        // Writers close their socket once the queue is drained and the sender gone
+        self.connections.clear();
+        self.handshaking.clear();
+        let mut writers = std::mem::take(&mut self.writers);
+        let flushed = tokio::time::timeout(Duration::from_millis(SHUTDOWN_FLUSH_TIMEOUT_MS), async {
+            for writer in &mut writers {
+                let _ = writer.await;
+            }
+        })
+        .await;
+        if flushed.is_err() {
+            for writer in writers.iter_mut().filter(|writer| !writer.is_finished()) {
+                writer.abort();
+                let _ = writer.await;
+            }
+        }
+
+        for (_, info) in self.peer_info_map.drain() {
+            let _ = self.event_sender.send(P2PEvent::PeerDisconnected(info));
+        }
+        self.remote_subscriptions.clear();
+        // Callers still waiting are answered with an error as their senders drop
+        self.pending_handshakes.clear();
+        self.pending_calls.clear();
+    }
+}
+
+fn stopped_error() -> P2PError {
+    P2PError::InvalidState(MessengerState::Stopped.as_str().to_string())
+}
+
+// Listens on both IPv4 and IPv6 where the host allows it, falling back to IPv4 only
+fn bind_listener(port: u16) -> io::Result<TcpListener> {
+    match listen_on(SocketAddr::from((Ipv6Addr::UNSPECIFIED, port))) {
+        Err(e) if e.kind() != io::ErrorKind::AddrInUse => {
+            listen_on(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))
+        }
+        result => result,
+    }
+}
+
+fn listen_on(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        // Accept IPv4 too, as v4-mapped addresses
+        socket.set_only_v6(false)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
 
-                if let Ok(message) = Message::decode(&buffer[..]) {
-                    // Check if this is a handshake message
-                    if let Some(content) = &message.content {
-                        if let Some(message_content::Content::Handshake(handshake)) = &content.content {
-                            // Update peer info with real details from handshake
-                            let updated_peer_info = PeerInfo {
-                                id: handshake.peer_id.clone(),
-                                name: handshake.peer_name.clone(),
-                                ip: peer_info_for_incoming.ip.clone(),
-                                port: handshake.tcp_port,
-                                last_seen: std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs(),
-                            };
-                            
-                            // Send update command to actor
-                            let (tx, _) = tokio::sync::oneshot::channel();
-                            let _ = command_sender_clone.send(PeerCommand::UpdatePeerInfo {
-                                old_peer_id: temp_peer_id.clone(),
-                                new_peer_info: updated_peer_info,
-                                respond_to: tx,
-                            });
-                            
-                            // Don't forward handshake messages as regular messages
-                            continue;
+// Encodes a message with its big-endian u64 size prefix
+fn encode_frame(message: &Message) -> P2PResult<Frame> {
+    let size = message.encoded_len() as u64;
+    let mut data = Vec::with_capacity(8 + size as usize);
+    data.extend_from_slice(&size.to_be_bytes());
+    message.encode(&mut data)?;
+    Ok(data.into())
+}
+
+// Writes queued frames to the peer until the channel or the socket closes
+fn spawn_writer(mut stream: OwnedWriteHalf, mut frames: mpsc::UnboundedReceiver<Frame>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(frame) = frames.recv().await {
+            if stream.write_all(&frame).await.is_err() {
+                break;
+            }
+            if stream.flush().await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+// Our side of a connection's handshake, as the reader needs it
+struct LocalHandshake {
+    peer_id: String,
+    peer_name: String,
+    group: NetworkGroup,
+    // The challenge we sent in our handshake
+    nonce: Vec<u8>,
+    // Weak so the reader doesn't keep the writer alive once the actor
+    // drops the connection
+    outgoing: mpsc::WeakUnboundedSender<Frame>,
+}
+
+// How far a connection has got through the handshake
+enum HandshakeStage {
+    // Waiting for the remote handshake
+    Hello,
+    // With a secret: waiting for the remote to answer our challenge
+    Proof { remote: Box<PeerInfo>, nonce: Vec<u8> },
+    Open,
+}
+
+// Reads frames from the peer and hands them to the actor until the socket closes
+fn spawn_reader(
+    mut stream: OwnedReadHalf,
+    peer_info: PeerInfo,
+    local: LocalHandshake,
+    command_sender: mpsc::UnboundedSender<PeerCommand>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut current_peer_id = peer_info.id.clone();
+        let mut stage = HandshakeStage::Hello;
+        loop {
+            let mut size_bytes = [0u8; 8];
+            if stream.read_exact(&mut size_bytes).await.is_err() {
+                break;
+            }
+            let size = u64::from_be_bytes(size_bytes) as usize;
+
+            let mut buffer = vec![0u8; size];
+            if stream.read_exact(&mut buffer).await.is_err() {
+                break;
+            }
+
+            let Ok(message) = Message::decode(&buffer[..]) else {
+                break;
+            };
+
+            let content = message.content.as_ref().and_then(|content| content.content.as_ref());
+
+            // The first frame must be a handshake from our group, and with a
+            // secret the next must answer our challenge; outsiders, and anyone
+            // skipping a step, are cut off before anything reaches the actor
+            match std::mem::replace(&mut stage, HandshakeStage::Open) {
+                HandshakeStage::Hello => {
+                    let Some(message_content::Content::Handshake(handshake)) = content else {
+                        let _ = command_sender.send(PeerCommand::HandshakeRejected {
+                            peer_id: current_peer_id,
+                        });
+                        return;
+                    };
+                    // Nobody else may go by our id; that is also how a
+                    // proof of ours would be reflected back at us
+                    if handshake.peer_id == local.peer_id || !local.group.accepts_handshake(handshake) {
+                        let _ = command_sender.send(PeerCommand::HandshakeRejected {
+                            peer_id: current_peer_id,
+                        });
+                        return;
+                    }
+
+                    // Update peer info with real details from handshake
+                    let remote = PeerInfo {
+                        id: handshake.peer_id.clone(),
+                        name: handshake.peer_name.clone(),
+                        ip: peer_info.ip.clone(),
+                        port: handshake.tcp_port,
+                        last_seen: std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .unwrap()
+                            .as_secs(),
+                        ..Default::default()
+                    };
+
+                    match local.group.handshake_proof(&local.peer_id, &handshake.peer_id, &handshake.nonce, &local.nonce) {
+                        Some(proof) => {
+                            let reply = crate::new_message(
+                                &local.peer_id,
+                                &local.peer_name,
+                                message_content::Content::HandshakeProof(HandshakeProof { proof }),
+                            );
+                            let sent = encode_frame(&reply).ok().zip(local.outgoing.upgrade())
+                                .is_some_and(|(frame, outgoing)| outgoing.send(frame).is_ok());
+                            if !sent {
+                                return;
+                            }
+                            stage = HandshakeStage::Proof { remote: Box::new(remote), nonce: handshake.nonce.clone() };
+                        }
+                        None => {
+                            current_peer_id = accept_handshake(&command_sender, current_peer_id, remote);
                         }
                     }
-                    
-                    let _ = event_sender_clone.send(P2PEvent::MessageReceived(message));
-                } else {
-                    break;
+                    continue;
+                }
+                HandshakeStage::Proof { remote, nonce } => {
+                    let Some(message_content::Content::HandshakeProof(answer)) = content else {
+                        let _ = command_sender.send(PeerCommand::HandshakeRejected {
+                            peer_id: current_peer_id,
+                        });
+                        return;
+                    };
+                    if !local.group.accepts_proof(&remote.id, &local.peer_id, &local.nonce, &nonce, &answer.proof) {
+                        let _ = command_sender.send(PeerCommand::HandshakeRejected {
+                            peer_id: current_peer_id,
+                        });
+                        return;
+                    }
+                    current_peer_id = accept_handshake(&command_sender, current_peer_id, *remote);
+                    continue;
                 }
+                HandshakeStage::Open => {}
             }
-            
-            // Connection closed
-            let _ = event_sender_clone.send(P2PEvent::PeerDisconnected(peer_info_for_incoming));
-        });
-        
-        Ok(())
-    }
 
-    async fn handle_update_peer_info(&mut self, old_peer_id: String, new_peer_info: PeerInfo) -> P2PResult<()> {
-        // Check if this is an update from "Unknown" to real info
-        let is_initial_handshake = if let Some(old_info) = self.peer_info_map.get(&old_peer_id) {
-            old_info.name == "Unknown"
-        } else {
-            false
-        };
-        
-        // Remove old entry and add new one with correct info
-        if let Some(connection_sender) = self.connections.remove(&old_peer_id) {
-            self.connections.insert(new_peer_info.id.clone(), connection_sender);
-        }
-        
-        // Update peer info
-        self.peer_info_map.remove(&old_peer_id);
-        self.peer_info_map.insert(new_peer_info.id.clone(), new_peer_info.clone());
-        
-        // Only emit PeerConnected event if this is the initial handshake (Unknown -> Real name)
-        if is_initial_handshake {
-            let _ = self.event_sender.send(P2PEvent::PeerConnected(new_peer_info));
+            match content {
+                // The peer is going away
+                Some(message_content::Content::Close(_)) => break,
+                // Who is on the other end was settled by the first ones
+                Some(message_content::Content::Handshake(_) | message_content::Content::HandshakeProof(_)) => continue,
+                _ => {}
+            }
+
+            // Let the actor decide whether to deliver or relay it
+            let _ = command_sender.send(PeerCommand::IncomingMessage {
+                from_peer_id: current_peer_id.clone(),
+                message,
+            });
         }
         
-        Ok(())
-    }
-}
\ No newline at end of file
+        // Closed, cleanly or not; the actor reports it with the peer's real details
+        let _ = command_sender.send(PeerCommand::PeerClosed {
+            peer_id: current_peer_id,
+            connection: local.outgoing,
+        });
+    })
+}
+
+// Hands the handshaken peer to the actor; returns the id the connection goes by now
+fn accept_handshake(
+    command_sender: &mpsc::UnboundedSender<PeerCommand>,
+    old_peer_id: String,
+    remote: PeerInfo,
+) -> String {
+    let peer_id = remote.id.clone();
+    let (tx, _) = tokio::sync::oneshot::channel();
+    let _ = command_sender.send(PeerCommand::UpdatePeerInfo {
+        old_peer_id,
+        new_peer_info: remote,
+        respond_to: tx,
+    });
+    peer_id
+}
@@ -0,0 +1,58 @@
+use crate::PeerInfo;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+
+/// Text form of a peer's IP as stored in `PeerInfo::ip`.
+///
+/// IPv4-mapped IPv6 addresses (as seen on a dual-stack listener) become plain
+/// IPv4, and link-local IPv6 addresses keep their interface as `%scope_id`,
+/// without which they can't be dialled back.
+pub fn ip_to_string(addr: &SocketAddr) -> String {
+    match addr {
+        SocketAddr::V4(v4) => v4.ip().to_string(),
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(v4) => v4.to_string(),
+            None if v6.scope_id() != 0 && v6.ip().is_unicast_link_local() => {
+                format!("{}%{}", v6.ip(), v6.scope_id())
+            }
+            None => v6.ip().to_string(),
+        },
+    }
+}
+
+/// Parse an IP produced by [`ip_to_string`] back into a dialable address
+pub fn parse_socket_addr(ip: &str, port: u16) -> io::Result<SocketAddr> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid peer IP '{}'", ip));
+
+    match ip.split_once('%') {
+        Some((address, scope)) => {
+            let address: Ipv6Addr = address.parse().map_err(|_| invalid())?;
+            let scope_id = scope.parse().map_err(|_| invalid())?;
+            Ok(SocketAddr::V6(SocketAddrV6::new(address, port, 0, scope_id)))
+        }
+        None => {
+            let address: IpAddr = ip.parse().map_err(|_| invalid())?;
+            Ok(SocketAddr::new(address, port))
+        }
+    }
+}
+
+/// How useful an address is for reaching a peer from elsewhere on the LAN.
+///
+/// Higher is better: IPv4, then global IPv6, then link-local IPv6, then loopback.
+pub fn preference(ip: &str) -> u8 {
+    match parse_socket_addr(ip, 0).map(|addr| addr.ip()) {
+        Ok(ip) if ip.is_loopback() => 0,
+        Ok(IpAddr::V4(_)) => 3,
+        Ok(IpAddr::V6(v6)) if v6.is_unicast_link_local() => 1,
+        Ok(IpAddr::V6(_)) => 2,
+        Err(_) => 0,
+    }
+}
+
+impl PeerInfo {
+    /// The peer's TCP address, with the IPv6 scope restored where needed
+    pub fn socket_addr(&self) -> io::Result<SocketAddr> {
+        parse_socket_addr(&self.ip, self.port as u16)
+    }
+}
@@ -0,0 +1,72 @@
+use prost::{DecodeError, Message, Name};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+type Decoder = Arc<dyn Fn(&[u8]) -> Result<Arc<dyn Any + Send + Sync>, DecodeError> + Send + Sync>;
+
+/// Application payload decoded by a registered type
+#[derive(Clone)]
+pub struct CustomPayload {
+    type_url: String,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl CustomPayload {
+    pub fn type_url(&self) -> &str {
+        &self.type_url
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.value.is::<T>()
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref::<T>()
+    }
+}
+
+impl fmt::Debug for CustomPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomPayload")
+            .field("type_url", &self.type_url)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Type URL -> decoder table shared between the messenger and the peer actor
+#[derive(Clone, Default)]
+pub struct CustomRegistry {
+    decoders: Arc<RwLock<HashMap<String, Decoder>>>,
+}
+
+impl CustomRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<T>(&self)
+    where
+        T: Message + Name + Default + Send + Sync + 'static,
+    {
+        let decoder: Decoder = Arc::new(|bytes| {
+            let value = T::decode(bytes)?;
+            Ok(Arc::new(value) as Arc<dyn Any + Send + Sync>)
+        });
+        self.decoders.write().unwrap().insert(T::type_url(), decoder);
+    }
+
+    pub fn unregister<T: Name>(&self) -> bool {
+        self.decoders.write().unwrap().remove(&T::type_url()).is_some()
+    }
+
+    /// Decode `any` with its registered type. Returns `None` for unknown types.
+    pub fn decode(&self, any: &prost_types::Any) -> Option<Result<CustomPayload, DecodeError>> {
+        let decoder = self.decoders.read().unwrap().get(&any.type_url).cloned()?;
+        Some(decoder(&any.value).map(|value| CustomPayload {
+            type_url: any.type_url.clone(),
+            value,
+        }))
+    }
+}
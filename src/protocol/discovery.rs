@@ -1,3 +1,30 @@
 pub const DISCOVERY_PORT: u16 = 6968;
 pub const BROADCAST_ADDR: &str = "255.255.255.255";
-pub const MULTICAST_ADDR: &str = "224.0.0.251";
\ No newline at end of file
+pub const MULTICAST_ADDR: &str = "224.0.0.251";
+// Link-local scope, so it has to be joined and sent per interface
+pub const MULTICAST_ADDR_V6: &str = "ff02::6968";
+// Peers not heard from for this long are dropped and reported as lost
+pub const PEER_TIMEOUT_SECS: u64 = 60;
+// How often the expiry timer looks for stale peers
+pub const PEER_EXPIRY_INTERVAL_SECS: u64 = 5;
+// How often local interfaces are checked for changes
+pub const INTERFACE_POLL_INTERVAL_SECS: u64 = 3;
+// Large enough for any UDP datagram
+pub const DISCOVERY_BUFFER_SIZE: usize = 65_536;
+// Upper bound for the random delay before answering a PeerRequest
+pub const REQUEST_RESPONSE_JITTER_MS: u64 = 250;
+// Copies of one PeerRequest arriving within this window get a single reply
+pub const REQUEST_REPLY_WINDOW_MS: u64 = 500;
+// Default announcement schedule: fast rounds after start or a network change,
+// backing off to the maximum, spread out further on networks with many peers
+pub const ANNOUNCE_MIN_INTERVAL_MS: u64 = 1000;
+pub const ANNOUNCE_MAX_INTERVAL_SECS: u64 = 20;
+pub const STARTUP_ANNOUNCEMENTS: u32 = 3;
+// Announcements per second the whole LAN should stay near
+pub const ANNOUNCE_BUDGET_PER_SEC: u32 = 20;
+// Once this many other peers announced within an interval the group is busy,
+// and a round that closely follows our last one is skipped
+pub const ANNOUNCE_REDUNDANCY: usize = 3;
+
+// DNS-SD service type advertised by the mDNS discovery backend
+pub const MDNS_SERVICE_TYPE: &str = "_archsockrust._tcp.local.";
@@ -2,4 +2,28 @@
 pub use crate::{
     P2pMessage as Message, MessageContent, TextMessage, FileMessage, 
     FileRequest, FileResponse, PeerInfo,
-};
\ No newline at end of file
+};
+
+/// Default number of relay hops a message may travel across the mesh
+pub const DEFAULT_HOP_LIMIT: u32 = 4;
+
+/// How many recently seen message IDs are remembered to drop relay duplicates
+pub const SEEN_MESSAGE_CACHE_SIZE: usize = 1024;
+
+/// How long connect-by-address waits for the remote handshake
+pub const HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+
+/// How long resolving a peer's address, and then dialing it, may each take
+pub const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// How long `stop` waits for queued messages to reach peers before closing
+pub const SHUTDOWN_FLUSH_TIMEOUT_MS: u64 = 2000;
+
+/// Events buffered per subscriber; one that falls further behind misses the oldest
+pub const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// Port the TCP listener uses unless configured otherwise
+pub const DEFAULT_TCP_PORT: u16 = 6969;
+
+/// Where received files are saved unless configured otherwise
+pub const DOWNLOAD_DIR: &str = "recibidos";
@@ -1,3 +1,5 @@
+pub mod address;
+pub mod custom;
 pub mod discovery;
 pub mod message;
 
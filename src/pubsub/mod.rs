@@ -0,0 +1,30 @@
+use crate::{P2pMessage, PublishMessage, SubscriptionMessage};
+use tokio::sync::mpsc;
+
+/// Publication received on a subscribed topic
+#[derive(Debug, Clone)]
+pub struct TopicMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub sender_id: String,
+    pub sender_name: String,
+    pub timestamp: u64,
+}
+
+impl TopicMessage {
+    pub(crate) fn from_publish(message: &P2pMessage, publish: PublishMessage) -> Self {
+        Self {
+            topic: publish.topic,
+            payload: publish.payload,
+            sender_id: message.sender_id.clone(),
+            sender_name: message.sender_name.clone(),
+            timestamp: message.timestamp,
+        }
+    }
+}
+
+pub type TopicReceiver = mpsc::UnboundedReceiver<TopicMessage>;
+
+pub(crate) fn subscription(topics: Vec<String>, subscribe: bool) -> SubscriptionMessage {
+    SubscriptionMessage { topics, subscribe }
+}
@@ -0,0 +1,80 @@
+use crate::{RpcMessage, rpc_message};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+/// Incoming call handed to a registered handler
+#[derive(Debug, Clone)]
+pub struct RpcRequest {
+    pub peer_id: String,
+    pub peer_name: String,
+    pub method: String,
+    pub payload: Vec<u8>,
+}
+
+pub type RpcFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, String>> + Send>>;
+pub type RpcHandler = Arc<dyn Fn(RpcRequest) -> RpcFuture + Send + Sync>;
+
+/// Method name -> handler table shared between the messenger and the peer actor
+#[derive(Clone, Default)]
+pub struct RpcRegistry {
+    handlers: Arc<RwLock<HashMap<String, RpcHandler>>>,
+}
+
+impl RpcRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<F, Fut>(&self, method: &str, handler: F)
+    where
+        F: Fn(RpcRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<u8>, String>> + Send + 'static,
+    {
+        let handler: RpcHandler = Arc::new(move |request| Box::pin(handler(request)));
+        self.handlers.write().unwrap().insert(method.to_string(), handler);
+    }
+
+    pub fn unregister(&self, method: &str) -> bool {
+        self.handlers.write().unwrap().remove(method).is_some()
+    }
+
+    pub fn get(&self, method: &str) -> Option<RpcHandler> {
+        self.handlers.read().unwrap().get(method).cloned()
+    }
+
+    pub fn methods(&self) -> Vec<String> {
+        self.handlers.read().unwrap().keys().cloned().collect()
+    }
+}
+
+pub(crate) fn rpc_request(correlation_id: String, method: String, payload: Vec<u8>) -> RpcMessage {
+    RpcMessage {
+        correlation_id,
+        method,
+        payload,
+        kind: rpc_message::Kind::Request as i32,
+        error: String::new(),
+    }
+}
+
+// Builds the reply for a finished handler
+pub(crate) fn rpc_reply(correlation_id: String, method: String, result: Result<Vec<u8>, String>) -> RpcMessage {
+    match result {
+        Ok(payload) => RpcMessage {
+            correlation_id,
+            method,
+            payload,
+            kind: rpc_message::Kind::Response as i32,
+            error: String::new(),
+        },
+        Err(error) => RpcMessage {
+            correlation_id,
+            method,
+            payload: Vec::new(),
+            kind: rpc_message::Kind::Error as i32,
+            error,
+        },
+    }
+}
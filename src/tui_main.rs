@@ -1,5 +1,5 @@
 use archsockrust::app::{AppState, AppEventHandler, ChatMessage, MessageType, PeerStatus};
-use archsockrust::{P2PMessenger, format_timestamp};
+use archsockrust::{ConfigFile, P2PMessenger, format_timestamp};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
     execute,
@@ -128,22 +128,21 @@ impl TuiState {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Parse CLI args
-    let args: Vec<String> = env::args().collect();
-    let (name, tcp_port, discovery_port) = if args.len() > 1 {
-        let name = args[1].clone();
-        let tcp_port = args.get(2).and_then(|p| p.parse().ok()).unwrap_or(6969);
-        let discovery_port = args.get(3).and_then(|p| p.parse().ok()).unwrap_or(6968);
-        (name, tcp_port, discovery_port)
-    } else {
-        ("TUI User".to_string(), 6969, 6968)
-    };
+    // Config file and ARCHSOCKRUST_* variables, then CLI args:
+    // [--config <path>] [name] [tcp_port] [discovery_port]
+    let args: Vec<String> = env::args().skip(1).collect();
+    let settings = ConfigFile::from_args(&args)?;
+    // Printed before the screen is taken over
+    for warning in &settings.warnings {
+        eprintln!("⚠️  {}", warning);
+    }
+    let config = settings.into_builder("TUI User")?.build()?;
 
     // Create messenger
-    let mut messenger = P2PMessenger::with_ports(name, tcp_port, discovery_port)?;
+    let messenger = P2PMessenger::with_config(config)?;
+    let mut event_receiver = messenger.subscribe_events();
     messenger.start().await?;
 
-    let mut event_receiver = messenger.get_event_receiver().unwrap();
     let app_state = Arc::new(Mutex::new(AppState::new(messenger)));
     
     // Setup terminal
@@ -164,13 +163,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
-    // Auto-discovery task
+    // Expiry task; announcements follow the discovery schedule on their own
     let app_state_for_discovery = app_state.clone();
     tokio::spawn(async move {
         loop {
             {
                 let app_state = app_state_for_discovery.lock().await;
-                let _ = app_state.messenger.discover_peers();
                 app_state.messenger.cleanup_stale_peers();
             }
             sleep(Duration::from_secs(5)).await;
@@ -293,11 +291,22 @@ fn draw_peers_panel(f: &mut Frame, area: Rect, tui_state: &TuiState) {
 
         for peer in &app_state.discovered_peers {
             let status = if peer.is_connected { " [CONNECTED]" } else { "" };
-            items.push(ListItem::new(Line::from(vec![
+            let mut lines = vec![Line::from(vec![
                 Span::raw("  "),
                 Span::styled(&peer.name, Style::default().fg(Color::Cyan)),
                 Span::raw(format!(" ({}:{}){}", peer.ip, peer.port, status)),
-            ])));
+            ])];
+
+            // Announced details on a second line of the same item, so
+            // selection indexes still map one item per peer
+            let details = peer.details();
+            if !details.is_empty() {
+                lines.push(Line::from(Span::styled(
+                    format!("    {}", details),
+                    Style::default().fg(Color::DarkGray),
+                )));
+            }
+            items.push(ListItem::new(lines));
         }
     }
 
@@ -360,10 +369,17 @@ fn draw_status_panel(f: &mut Frame, area: Rect, tui_state: &TuiState) {
     }
     let app_state = app_state_lock.unwrap();
 
+    // The bound port, which differs from the configured one when that was 0
+    let tcp_port = app_state
+        .messenger
+        .listen_addresses()
+        .map(|addresses| addresses.tcp.port().to_string())
+        .unwrap_or_else(|| "-".to_string());
     let status_text = format!(
-        "📡 {} | ID: {:.8}... | 🔍{} 🔗{}",
+        "📡 {} | ID: {:.8}... | TCP :{} | 🔍{} 🔗{}",
         app_state.messenger.peer_name(),
         app_state.messenger.peer_id(),
+        tcp_port,
         app_state.discovered_peers.len(),
         app_state.connected_peers.len()
     );
@@ -458,6 +474,7 @@ fn draw_help_popup(f: &mut Frame, area: Rect) {
         Line::from("  Tab / Shift+Tab  - Switch between panels"),
         Line::from("  ↑/↓ (in peers)   - Select peer"),
         Line::from("  Enter (in input) - Send message"),
+        Line::from("  /all <text>      - Broadcast to all connected peers"),
         Line::from(""),
         Line::from(Span::styled("Actions:", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))),
         Line::from("  c - Connect to selected peer"),
@@ -470,7 +487,7 @@ fn draw_help_popup(f: &mut Frame, area: Rect) {
         Line::from("  q - Quit application"),
         Line::from(""),
         Line::from(Span::styled("Features:", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))),
-        Line::from("  • Auto-discovery every 5 seconds"),
+        Line::from("  • Adaptive auto-discovery (fast at startup, up to every 20 seconds)"),
         Line::from("  • Real-time peer connections"),
         Line::from("  • Text and file messaging"),
         Line::from("  • Handshake-based peer identification"),
@@ -607,6 +624,16 @@ async fn send_message(tui_state: &mut TuiState) {
     let message = tui_state.input_buffer.clone();
     tui_state.input_buffer.clear();
 
+    // "/all <text>" broadcasts to every connected peer
+    if let Some(text) = message.strip_prefix("/all ") {
+        let mut app_state = tui_state.app_state.lock().await;
+        match app_state.broadcast_text_message(text.to_string()).await {
+            Ok(msg) => tui_state.status_message = msg,
+            Err(e) => tui_state.status_message = e,
+        }
+        return;
+    }
+
     let selected = tui_state.peer_list_state.selected();
     if let Some(visual_index) = selected {
         let mut app_state = tui_state.app_state.lock().await;
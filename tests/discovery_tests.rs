@@ -158,10 +158,12 @@ async fn test_discovery_message_serialization() {
         peer_name: "TestPeer🚀".to_string(), // Include Unicode to test encoding
         peer_id: "test-peer-id-123".to_string(),
         tcp_port: 6969,
+        ..Default::default()
     };
     
     let discovery_msg = DiscoveryMessage {
         message: Some(discovery_message::Message::Announce(announcement.clone())),
+        ..Default::default()
     };
     
     // Serialize
@@ -191,7 +193,10 @@ async fn test_discovery_message_serialization() {
     
     // Test PeerRequest
     let request_msg = DiscoveryMessage {
-        message: Some(discovery_message::Message::Request(PeerRequest {})),
+        message: Some(discovery_message::Message::Request(PeerRequest {
+            peer_id: "test-peer-id-123".to_string(),
+        })),
+        ..Default::default()
     };
     
     let mut request_buffer = Vec::new();
@@ -208,6 +213,41 @@ async fn test_discovery_message_serialization() {
     }
 }
 
+#[tokio::test]
+async fn test_peer_request_gets_direct_reply() {
+    // The requester joins after the responder's startup announcement, so it
+    // can only learn about the responder by probing
+    
+    let responder = archsockrust::discovery::DiscoveryService::new(
+        "Responder".to_string(),
+        8550,
+        8554,
+    ).unwrap();
+    let requester = archsockrust::discovery::DiscoveryService::new(
+        "Requester".to_string(),
+        8552,
+        8554,
+    ).unwrap();
+    
+    assert!(responder.start().await.is_ok(), "Failed to start responder");
+    sleep(Duration::from_millis(300)).await;
+    assert!(requester.start().await.is_ok(), "Failed to start requester");
+    
+    let peers = requester
+        .discover_peers_within(Duration::from_secs(1))
+        .await
+        .expect("Discovery probe should be sent");
+    
+    let found = peers.iter().find(|peer| peer.id == responder.peer_id);
+    assert!(found.is_some(), "Responder should answer the probe within the window");
+    assert_eq!(found.unwrap().port, 8550);
+    
+    println!("✅ Requester discovered {} peer(s) from direct replies", peers.len());
+    
+    responder.stop();
+    requester.stop();
+}
+
 #[tokio::test]
 async fn test_event_system_integration() {
     // Test that the event system properly connects discovery to the event manager
@@ -219,7 +259,8 @@ async fn test_event_system_integration() {
     ).unwrap();
     
     // Create event channel
-    let (event_sender, mut event_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let bus = archsockrust::events::EventManager::new();
+    let (event_sender, mut event_receiver) = (bus.get_sender(), bus.subscribe());
     
     // Connect discovery to event system
     discovery.set_event_sender(event_sender);
@@ -260,6 +301,7 @@ async fn test_peer_info_structure() {
         ip: "192.168.1.100".to_string(),
         port: 6969,
         last_seen: archsockrust::get_current_timestamp(),
+        ..Default::default()
     };
     
     assert_eq!(peer.id, "test-peer-123");
@@ -278,4 +320,961 @@ async fn test_peer_info_structure() {
     println!("   Name: {}", peer.name);
     println!("   Address: {}:{}", peer.ip, peer.port);
     println!("   Last seen: {} ({})", peer.last_seen, formatted_time);
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_mdns_discovery_finds_peer() {
+    // Two DNS-SD advertisers on the same host browse for each other
+    
+    use archsockrust::discovery::mdns::MdnsDiscovery;
+    use archsockrust::discovery::Discovery;
+    
+    let alice = MdnsDiscovery::new("mdns-alice-id".to_string(), "MdnsAlice".to_string(), 8560).unwrap();
+    let bob = MdnsDiscovery::new("mdns-bob-id".to_string(), "MdnsBob".to_string(), 8562).unwrap();
+    
+    assert!(alice.start().await.is_ok(), "Failed to start Alice's mDNS discovery");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob's mDNS discovery");
+    
+    let found = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(peer) = alice.get_peers().into_iter().find(|peer| peer.id == "mdns-bob-id") {
+                return peer;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }).await;
+    
+    let peer = found.expect("Alice should resolve Bob's service");
+    assert_eq!(peer.name, "MdnsBob");
+    assert_eq!(peer.port, 8562);
+    assert!(
+        alice.get_peers().iter().all(|peer| peer.id != "mdns-alice-id"),
+        "Own advertisement should be ignored"
+    );
+    
+    // A peer that keeps advertising outlives the timeout, and answers the
+    // reconfirmation that follows it
+    for _ in 0..12 {
+        sleep(Duration::from_secs(1)).await;
+        Discovery::cleanup_stale_peers(&alice, 1);
+    }
+    assert!(
+        alice.get_peers().iter().any(|peer| peer.id == "mdns-bob-id"),
+        "Advertising peer should not expire"
+    );
+    
+    println!("✅ Resolved {} at {}:{} via mDNS", peer.name, peer.ip, peer.port);
+    
+    alice.stop();
+    bob.stop();
+}
+
+#[test]
+fn test_static_peer_list_parsing() {
+    use archsockrust::discovery::static_list::StaticDiscovery;
+    
+    let contents = "\
+# Office machines
+192.168.1.10:6969 Reception PC
+10.0.0.5:7000   # no name given
+
+";
+    let peers = StaticDiscovery::parse(contents).expect("Peer list should parse");
+    
+    assert_eq!(peers.len(), 2);
+    assert_eq!(peers[0].id, "static:192.168.1.10:6969");
+    assert_eq!(peers[0].name, "Reception PC");
+    assert_eq!(peers[0].ip, "192.168.1.10");
+    assert_eq!(peers[0].port, 6969);
+    assert_eq!(peers[1].name, "10.0.0.5:7000");
+    assert_eq!(peers[1].port, 7000);
+    
+    assert!(StaticDiscovery::parse("not-an-address").is_err(), "Entries without a port should be rejected");
+}
+
+#[tokio::test]
+async fn test_messenger_merges_discovery_backends() {
+    // Two in-memory backends report an overlapping peer
+    
+    use archsockrust::discovery::memory::MemoryDiscovery;
+    use archsockrust::discovery::DiscoveryBackend;
+    
+    let mut messenger = P2PMessenger::with_discovery_backend(
+        "MergeTest".to_string(),
+        8570,
+        8571,
+        DiscoveryBackend::None,
+    ).unwrap();
+    let mut events = messenger.subscribe_events();
+    
+    let first = MemoryDiscovery::new();
+    let second = MemoryDiscovery::new();
+    messenger.add_discovery_backend(first.clone());
+    messenger.add_discovery_backend(second.clone());
+    
+    let peer = |id: &str, name: &str, last_seen: u64| PeerInfo {
+        id: id.to_string(),
+        name: name.to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 9000,
+        last_seen,
+        ..Default::default()
+    };
+    let now = get_current_timestamp();
+    first.add_peer(peer("shared", "Stale", now - 10));
+    second.add_peer(peer("shared", "Fresh", now));
+    
+    assert!(messenger.start().await.is_ok(), "Failed to start messenger");
+    
+    second.add_peer(peer("only-second", "Second", now));
+    
+    let mut discovered = Vec::new();
+    while let Ok(Some(event)) = timeout(Duration::from_millis(200), events.recv()).await {
+        if let P2PEvent::PeerDiscovered(peer) = event {
+            discovered.push(peer.id);
+        }
+    }
+    assert!(discovered.contains(&"shared".to_string()));
+    assert!(discovered.contains(&"only-second".to_string()));
+    
+    let peers = messenger.get_discovered_peers();
+    assert_eq!(peers.len(), 2, "Duplicate peers should be merged by id");
+    let shared = peers.iter().find(|peer| peer.id == "shared").unwrap();
+    assert_eq!(shared.name, "Fresh", "The most recently seen entry should win");
+    
+    println!("✅ Merged {} peers from two backends", peers.len());
+    
+    messenger.stop().await;
+}
+
+#[test]
+fn test_backends_added_later_get_the_interface_filter() {
+    use archsockrust::discovery::memory::MemoryDiscovery;
+    use archsockrust::discovery::{DiscoveryBackend, InterfaceFilter};
+    use std::net::IpAddr;
+
+    let loopback = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let config = P2PConfig::builder("FilterTest")
+        .discovery_backend(DiscoveryBackend::None)
+        .discovery_bind_address(loopback)
+        .interface_filter(InterfaceFilter::new().exclude("docker*").unwrap())
+        .build()
+        .unwrap();
+    let mut messenger = P2PMessenger::with_config(config).unwrap();
+
+    let backend = MemoryDiscovery::new();
+    messenger.add_discovery_backend(backend.clone());
+    assert_eq!(
+        backend.interface_filter(),
+        InterfaceFilter::new().exclude("docker*").unwrap().with_address(loopback),
+        "A late backend should get the configured filter, narrowed to the bind address"
+    );
+
+    // Later changes reach it too, still narrowed
+    messenger.set_interface_filter(InterfaceFilter::new().include("lo").unwrap());
+    assert_eq!(backend.interface_filter(), InterfaceFilter::new().include("lo").unwrap().with_address(loopback));
+
+    let late = MemoryDiscovery::new();
+    messenger.add_discovery_backend(late.clone());
+    assert_eq!(late.interface_filter(), backend.interface_filter());
+}
+
+#[tokio::test]
+async fn test_ipv6_announcement_keeps_scope() {
+    use archsockrust::discovery::DiscoveryService;
+    use prost::Message;
+    use std::net::{SocketAddrV6, UdpSocket};
+
+    let listener = DiscoveryService::new("V6Listener".to_string(), 8590, 8594).unwrap();
+    if !listener.has_ipv6() {
+        println!("ℹ️ No IPv6 on this host, skipping");
+        return;
+    }
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+
+    // Announce only over IPv6, as a peer on an IPv6-only network would
+    let announcement = DiscoveryMessage {
+        message: Some(discovery_message::Message::Announce(PeerAnnouncement {
+            peer_name: "V6Peer".to_string(),
+            peer_id: "v6-peer".to_string(),
+            tcp_port: 8592,
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    let buf = announcement.encode_to_vec();
+    let sender = UdpSocket::bind("[::]:0").unwrap();
+    for index in DiscoveryService::get_multicast_interfaces_v6() {
+        let target = SocketAddrV6::new(listener.multicast_group_v6(), 8594, 0, index);
+        let _ = sender.send_to(&buf, target);
+    }
+
+    sleep(Duration::from_millis(500)).await;
+
+    let peer = listener.get_peers().into_iter().find(|peer| peer.id == "v6-peer");
+    let peer = peer.expect("IPv6 announcement should be discovered");
+    let addr = peer.socket_addr().expect("Discovered address should be dialable");
+    assert!(addr.is_ipv6(), "Peer should be reached over IPv6, got {}", peer.ip);
+    if peer.ip.starts_with("fe80") {
+        assert!(peer.ip.contains('%'), "Link-local address must keep its scope: {}", peer.ip);
+    }
+    assert_eq!(addr.port(), 8592);
+
+    println!("✅ IPv6 peer discovered at {}", addr);
+
+    listener.stop();
+}
+
+#[tokio::test]
+async fn test_discovery_receives_bursts_and_large_datagrams() {
+    use archsockrust::discovery::DiscoveryService;
+    use prost::Message;
+    use std::net::UdpSocket;
+
+    let listener = DiscoveryService::new("BurstListener".to_string(), 8610, 8614).unwrap();
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+
+    let announce = |id: String, name: String| DiscoveryMessage {
+        message: Some(discovery_message::Message::Announce(PeerAnnouncement {
+            peer_name: name,
+            peer_id: id,
+            tcp_port: 8612,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec();
+
+    // Fire a burst in one go and one announcement bigger than the old 1 KiB buffer
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for i in 0..50 {
+        sender.send_to(&announce(format!("burst-{}", i), format!("Burst {}", i)), "127.0.0.1:8614").unwrap();
+    }
+    let long_name = "L".repeat(4000);
+    sender.send_to(&announce("large".to_string(), long_name.clone()), "127.0.0.1:8614").unwrap();
+
+    sleep(Duration::from_millis(300)).await;
+
+    let peers = listener.get_peers();
+    let burst = peers.iter().filter(|peer| peer.id.starts_with("burst-")).count();
+    assert_eq!(burst, 50, "Every announcement in the burst should be handled");
+    let large = peers.iter().find(|peer| peer.id == "large").expect("Large announcement should be handled");
+    assert_eq!(large.name, long_name);
+
+    // Stopping ends the receive loops straight away
+    listener.stop();
+    sleep(Duration::from_millis(50)).await;
+    sender.send_to(&announce("late".to_string(), "Late".to_string()), "127.0.0.1:8614").unwrap();
+    sleep(Duration::from_millis(300)).await;
+    assert!(
+        listener.get_peers().iter().all(|peer| peer.id != "late"),
+        "Stopped service should not handle new announcements"
+    );
+
+    println!("✅ Burst of {} announcements and a {}-byte name were received", burst, long_name.len());
+}
+
+// Next PeerDiscovered/PeerUpdated/PeerLost event about `peer_id`
+async fn next_peer_event(
+    events: &mut EventSubscription,
+    peer_id: &str,
+) -> Option<P2PEvent> {
+    while let Some(event) = events.recv().await {
+        match &event {
+            P2PEvent::PeerDiscovered(peer) | P2PEvent::PeerUpdated(peer) | P2PEvent::PeerLost(peer)
+                if peer.id == peer_id =>
+            {
+                return Some(event);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[tokio::test]
+async fn test_goodbye_reports_peer_lost() {
+    use archsockrust::discovery::DiscoveryService;
+
+    let mut listener = DiscoveryService::new("ByeListener".to_string(), 8620, 8624).unwrap();
+    let announcer = DiscoveryService::new("ByeAnnouncer".to_string(), 8622, 8624).unwrap();
+    let bus = archsockrust::events::EventManager::new();
+    let (sender, mut events) = (bus.get_sender(), bus.subscribe());
+    listener.set_event_sender(sender);
+
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+    assert!(announcer.start().await.is_ok(), "Failed to start announcer");
+
+    let discovered = timeout(Duration::from_secs(2), next_peer_event(&mut events, &announcer.peer_id)).await;
+    assert!(
+        matches!(discovered, Ok(Some(P2PEvent::PeerDiscovered(_)))),
+        "Announcer should be discovered"
+    );
+
+    // A clean shutdown is noticed straight away, not after the expiry timeout.
+    // Skip updates from the announcer's other interfaces arriving meanwhile.
+    announcer.stop();
+    let lost = timeout(Duration::from_secs(1), async {
+        loop {
+            match next_peer_event(&mut events, &announcer.peer_id).await {
+                Some(P2PEvent::PeerUpdated(_)) => continue,
+                other => return other,
+            }
+        }
+    })
+    .await;
+    assert!(matches!(lost, Ok(Some(P2PEvent::PeerLost(_)))), "Goodbye should report the peer as lost, got {:?}", lost);
+    assert!(listener.get_peers().iter().all(|peer| peer.id != announcer.peer_id));
+
+    println!("✅ Goodbye removed the peer immediately");
+
+    listener.stop();
+}
+
+#[tokio::test]
+async fn test_services_sharing_a_port_all_receive_announcements() {
+    use archsockrust::discovery::DiscoveryService;
+
+    // Two local instances bind the same discovery port through SO_REUSEPORT;
+    // multicast delivers every announcement to both of them.
+    let first = DiscoveryService::new("SharedFirst".to_string(), 8820, 8824).unwrap();
+    let second = DiscoveryService::new("SharedSecond".to_string(), 8821, 8824).unwrap();
+    let announcer = DiscoveryService::new("SharedAnnouncer".to_string(), 8822, 8824).unwrap();
+
+    assert!(first.start().await.is_ok(), "First service should bind the shared port");
+    assert!(second.start().await.is_ok(), "Second service should bind the shared port");
+    assert!(announcer.start().await.is_ok(), "Failed to start announcer");
+
+    let knows_announcer = |svc: &DiscoveryService| svc.get_peers().iter().any(|peer| peer.id == announcer.peer_id);
+    let both_heard = timeout(Duration::from_secs(3), async {
+        while !(knows_announcer(&first) && knows_announcer(&second)) {
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(both_heard.is_ok(), "Both services on the shared port should record the announcer");
+
+    println!("✅ Services sharing a discovery port all received the announcement");
+
+    announcer.stop();
+    second.stop();
+    first.stop();
+}
+
+#[tokio::test]
+async fn test_peer_changes_report_updated_and_expiry_reports_lost() {
+    use archsockrust::discovery::DiscoveryService;
+    use prost::Message;
+    use std::net::UdpSocket;
+
+    let mut listener = DiscoveryService::new("ChangeListener".to_string(), 8630, 8634).unwrap();
+    let bus = archsockrust::events::EventManager::new();
+    let (sender, mut events) = (bus.get_sender(), bus.subscribe());
+    listener.set_event_sender(sender);
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+
+    let announce = |name: &str, tcp_port: u32| DiscoveryMessage {
+        message: Some(discovery_message::Message::Announce(PeerAnnouncement {
+            peer_name: name.to_string(),
+            peer_id: "changing-peer".to_string(),
+            tcp_port,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    socket.send_to(&announce("Before", 8632), "127.0.0.1:8634").unwrap();
+    let event = timeout(Duration::from_secs(1), next_peer_event(&mut events, "changing-peer")).await;
+    assert!(matches!(event, Ok(Some(P2PEvent::PeerDiscovered(_)))));
+
+    socket.send_to(&announce("After", 8636), "127.0.0.1:8634").unwrap();
+    let event = timeout(Duration::from_secs(1), next_peer_event(&mut events, "changing-peer")).await;
+    match event {
+        Ok(Some(P2PEvent::PeerUpdated(peer))) => {
+            assert_eq!(peer.name, "After");
+            assert_eq!(peer.port, 8636);
+        }
+        other => panic!("Rename should report PeerUpdated, got {:?}", other),
+    }
+
+    // A repeat announcement with nothing changed is not an update
+    socket.send_to(&announce("After", 8636), "127.0.0.1:8634").unwrap();
+    let repeat = timeout(Duration::from_millis(300), next_peer_event(&mut events, "changing-peer")).await;
+    assert!(repeat.is_err(), "Unchanged announcement should not emit an event");
+
+    listener.cleanup_stale_peers(0);
+    let lost = timeout(Duration::from_secs(1), next_peer_event(&mut events, "changing-peer")).await;
+    assert!(matches!(lost, Ok(Some(P2PEvent::PeerLost(_)))), "Expiry should report PeerLost");
+
+    println!("✅ Changes reported as PeerUpdated and expiry as PeerLost");
+
+    listener.stop();
+}
+
+#[tokio::test]
+async fn test_peer_moving_to_a_new_address_is_followed() {
+    use archsockrust::discovery::{AnnounceSchedule, DiscoveryService};
+    use prost::Message;
+    use std::net::UdpSocket;
+
+    let mut listener = DiscoveryService::new("MoveListener".to_string(), 8840, 8844).unwrap();
+    listener.set_announce_schedule(
+        AnnounceSchedule::new(Duration::from_millis(50), Duration::from_millis(200)).unwrap(),
+    );
+    let bus = archsockrust::events::EventManager::new();
+    let (sender, mut events) = (bus.get_sender(), bus.subscribe());
+    listener.set_event_sender(sender);
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+
+    let announce = DiscoveryMessage {
+        message: Some(discovery_message::Message::Announce(PeerAnnouncement {
+            peer_name: "Mover".to_string(),
+            peer_id: "moving-peer".to_string(),
+            tcp_port: 8842,
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    let before = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let after = UdpSocket::bind("127.0.0.2:0").unwrap();
+
+    before.send_to(&announce, "127.0.0.1:8844").unwrap();
+    let event = timeout(Duration::from_secs(1), next_peer_event(&mut events, "moving-peer")).await;
+    assert!(matches!(event, Ok(Some(P2PEvent::PeerDiscovered(peer))) if peer.ip == "127.0.0.1"));
+
+    // Another copy of the round from an equally good address doesn't move it
+    after.send_to(&announce, "127.0.0.1:8844").unwrap();
+    let copy = timeout(Duration::from_millis(150), next_peer_event(&mut events, "moving-peer")).await;
+    assert!(copy.is_err(), "A second address within the round should not move the peer");
+
+    // Once the old address has gone quiet, e.g. after a DHCP renewal, the
+    // peer is followed to the new one
+    sleep(Duration::from_millis(500)).await;
+    after.send_to(&announce, "127.0.0.1:8844").unwrap();
+    let event = timeout(Duration::from_secs(1), next_peer_event(&mut events, "moving-peer")).await;
+    match event {
+        Ok(Some(P2PEvent::PeerUpdated(peer))) => assert_eq!(peer.ip, "127.0.0.2"),
+        other => panic!("Move should report PeerUpdated, got {:?}", other),
+    }
+
+    println!("✅ Peer followed to its new address");
+
+    listener.stop();
+}
+
+#[tokio::test]
+async fn test_announced_profile_reaches_peers() {
+    let alice = P2PMessenger::with_ports("ProfileAlice".to_string(), 8640, 8644)
+        .expect("Failed to create Alice messenger");
+    let bob = P2PMessenger::with_ports("ProfileBob".to_string(), 8642, 8644)
+        .expect("Failed to create Bob messenger");
+    let mut alice_events = alice.subscribe_events();
+
+    bob.set_metadata("team", "blue");
+    bob.set_accepts_files(false);
+    assert!(alice.start().await.is_ok(), "Failed to start Alice");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+
+    let discovered = timeout(Duration::from_secs(2), next_peer_event(&mut alice_events, bob.peer_id())).await;
+    assert!(matches!(discovered, Ok(Some(P2PEvent::PeerDiscovered(_)))), "Alice should discover Bob");
+
+    let peer = alice
+        .get_discovered_peers()
+        .into_iter()
+        .find(|peer| peer.id == bob.peer_id())
+        .expect("Bob should be listed");
+    let profile = bob.profile();
+    assert_eq!(peer.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(peer.os, std::env::consts::OS);
+    assert_eq!(peer.hostname, profile.hostname);
+    assert_eq!(peer.status, "online");
+    assert!(!peer.accepts_files);
+    assert_eq!(peer.metadata.get("team").map(String::as_str), Some("blue"));
+
+    // Runtime changes are announced straight away and reported as updates
+    bob.set_status("away");
+    let updated = timeout(Duration::from_secs(2), async {
+        loop {
+            match next_peer_event(&mut alice_events, bob.peer_id()).await {
+                Some(P2PEvent::PeerUpdated(peer)) if peer.status == "away" => return Some(peer),
+                Some(_) => continue,
+                None => return None,
+            }
+        }
+    })
+    .await;
+    let updated = updated.ok().flatten().expect("Status change should arrive before the next announce round");
+    assert_eq!(updated.metadata.get("team").map(String::as_str), Some("blue"));
+
+    println!("✅ Bob's profile reached Alice: {} on {}@{}", updated.status, updated.os, updated.hostname);
+
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_network_groups_isolate_discovery() {
+    use archsockrust::discovery::NetworkGroup;
+
+    // Everyone shares the discovery port; only the group and secret differ
+    let qa = || NetworkGroup::new("qa").with_secret(b"qa-secret");
+    let mut qa_one = P2PMessenger::with_ports("QaOne".to_string(), 8650, 8656).unwrap();
+    let mut qa_two = P2PMessenger::with_ports("QaTwo".to_string(), 8651, 8656).unwrap();
+    let dev = P2PMessenger::with_ports("Dev".to_string(), 8652, 8656).unwrap();
+    let mut impostor = P2PMessenger::with_ports("Impostor".to_string(), 8653, 8656).unwrap();
+
+    qa_one.set_network_group(qa());
+    qa_two.set_network_group(qa());
+    impostor.set_network_group(NetworkGroup::new("qa").with_secret(b"guess"));
+    assert_eq!(qa_one.network_group().name(), "qa");
+
+    for messenger in [&qa_one, &qa_two, &dev, &impostor] {
+        assert!(messenger.start().await.is_ok(), "Failed to start {}", messenger.peer_name());
+    }
+
+    let found = qa_one.discover_peers_within(Duration::from_millis(800)).await.unwrap();
+    let names: Vec<&str> = found
+        .iter()
+        .filter(|peer| peer.id != qa_one.peer_id())
+        .map(|peer| peer.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["QaTwo"], "QA should only see its own group");
+
+    let found = dev.discover_peers_within(Duration::from_millis(800)).await.unwrap();
+    assert!(found.iter().all(|peer| peer.name != "QaOne" && peer.name != "QaTwo"), "Dev should not see QA");
+    assert!(found.iter().all(|peer| peer.name != "Impostor"), "Dev should not see other groups");
+
+    println!("✅ Groups kept apart: QA saw {:?}", names);
+
+    for messenger in [&qa_one, &qa_two, &dev, &impostor] {
+        messenger.stop().await;
+    }
+}
+
+#[tokio::test]
+async fn test_spoofed_announcements_are_reported_not_trusted() {
+    use archsockrust::discovery::{DiscoveryService, PeerIdentity};
+    use prost::Message;
+    use std::net::UdpSocket;
+
+    let mut listener = DiscoveryService::new("TrustListener".to_string(), 8680, 8684).unwrap();
+    let victim = DiscoveryService::new("Victim".to_string(), 8682, 8684).unwrap();
+    let bus = archsockrust::events::EventManager::new();
+    let (sender, mut events) = (bus.get_sender(), bus.subscribe());
+    listener.set_event_sender(sender);
+
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+    assert!(victim.start().await.is_ok(), "Failed to start victim");
+
+    let discovered = timeout(Duration::from_secs(2), next_peer_event(&mut events, &victim.peer_id)).await;
+    assert!(matches!(discovered, Ok(Some(P2PEvent::PeerDiscovered(_)))), "Victim should be discovered");
+    let known = listener.get_peers().into_iter().find(|peer| peer.id == victim.peer_id).unwrap();
+    assert_eq!(known.public_key, victim.public_key(), "Victim's key should be recorded");
+
+    // An attacker claims the victim's ID, unsigned and under its own key
+    let attacker = PeerIdentity::generate();
+    let spoof = |signed: bool| {
+        let mut announce = PeerAnnouncement {
+            peer_name: "Victim".to_string(),
+            peer_id: victim.peer_id.clone(),
+            tcp_port: 8686,
+            timestamp: u64::MAX,
+            ..Default::default()
+        };
+        if signed {
+            attacker.sign_announcement(&mut announce);
+        }
+        DiscoveryMessage {
+            message: Some(discovery_message::Message::Announce(announce)),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    };
+    // Multicast, since the victim shares the listener's port and would take
+    // a share of unicast datagrams
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let group = (listener.multicast_group(), 8684);
+    socket.send_to(&spoof(false), group).unwrap();
+    socket.send_to(&spoof(true), group).unwrap();
+
+    let conflicts = timeout(Duration::from_secs(1), async {
+        let mut conflicts = Vec::new();
+        while let Some(event) = events.recv().await {
+            if let P2PEvent::PeerConflict(peer) = event {
+                conflicts.push(peer);
+                if conflicts.len() == 2 {
+                    break;
+                }
+            }
+        }
+        conflicts
+    })
+    .await
+    .expect("Both spoofed claims should be reported");
+    assert!(conflicts.iter().all(|peer| peer.id == victim.peer_id && peer.port == 8686));
+    assert!(conflicts.iter().any(|peer| peer.public_key == attacker.public_key()));
+
+    // Repeats are not reported again, but stay listed
+    socket.send_to(&spoof(true), group).unwrap();
+    sleep(Duration::from_millis(300)).await;
+    while let Some(event) = events.try_recv() {
+        assert!(!matches!(event, P2PEvent::PeerConflict(_)), "Repeated claim reported again");
+    }
+    assert_eq!(listener.conflicting_claims().len(), 2);
+
+    // An unsigned goodbye can't remove a peer with a pinned key either
+    let goodbye = DiscoveryMessage {
+        message: Some(discovery_message::Message::Goodbye(PeerGoodbye {
+            peer_id: victim.peer_id.clone(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    socket.send_to(&goodbye.encode_to_vec(), group).unwrap();
+    sleep(Duration::from_millis(300)).await;
+
+    let peer = listener.get_peers().into_iter().find(|peer| peer.id == victim.peer_id);
+    let peer = peer.expect("Spoofed goodbye should not remove the victim");
+    assert_eq!(peer.port, 8682, "Known address should not be replaced");
+    assert_eq!(peer.public_key, victim.public_key());
+
+    println!("✅ {} spoofed claims reported, victim kept at port {}", conflicts.len(), peer.port);
+
+    victim.stop();
+    listener.stop();
+}
+
+#[tokio::test]
+async fn test_replayed_goodbye_is_ignored() {
+    use archsockrust::discovery::{DiscoveryService, PeerIdentity};
+    use prost::Message;
+    use std::net::UdpSocket;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let identity = PeerIdentity::generate();
+    let mut listener = DiscoveryService::new("GoodbyeListener".to_string(), 8780, 8784).unwrap();
+    let victim = DiscoveryService::new("GoodbyeVictim".to_string(), 8782, 8784)
+        .unwrap()
+        .with_identity(identity.clone());
+    let bus = archsockrust::events::EventManager::new();
+    let (sender, mut events) = (bus.get_sender(), bus.subscribe());
+    listener.set_event_sender(sender);
+
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+    assert!(victim.start().await.is_ok(), "Failed to start victim");
+    let discovered = timeout(Duration::from_secs(2), next_peer_event(&mut events, &victim.peer_id)).await;
+    assert!(matches!(discovered, Ok(Some(P2PEvent::PeerDiscovered(_)))), "Victim should be discovered");
+
+    let goodbye = |timestamp: u64| {
+        let mut goodbye = PeerGoodbye {
+            peer_id: victim.peer_id.clone(),
+            timestamp,
+            ..Default::default()
+        };
+        identity.sign_goodbye(&mut goodbye);
+        DiscoveryMessage {
+            message: Some(discovery_message::Message::Goodbye(goodbye)),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let group = (listener.multicast_group(), 8784);
+
+    // A properly signed goodbye captured from an earlier session
+    let earlier = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64 - 60_000;
+    socket.send_to(&goodbye(earlier), group).unwrap();
+    sleep(Duration::from_millis(300)).await;
+    assert!(
+        listener.get_peers().iter().any(|peer| peer.id == victim.peer_id),
+        "Replayed goodbye should not remove the victim"
+    );
+
+    // A fresh one still does
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    socket.send_to(&goodbye(now), group).unwrap();
+    let lost = timeout(Duration::from_secs(1), next_peer_event(&mut events, &victim.peer_id)).await;
+    assert!(matches!(lost, Ok(Some(P2PEvent::PeerLost(_)))), "Fresh goodbye should remove the victim");
+
+    victim.stop();
+    listener.stop();
+}
+
+#[tokio::test]
+async fn test_replayed_announcements_do_not_keep_peers_alive() {
+    use archsockrust::discovery::{DiscoveryService, PeerIdentity};
+    use prost::Message;
+    use std::net::UdpSocket;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let mut listener = DiscoveryService::new("ReplayListener".to_string(), 8790, 8794).unwrap();
+    let bus = archsockrust::events::EventManager::new();
+    let (sender, mut events) = (bus.get_sender(), bus.subscribe());
+    listener.set_event_sender(sender);
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+
+    let announcement = |identity: &PeerIdentity| {
+        let mut announce = PeerAnnouncement {
+            peer_name: "Departed".to_string(),
+            peer_id: "departed-peer".to_string(),
+            tcp_port: 8792,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+            ..Default::default()
+        };
+        identity.sign_announcement(&mut announce);
+        DiscoveryMessage {
+            message: Some(discovery_message::Message::Announce(announce)),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let group = (listener.multicast_group(), 8794);
+
+    let original = PeerIdentity::generate();
+    let captured = announcement(&original);
+    socket.send_to(&captured, group).unwrap();
+    let discovered = timeout(Duration::from_secs(1), next_peer_event(&mut events, "departed-peer")).await;
+    assert!(matches!(discovered, Ok(Some(P2PEvent::PeerDiscovered(_)))), "Peer should be discovered");
+
+    // Replaying its last announcement doesn't count as hearing from it
+    sleep(Duration::from_millis(1200)).await;
+    socket.send_to(&captured, group).unwrap();
+    sleep(Duration::from_millis(100)).await;
+    listener.cleanup_stale_peers(1);
+    assert!(
+        listener.get_peers().iter().all(|peer| peer.id != "departed-peer"),
+        "Replayed announcement kept the peer alive"
+    );
+
+    // Its key expired with it, so the ID can be claimed afresh
+    socket.send_to(&announcement(&PeerIdentity::generate()), group).unwrap();
+    let reclaimed = timeout(Duration::from_secs(1), async {
+        loop {
+            match events.recv().await {
+                Some(P2PEvent::PeerDiscovered(peer)) if peer.id == "departed-peer" => return true,
+                Some(P2PEvent::PeerConflict(_)) | None => return false,
+                Some(_) => continue,
+            }
+        }
+    })
+    .await;
+    assert_eq!(reclaimed, Ok(true), "Expired key should not be held against a new claim");
+
+    listener.stop();
+}
+
+// Announcement rounds from `peer_id` heard on the group within `window`. A
+// round is sent once per interface, so copies arriving close together count once.
+async fn count_announcement_rounds(socket: &tokio::net::UdpSocket, peer_id: &str, window: Duration) -> usize {
+    use prost::Message;
+
+    let mut buffer = vec![0u8; 65_536];
+    let mut rounds = 0;
+    let mut last: Option<tokio::time::Instant> = None;
+    let deadline = tokio::time::Instant::now() + window;
+
+    while let Ok(Ok((len, _))) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+        let announced = match DiscoveryMessage::decode(&buffer[..len]).map(|message| message.message) {
+            Ok(Some(discovery_message::Message::Announce(announce))) => announce.peer_id == peer_id,
+            _ => false,
+        };
+        if !announced {
+            continue;
+        }
+        let now = tokio::time::Instant::now();
+        if last.is_none_or(|previous| now - previous > Duration::from_millis(25)) {
+            rounds += 1;
+        }
+        last = Some(now);
+    }
+    rounds
+}
+
+#[tokio::test]
+async fn test_announcements_back_off_and_restart_fast() {
+    use archsockrust::discovery::{AnnounceSchedule, DiscoveryService};
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::net::SocketAddrV4;
+
+    let schedule = AnnounceSchedule::new(Duration::from_millis(100), Duration::from_millis(3200))
+        .unwrap()
+        .with_startup_announcements(3)
+        .with_budget(0);
+    let announcer = DiscoveryService::new("Scheduled".to_string(), 8690, 8694)
+        .unwrap()
+        .with_announce_schedule(schedule);
+    assert_eq!(announcer.announce_schedule(), schedule);
+
+    // Listen on the group alongside the announcer's own socket
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    socket.set_reuse_address(true).unwrap();
+    #[cfg(unix)]
+    socket.set_reuse_port(true).unwrap();
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8694).into()).unwrap();
+    for interface in DiscoveryService::get_multicast_interfaces() {
+        let _ = socket.join_multicast_v4(&announcer.multicast_group(), &interface);
+    }
+    socket.set_nonblocking(true).unwrap();
+    let socket = tokio::net::UdpSocket::from_std(socket.into()).unwrap();
+
+    assert!(announcer.start().await.is_ok(), "Failed to start announcer");
+
+    // Fast rounds first, then doubling: far fewer than a fixed 100ms interval
+    let startup = count_announcement_rounds(&socket, &announcer.peer_id, Duration::from_millis(1500)).await;
+    assert!((3..=12).contains(&startup), "Expected a quick burst backing off, got {} rounds", startup);
+
+    let stable = count_announcement_rounds(&socket, &announcer.peer_id, Duration::from_millis(1500)).await;
+    assert!(stable <= 2, "Stable network should announce rarely, got {} rounds", stable);
+
+    // A new schedule (like a network change) starts over with fast rounds
+    announcer.set_announce_schedule(schedule);
+    let restarted = count_announcement_rounds(&socket, &announcer.peer_id, Duration::from_millis(400)).await;
+    assert!(restarted >= 2, "Restart should announce quickly again, got {} rounds", restarted);
+
+    println!("✅ Announcement rounds: {} at startup, {} when stable, {} after restart", startup, stable, restarted);
+
+    announcer.stop();
+}
+
+#[tokio::test]
+async fn test_busy_group_suppresses_announcement_rounds() {
+    use archsockrust::discovery::{AnnounceSchedule, DiscoveryService};
+    use prost::Message;
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::net::{SocketAddrV4, UdpSocket};
+
+    let schedule = AnnounceSchedule::new(Duration::from_millis(200), Duration::from_millis(200))
+        .unwrap()
+        .with_startup_announcements(0)
+        .with_budget(0);
+    let announcer = DiscoveryService::new("Crowded".to_string(), 8810, 8814)
+        .unwrap()
+        .with_announce_schedule(schedule);
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    socket.set_reuse_address(true).unwrap();
+    #[cfg(unix)]
+    socket.set_reuse_port(true).unwrap();
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8814).into()).unwrap();
+    for interface in DiscoveryService::get_multicast_interfaces() {
+        let _ = socket.join_multicast_v4(&announcer.multicast_group(), &interface);
+    }
+    socket.set_nonblocking(true).unwrap();
+    let socket = tokio::net::UdpSocket::from_std(socket.into()).unwrap();
+
+    assert!(announcer.start().await.is_ok(), "Failed to start announcer");
+    let quiet = count_announcement_rounds(&socket, &announcer.peer_id, Duration::from_millis(2000)).await;
+
+    // Three other peers announce on the shared port
+    let chatter = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let target = (announcer.multicast_group(), 8814);
+    let chatting = tokio::spawn(async move {
+        loop {
+            for peer in ["chatty-1", "chatty-2", "chatty-3"] {
+                let announcement = DiscoveryMessage {
+                    message: Some(discovery_message::Message::Announce(PeerAnnouncement {
+                        peer_name: peer.to_string(),
+                        peer_id: peer.to_string(),
+                        tcp_port: 8812,
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                };
+                let _ = chatter.send_to(&announcement.encode_to_vec(), target);
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+    });
+    let busy = count_announcement_rounds(&socket, &announcer.peer_id, Duration::from_millis(2000)).await;
+    chatting.abort();
+
+    assert!(busy > 0, "Busy group should still hear from the announcer");
+    assert!(busy * 4 <= quiet * 3, "Busy group should thin rounds out: {} quiet, {} busy", quiet, busy);
+
+    println!("✅ Announcement rounds: {} on a quiet group, {} on a busy one", quiet, busy);
+
+    announcer.stop();
+}
+
+#[tokio::test]
+async fn test_interface_filter_limits_discovery_and_reports_changes() {
+    use archsockrust::discovery::{DiscoveryService, InterfaceFilter};
+
+    let loopback_only = || InterfaceFilter::new().include("lo*").unwrap();
+
+    let mut alice = DiscoveryService::new("FilterAlice".to_string(), 8670, 8674).unwrap();
+    let bob = DiscoveryService::new("FilterBob".to_string(), 8672, 8674).unwrap();
+    let bus = archsockrust::events::EventManager::new();
+    let (sender, mut events) = (bus.get_sender(), bus.subscribe());
+    alice.set_event_sender(sender);
+    alice.set_interface_filter(loopback_only());
+    bob.set_interface_filter(loopback_only());
+    assert!(alice.start().await.is_ok(), "Failed to start Alice");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+
+    let active = alice.active_interfaces();
+    assert!(!active.is_empty(), "Loopback should be in use");
+    assert!(active.iter().all(|interface| interface.ip.is_loopback()), "Only loopback allowed: {:?}", active);
+
+    // Discovery still works on the interfaces that are left
+    let found = bob.discover_peers_within(Duration::from_millis(800)).await.unwrap();
+    assert!(found.iter().any(|peer| peer.id == alice.peer_id), "Bob should find Alice over loopback");
+
+    // Widening the filter at runtime rejoins and reports what changed
+    alice.set_interface_filter(InterfaceFilter::new().exclude("192.0.2.0/24").unwrap());
+    let expected: Vec<_> = InterfaceFilter::new()
+        .exclude("192.0.2.0/24")
+        .unwrap()
+        .interfaces()
+        .into_iter()
+        .filter(|interface| !interface.ip.is_loopback())
+        .collect();
+    if !expected.is_empty() {
+        let changed = timeout(Duration::from_secs(1), async {
+            while let Some(event) = events.recv().await {
+                if let P2PEvent::NetworkChanged { added, removed } = event {
+                    return Some((added, removed));
+                }
+            }
+            None
+        })
+        .await;
+        let (added, removed) = changed.ok().flatten().expect("Widening the filter should report NetworkChanged");
+        assert_eq!(added, expected);
+        assert!(removed.is_empty());
+        assert_eq!(alice.active_interfaces().len(), active.len() + expected.len());
+    }
+
+    println!("✅ Discovery limited to {:?}, then widened", active);
+
+    alice.stop();
+    bob.stop();
+}
+
+#[tokio::test]
+async fn test_interface_filter_matching_nothing_sends_nothing() {
+    use archsockrust::discovery::{DiscoveryService, InterfaceFilter};
+
+    // Only an interface that doesn't exist is allowed
+    let muted = DiscoveryService::new("MutedPeer".to_string(), 8850, 8854).unwrap();
+    let listener = DiscoveryService::new("MutedListener".to_string(), 8852, 8854).unwrap();
+    muted.set_interface_filter(InterfaceFilter::new().include("no-such-interface").unwrap());
+    assert!(listener.start().await.is_ok(), "Failed to start listener");
+    assert!(muted.start().await.is_ok(), "Failed to start muted peer");
+    assert!(muted.active_interfaces().is_empty());
+
+    // The startup announcements don't fall back to the OS default interface
+    sleep(Duration::from_millis(1500)).await;
+    assert!(
+        listener.get_peers().iter().all(|peer| peer.id != muted.peer_id),
+        "Nothing should be sent when no interface is allowed"
+    );
+
+    println!("✅ No interfaces allowed, nothing sent");
+
+    listener.stop();
+    muted.stop();
+}
@@ -5,7 +5,7 @@ use tokio::time::{timeout, Duration, sleep};
 async fn test_p2p_messenger_lifecycle() {
     // Test the complete lifecycle of a P2P messenger
     
-    let mut messenger = P2PMessenger::new("LifecycleTest".to_string())
+    let messenger = P2PMessenger::new("LifecycleTest".to_string())
         .expect("Failed to create P2P messenger");
     
     // Test initial state
@@ -22,16 +22,17 @@ async fn test_p2p_messenger_lifecycle() {
     println!("   ID: {}", messenger.peer_id());
     println!("   Local IP: {}", local_ip);
     
+    // Test event subscriptions, which can be taken any number of times;
+    // taken before starting so nothing emitted on the way up is missed
+    let mut ui_events = messenger.subscribe_events();
+    let mut log_events = messenger.subscribe_events();
+    
     // Test starting the messenger
     let start_result = messenger.start().await;
     assert!(start_result.is_ok(), "Failed to start messenger: {:?}", start_result.err());
     
     println!("✅ P2P Messenger started successfully");
     
-    // Test event receiver
-    let mut event_receiver = messenger.get_event_receiver();
-    assert!(event_receiver.is_some(), "Should have event receiver after starting");
-    
     // Test peer discovery
     let discovery_result = messenger.discover_peers();
     assert!(discovery_result.is_ok(), "Failed to discover peers: {:?}", discovery_result.err());
@@ -54,29 +55,41 @@ async fn test_p2p_messenger_lifecycle() {
     // Test stopping
     messenger.stop().await;
     println!("✅ P2P Messenger stopped successfully");
+
+    // Both subscribers saw the whole start/stop cycle independently
+    for events in [&mut ui_events, &mut log_events] {
+        let mut states = Vec::new();
+        while let Some(event) = events.try_recv() {
+            if let P2PEvent::StateChanged(state) = event {
+                states.push(state);
+            }
+        }
+        use MessengerState::*;
+        assert_eq!(states, [Starting, Running, Stopping, Stopped]);
+    }
 }
 
 #[tokio::test]
 async fn test_two_messenger_discovery() {
     // Test that two messengers can discover each other
     
-    let mut messenger1 = P2PMessenger::with_ports("Alice".to_string(), 8000, 8001)
+    let messenger1 = P2PMessenger::with_ports("Alice".to_string(), 8000, 8001)
         .expect("Failed to create Alice messenger");
-    let mut messenger2 = P2PMessenger::with_ports("Bob".to_string(), 8002, 8003)
+    let messenger2 = P2PMessenger::with_ports("Bob".to_string(), 8002, 8003)
         .expect("Failed to create Bob messenger");
     
     println!("🚀 Starting two-messenger discovery test:");
     println!("   Alice: ID={}, TCP=8000, Discovery=8001", messenger1.peer_id());
     println!("   Bob: ID={}, TCP=8002, Discovery=8003", messenger2.peer_id());
     
+    // Get event receivers before starting so no early event is missed
+    let mut alice_events = messenger1.subscribe_events();
+    let mut bob_events = messenger2.subscribe_events();
+    
     // Start both messengers
     assert!(messenger1.start().await.is_ok(), "Failed to start Alice");
     assert!(messenger2.start().await.is_ok(), "Failed to start Bob");
     
-    // Get event receivers
-    let mut alice_events = messenger1.get_event_receiver().unwrap();
-    let mut bob_events = messenger2.get_event_receiver().unwrap();
-    
     // Give them time to fully initialize
     sleep(Duration::from_millis(200)).await;
     
@@ -163,11 +176,12 @@ async fn test_two_messenger_discovery() {
 async fn test_custom_port_configurations() {
     // Test that messengers can be created with various port configurations
     
+    // Port 0 lets the OS pick, so nothing has to guess which ports are free
     let port_configs = vec![
-        (7000, 7001, "Config 1"),
-        (7100, 7101, "Config 2"),
-        (7200, 7201, "Config 3"),
-        (7300, 7301, "Config 4"),
+        (0, 0, "Config 1"),
+        (0, 0, "Config 2"),
+        (0, 0, "Config 3"),
+        (0, 0, "Config 4"),
     ];
     
     let mut messengers = Vec::new();
@@ -191,13 +205,22 @@ async fn test_custom_port_configurations() {
         messengers.push(messenger);
     }
     
-    // Start all messengers
+    // Start all messengers; each reports the ports it was given
     println!("🚀 Starting all messengers:");
+    let mut tcp_ports = Vec::new();
     for (i, messenger) in messengers.iter().enumerate() {
         let result = messenger.start().await;
         assert!(result.is_ok(), "Failed to start messenger {}: {:?}", i, result.err());
-        println!("   ✅ Started messenger {} ({})", i + 1, port_configs[i].2);
+        let addresses = result.unwrap();
+        assert_ne!(addresses.tcp.port(), 0);
+        assert_ne!(addresses.discovery.map(|addr| addr.port()), Some(0));
+        assert_eq!(messenger.listen_addresses(), Some(addresses));
+        tcp_ports.push(addresses.tcp.port());
+        println!("   ✅ Started messenger {} ({}) on {}", i + 1, port_configs[i].2, addresses.tcp);
     }
+    tcp_ports.sort_unstable();
+    tcp_ports.dedup();
+    assert_eq!(tcp_ports.len(), messengers.len(), "Each messenger should get its own TCP port");
     
     // Give them time to initialize
     sleep(Duration::from_millis(300)).await;
@@ -411,4 +434,1124 @@ fn test_timestamp_utilities() {
     }
     
     println!("✅ Timestamp utilities test completed");
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_broadcast_text_message_to_connected_peers() {
+    // Test that a broadcast reaches every connected peer and reports per-peer results
+    
+    let alice = P2PMessenger::with_ports("BroadcastAlice".to_string(), 8500, 8501)
+        .expect("Failed to create Alice messenger");
+    let bob = P2PMessenger::with_ports("BroadcastBob".to_string(), 8502, 8503)
+        .expect("Failed to create Bob messenger");
+    let carol = P2PMessenger::with_ports("BroadcastCarol".to_string(), 8504, 8505)
+        .expect("Failed to create Carol messenger");
+    
+    let _alice_events = alice.subscribe_events();
+    let mut bob_events = bob.subscribe_events();
+    let mut carol_events = carol.subscribe_events();
+    
+    assert!(alice.start().await.is_ok(), "Failed to start Alice");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+    assert!(carol.start().await.is_ok(), "Failed to start Carol");
+    
+    // Broadcasting with nobody connected yields an empty result map
+    let results = alice.broadcast_text_message("Nobody home".to_string()).await
+        .expect("Broadcast should succeed without peers");
+    assert!(results.is_empty(), "No peers should be reported without connections");
+    
+    for (peer, port) in [(&bob, 8502), (&carol, 8504)] {
+        let peer_info = PeerInfo {
+            id: peer.peer_id().to_string(),
+            name: peer.peer_name().to_string(),
+            ip: "127.0.0.1".to_string(),
+            port,
+            last_seen: get_current_timestamp(),
+            ..Default::default()
+        };
+        assert!(alice.connect_to_peer(&peer_info).await.is_ok(), "Alice should connect to {}", peer.peer_name());
+    }
+    
+    let results = alice.broadcast_text_message("Hello everyone!".to_string()).await
+        .expect("Broadcast should succeed");
+    
+    assert_eq!(results.len(), 2, "Broadcast should report a result per connected peer");
+    assert!(results.get(bob.peer_id()).is_some_and(|r| r.is_ok()), "Bob should be reached");
+    assert!(results.get(carol.peer_id()).is_some_and(|r| r.is_ok()), "Carol should be reached");
+    
+    // Both receivers get the same broadcast text
+    for (name, events) in [("Bob", &mut bob_events), ("Carol", &mut carol_events)] {
+        let received = timeout(Duration::from_secs(2), async {
+            while let Some(event) = events.recv().await {
+                if let P2PEvent::MessageReceived(message) = event {
+                    if let Some(MessageContent { content: Some(message_content::Content::Text(text)) }) = message.content {
+                        return Some((message.id, text.text));
+                    }
+                }
+            }
+            None
+        }).await;
+        
+        let (_, text) = received.ok().flatten()
+            .unwrap_or_else(|| panic!("{} should receive the broadcast", name));
+        assert_eq!(text, "Hello everyone!");
+        println!("✅ {} received broadcast: {}", name, text);
+    }
+    
+    alice.stop().await;
+    bob.stop().await;
+    carol.stop().await;
+}
+
+#[tokio::test]
+async fn test_relay_delivers_between_peers_without_direct_link() {
+    // Alice and Carol are only connected through Bob, who relays between them
+    
+    let alice = P2PMessenger::with_ports("RelayAlice".to_string(), 8510, 8511)
+        .expect("Failed to create Alice messenger");
+    let bob = P2PMessenger::with_ports("RelayBob".to_string(), 8512, 8513)
+        .expect("Failed to create Bob messenger");
+    let carol = P2PMessenger::with_ports("RelayCarol".to_string(), 8514, 8515)
+        .expect("Failed to create Carol messenger");
+    
+    let _alice_events = alice.subscribe_events();
+    let _bob_events = bob.subscribe_events();
+    let mut carol_events = carol.subscribe_events();
+    
+    for messenger in [&alice, &bob, &carol] {
+        assert!(messenger.start().await.is_ok(), "Failed to start {}", messenger.peer_name());
+    }
+    
+    let bob_info = PeerInfo {
+        id: bob.peer_id().to_string(),
+        name: bob.peer_name().to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 8512,
+        last_seen: get_current_timestamp(),
+        ..Default::default()
+    };
+    assert!(alice.connect_to_peer(&bob_info).await.is_ok(), "Alice should connect to Bob");
+    assert!(carol.connect_to_peer(&bob_info).await.is_ok(), "Carol should connect to Bob");
+    
+    // Let Bob process both handshakes
+    sleep(Duration::from_millis(200)).await;
+    
+    // Without relaying Alice has no route to Carol
+    let result = alice.send_text_message(carol.peer_id(), "Direct?".to_string()).await;
+    assert!(result.is_err(), "Sending to an unconnected peer should fail without relay");
+    
+    alice.set_relay_enabled(true);
+    bob.set_relay_enabled(true);
+    
+    let result = alice.send_text_message(carol.peer_id(), "Hello via Bob".to_string()).await;
+    assert!(result.is_ok(), "Relayed send should be accepted: {:?}", result.err());
+    
+    let received = timeout(Duration::from_secs(2), async {
+        while let Some(event) = carol_events.recv().await {
+            if let P2PEvent::MessageReceived(message) = event {
+                if let Some(MessageContent { content: Some(message_content::Content::Text(text)) }) = &message.content {
+                    return Some((message.sender_id.clone(), text.text.clone()));
+                }
+            }
+        }
+        None
+    }).await;
+    
+    let (sender_id, text) = received.ok().flatten().expect("Carol should receive the relayed message");
+    assert_eq!(sender_id, alice.peer_id(), "Relayed message should keep the original sender");
+    assert_eq!(text, "Hello via Bob");
+    
+    println!("✅ Carol received relayed message from Alice: {}", text);
+    
+    alice.stop().await;
+    bob.stop().await;
+    carol.stop().await;
+}
+
+#[tokio::test]
+async fn test_rpc_call_round_trip() {
+    // Alice calls methods that Bob serves over their connection
+    
+    let alice = P2PMessenger::with_ports("RpcAlice".to_string(), 8520, 8521)
+        .expect("Failed to create Alice messenger");
+    let bob = P2PMessenger::with_ports("RpcBob".to_string(), 8522, 8523)
+        .expect("Failed to create Bob messenger");
+    
+    let _alice_events = alice.subscribe_events();
+    let _bob_events = bob.subscribe_events();
+    
+    assert!(alice.start().await.is_ok(), "Failed to start Alice");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+    
+    bob.register_rpc_handler("echo", |request| async move { Ok(request.payload) });
+    bob.register_rpc_handler("fail", |_| async { Err("nope".to_string()) });
+    bob.register_rpc_handler("slow", |_| async {
+        sleep(Duration::from_secs(2)).await;
+        Ok(Vec::new())
+    });
+    
+    let bob_info = PeerInfo {
+        id: bob.peer_id().to_string(),
+        name: bob.peer_name().to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 8522,
+        last_seen: get_current_timestamp(),
+        ..Default::default()
+    };
+    assert!(alice.connect_to_peer(&bob_info).await.is_ok(), "Alice should connect to Bob");
+    sleep(Duration::from_millis(200)).await;
+    
+    let reply = alice.call(bob.peer_id(), "echo", b"ping".to_vec(), Duration::from_secs(2)).await;
+    assert_eq!(reply.expect("Echo call should succeed"), b"ping".to_vec());
+    
+    match alice.call(bob.peer_id(), "fail", Vec::new(), Duration::from_secs(2)).await {
+        Err(error::P2PError::Rpc(message)) => assert_eq!(message, "nope"),
+        other => panic!("Expected remote error, got {:?}", other),
+    }
+    
+    match alice.call(bob.peer_id(), "missing", Vec::new(), Duration::from_secs(2)).await {
+        Err(error::P2PError::Rpc(message)) => assert!(message.contains("missing")),
+        other => panic!("Expected unknown method error, got {:?}", other),
+    }
+    
+    let result = alice.call(bob.peer_id(), "slow", Vec::new(), Duration::from_millis(200)).await;
+    assert!(matches!(result, Err(error::P2PError::Timeout)), "Slow call should time out");
+    
+    assert!(bob.unregister_rpc_handler("echo"));
+    assert!(!bob.unregister_rpc_handler("echo"));
+    
+    println!("✅ RPC calls completed with replies, errors and timeouts");
+    
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct GameMove {
+    #[prost(string, tag = "1")]
+    player: String,
+    #[prost(uint32, tag = "2")]
+    square: u32,
+}
+
+impl prost::Name for GameMove {
+    const NAME: &'static str = "GameMove";
+    const PACKAGE: &'static str = "tests";
+}
+
+#[tokio::test]
+async fn test_custom_message_typed_dispatch() {
+    // Bob decodes GameMove payloads; unregistered types arrive as plain messages
+    
+    let alice = P2PMessenger::with_ports("CustomAlice".to_string(), 8530, 8531)
+        .expect("Failed to create Alice messenger");
+    let bob = P2PMessenger::with_ports("CustomBob".to_string(), 8532, 8533)
+        .expect("Failed to create Bob messenger");
+    
+    let _alice_events = alice.subscribe_events();
+    let mut bob_events = bob.subscribe_events();
+    
+    assert!(alice.start().await.is_ok(), "Failed to start Alice");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+    
+    let bob_info = PeerInfo {
+        id: bob.peer_id().to_string(),
+        name: bob.peer_name().to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 8532,
+        last_seen: get_current_timestamp(),
+        ..Default::default()
+    };
+    assert!(alice.connect_to_peer(&bob_info).await.is_ok(), "Alice should connect to Bob");
+    sleep(Duration::from_millis(200)).await;
+    
+    let game_move = GameMove { player: "alice".to_string(), square: 4 };
+    
+    // Not registered yet: delivered as a raw Any
+    assert!(alice.send_custom_message(bob.peer_id(), &game_move).await.is_ok());
+    
+    let raw = timeout(Duration::from_secs(2), async {
+        while let Some(event) = bob_events.recv().await {
+            if let P2PEvent::MessageReceived(message) = event {
+                if let Some(MessageContent { content: Some(message_content::Content::Custom(any)) }) = message.content {
+                    return Some(any);
+                }
+            }
+        }
+        None
+    }).await;
+    let any = raw.ok().flatten().expect("Bob should receive the raw payload");
+    assert_eq!(any.type_url, "/tests.GameMove");
+    
+    bob.register_custom_type::<GameMove>();
+    assert!(alice.send_custom_message(bob.peer_id(), &game_move).await.is_ok());
+    
+    let decoded = timeout(Duration::from_secs(2), async {
+        while let Some(event) = bob_events.recv().await {
+            if let P2PEvent::CustomMessage { message, payload } = event {
+                return Some((message.sender_id, payload));
+            }
+        }
+        None
+    }).await;
+    let (sender_id, payload) = decoded.ok().flatten().expect("Bob should receive a decoded GameMove");
+    assert_eq!(sender_id, alice.peer_id());
+    assert_eq!(payload.downcast_ref::<GameMove>(), Some(&game_move));
+    
+    assert!(bob.unregister_custom_type::<GameMove>());
+    
+    println!("✅ Custom payload decoded as {}", payload.type_url());
+    
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_publish_reaches_only_subscribed_peers() {
+    // Bob and Carol connect to Alice, who publishes telemetry
+    
+    let alice = P2PMessenger::with_ports("PubAlice".to_string(), 8540, 8541)
+        .expect("Failed to create Alice messenger");
+    let bob = P2PMessenger::with_ports("PubBob".to_string(), 8542, 8543)
+        .expect("Failed to create Bob messenger");
+    let carol = P2PMessenger::with_ports("PubCarol".to_string(), 8544, 8545)
+        .expect("Failed to create Carol messenger");
+    
+    let _alice_events = alice.subscribe_events();
+    let _bob_events = bob.subscribe_events();
+    let _carol_events = carol.subscribe_events();
+    
+    for messenger in [&alice, &bob, &carol] {
+        assert!(messenger.start().await.is_ok(), "Failed to start {}", messenger.peer_name());
+    }
+    
+    // Bob subscribes before connecting, Carol after
+    let mut bob_telemetry = bob.subscribe("telemetry").await.expect("Bob should subscribe");
+    
+    let alice_info = PeerInfo {
+        id: alice.peer_id().to_string(),
+        name: alice.peer_name().to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 8540,
+        last_seen: get_current_timestamp(),
+        ..Default::default()
+    };
+    assert!(bob.connect_to_peer(&alice_info).await.is_ok(), "Bob should connect to Alice");
+    assert!(carol.connect_to_peer(&alice_info).await.is_ok(), "Carol should connect to Alice");
+    
+    let mut carol_telemetry = carol.subscribe("telemetry").await.expect("Carol should subscribe");
+    sleep(Duration::from_millis(200)).await;
+    
+    let sent = alice.publish("telemetry", b"cpu=42".to_vec()).await.expect("Publish should succeed");
+    assert_eq!(sent, 2, "Both subscribers should be targeted");
+    
+    for (name, stream) in [("Bob", &mut bob_telemetry), ("Carol", &mut carol_telemetry)] {
+        let message = timeout(Duration::from_secs(2), stream.recv())
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| panic!("{} should receive the publication", name));
+        assert_eq!(message.topic, "telemetry");
+        assert_eq!(message.payload, b"cpu=42".to_vec());
+        assert_eq!(message.sender_id, alice.peer_id());
+    }
+    
+    let sent = alice.publish("alerts", b"disk full".to_vec()).await.expect("Publish should succeed");
+    assert_eq!(sent, 0, "Nobody subscribed to alerts");
+    
+    carol.unsubscribe("telemetry");
+    sleep(Duration::from_millis(200)).await;
+    
+    let sent = alice.publish("telemetry", b"cpu=43".to_vec()).await.expect("Publish should succeed");
+    assert_eq!(sent, 1, "Only Bob should remain subscribed");
+    
+    println!("✅ Publications reached only subscribed peers");
+    
+    alice.stop().await;
+    bob.stop().await;
+    carol.stop().await;
+}
+
+#[tokio::test]
+async fn test_connect_by_address_learns_peer_identity() {
+    // Alice only knows Bob's address; the handshake tells her who he is
+    
+    let alice = P2PMessenger::with_ports("AddrAlice".to_string(), 8580, 8581)
+        .expect("Failed to create Alice messenger");
+    let bob = P2PMessenger::with_ports("AddrBob".to_string(), 8582, 8583)
+        .expect("Failed to create Bob messenger");
+    
+    let mut bob_events = bob.subscribe_events();
+    
+    assert!(alice.start().await.is_ok(), "Failed to start Alice");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+    
+    let peer = alice.connect_to_address("127.0.0.1:8582").await
+        .expect("Alice should connect to Bob by address");
+    assert_eq!(peer.id, bob.peer_id(), "Handshake should reveal Bob's real ID");
+    assert_eq!(peer.name, "AddrBob");
+    assert_eq!(peer.port, 8582);
+    
+    let connected = alice.get_connected_peers().await;
+    assert_eq!(connected.len(), 1);
+    assert_eq!(connected[0].id, bob.peer_id(), "Placeholder ID should be replaced");
+    
+    assert!(alice.send_text_message(bob.peer_id(), "found you".to_string()).await.is_ok());
+    let received = timeout(Duration::from_secs(2), async {
+        while let Some(event) = bob_events.recv().await {
+            if let P2PEvent::MessageReceived(message) = event {
+                return Some(message);
+            }
+        }
+        None
+    }).await.ok().flatten().expect("Bob should receive Alice's message");
+    assert_eq!(received.sender_id, alice.peer_id());
+    
+    // Nothing listens here
+    assert!(alice.connect_to_address("127.0.0.1:8589").await.is_err(), "Dead address should fail");
+    
+    println!("✅ Connect-by-address learned {} ({})", peer.name, peer.id);
+    
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_bootstrap_peers_dialled_on_start() {
+    let bob = P2PMessenger::with_ports("BootBob".to_string(), 8584, 8585)
+        .expect("Failed to create Bob messenger");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+    
+    let mut carol = P2PMessenger::with_ports("BootCarol".to_string(), 8586, 8587)
+        .expect("Failed to create Carol messenger");
+    carol.add_bootstrap_peer("127.0.0.1:8584");
+    let mut carol_events = carol.subscribe_events();
+    assert!(carol.start().await.is_ok(), "Failed to start Carol");
+    
+    let connected = timeout(Duration::from_secs(3), async {
+        while let Some(event) = carol_events.recv().await {
+            if let P2PEvent::PeerConnected(peer) = event {
+                if peer.id == bob.peer_id() {
+                    return true;
+                }
+            }
+        }
+        false
+    }).await.unwrap_or(false);
+    assert!(connected, "Carol should connect to her bootstrap peer on start");
+    
+    println!("✅ Bootstrap peer dialled on start");
+    
+    bob.stop().await;
+    carol.stop().await;
+}
+
+#[tokio::test]
+async fn test_messenger_built_from_config() {
+    use archsockrust::discovery::PeerIdentity;
+
+    let bob = P2PMessenger::with_ports("ConfigBob".to_string(), 8700, 8701)
+        .expect("Failed to create Bob messenger");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+
+    let identity = PeerIdentity::generate();
+    let download_dir = std::env::temp_dir().join(format!("archsockrust-config-{}", std::process::id()));
+    let config = P2PConfig::builder("ConfigAlice")
+        .peer_id("config-alice")
+        .identity(identity.clone())
+        .tcp_port(8702)
+        .discovery_port(8703)
+        .download_dir(&download_dir)
+        .peer_timeout(Duration::from_secs(90))
+        .bootstrap_peer("127.0.0.1:8700")
+        .build()
+        .expect("Config should be valid");
+    assert_eq!(config.bootstrap_peers(), ["127.0.0.1:8700"]);
+
+    let alice = P2PMessenger::with_config(config).expect("Failed to create Alice messenger");
+    assert_eq!(alice.peer_id(), "config-alice");
+    assert_eq!(alice.identity().public_key(), identity.public_key());
+
+    // Settings reach the running messenger: Bob sees the configured ID
+    let mut events = alice.subscribe_events();
+    assert!(alice.start().await.is_ok(), "Failed to start Alice");
+    let connected = timeout(Duration::from_secs(3), async {
+        while let Some(event) = events.recv().await {
+            if let P2PEvent::PeerConnected(peer) = event {
+                return Some(peer);
+            }
+        }
+        None
+    }).await.ok().flatten().expect("Alice should dial her bootstrap peer");
+    assert_eq!(connected.id, bob.peer_id());
+    sleep(Duration::from_millis(200)).await;
+    assert!(bob.get_connected_peers().await.iter().any(|peer| peer.id == "config-alice"));
+
+    // Received files go to the configured directory
+    let file = P2pMessage {
+        content: Some(MessageContent {
+            content: Some(message_content::Content::File(FileMessage {
+                filename: "note.txt".to_string(),
+                data: b"configured".to_vec(),
+            })),
+        }),
+        ..Default::default()
+    };
+    let saved = alice.save_received_file(&file).expect("File should be saved");
+    assert_eq!(std::path::Path::new(&saved), download_dir.join("note.txt"));
+    assert_eq!(std::fs::read(&saved).unwrap(), b"configured");
+    let _ = std::fs::remove_dir_all(&download_dir);
+
+    println!("✅ Messenger built from config as {}", alice.peer_id());
+
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_connect_over_ipv6_loopback() {
+    if std::net::TcpListener::bind("[::1]:0").is_err() {
+        println!("ℹ️ No IPv6 loopback on this host, skipping");
+        return;
+    }
+    
+    let alice = P2PMessenger::with_ports("V6Alice".to_string(), 8596, 8597)
+        .expect("Failed to create Alice messenger");
+    let bob = P2PMessenger::with_ports("V6Bob".to_string(), 8598, 8599)
+        .expect("Failed to create Bob messenger");
+    
+    assert!(alice.start().await.is_ok(), "Failed to start Alice");
+    assert!(bob.start().await.is_ok(), "Failed to start Bob");
+    
+    let peer = alice.connect_to_address("[::1]:8598").await
+        .expect("Alice should connect to Bob over IPv6");
+    assert_eq!(peer.id, bob.peer_id());
+    assert_eq!(peer.ip, "::1");
+    
+    // The same listener still accepts IPv4
+    let carol = P2PMessenger::with_ports("V6Carol".to_string(), 8600, 8601)
+        .expect("Failed to create Carol messenger");
+    assert!(carol.start().await.is_ok(), "Failed to start Carol");
+    let peer = carol.connect_to_address("127.0.0.1:8598").await
+        .expect("Carol should connect to Bob over IPv4");
+    assert_eq!(peer.id, bob.peer_id());
+    
+    sleep(Duration::from_millis(200)).await;
+    let bob_peers = bob.get_connected_peers().await;
+    assert_eq!(bob_peers.len(), 2, "Bob should see Alice and Carol");
+    assert!(
+        bob_peers.iter().all(|peer| !peer.ip.starts_with("::ffff:")),
+        "IPv4 peers should not show as v4-mapped: {:?}",
+        bob_peers.iter().map(|peer| &peer.ip).collect::<Vec<_>>()
+    );
+    
+    println!("✅ Dual-stack listener accepted IPv6 and IPv4 peers");
+    
+    alice.stop().await;
+    bob.stop().await;
+    carol.stop().await;
+}
+
+#[tokio::test]
+async fn test_handshake_outside_network_group_is_rejected() {
+    use archsockrust::discovery::NetworkGroup;
+
+    let mut alice = P2PMessenger::with_ports("GroupAlice".to_string(), 8660, 8661).unwrap();
+    let mut bob = P2PMessenger::with_ports("GroupBob".to_string(), 8662, 8663).unwrap();
+    let outsider = P2PMessenger::with_ports("Outsider".to_string(), 8664, 8665).unwrap();
+    alice.set_network_group(NetworkGroup::new("qa").with_secret(b"qa-secret"));
+    bob.set_network_group(NetworkGroup::new("qa").with_secret(b"qa-secret"));
+
+    let mut alice_events = alice.subscribe_events();
+    for messenger in [&alice, &bob, &outsider] {
+        assert!(messenger.start().await.is_ok());
+    }
+
+    // Members connect as usual
+    let peer = bob.connect_to_address("127.0.0.1:8660").await.expect("Same group should connect");
+    assert_eq!(peer.id, alice.peer_id());
+
+    // An outsider is refused, and Alice never reports it as connected
+    let result = outsider.connect_to_address("127.0.0.1:8660").await;
+    assert!(matches!(result, Err(error::P2PError::NotInGroup)), "Outsider should be rejected, got {:?}", result);
+
+    sleep(Duration::from_millis(200)).await;
+    let connected = alice.get_connected_peers().await;
+    assert_eq!(connected.len(), 1, "Only Bob should stay connected");
+    assert_eq!(connected[0].id, bob.peer_id());
+
+    let mut rejected = false;
+    while let Some(event) = alice_events.try_recv() {
+        match event {
+            P2PEvent::PeerConnected(peer) => assert_ne!(peer.name, "Outsider"),
+            P2PEvent::Error(error) if error.contains("network group") => rejected = true,
+            _ => {}
+        }
+    }
+    assert!(rejected, "Alice should report the rejected handshake");
+
+    println!("✅ Outsider kept out of group '{}'", alice.network_group().name());
+
+    for messenger in [&alice, &bob, &outsider] {
+        messenger.stop().await;
+    }
+}
+
+#[tokio::test]
+async fn test_messages_before_handshake_are_dropped() {
+    use prost::Message;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let alice = P2PMessenger::with_ports("GuardedAlice".to_string(), 8750, 0).unwrap();
+    let mut alice_events = alice.subscribe_events();
+    alice.start().await.expect("Failed to start Alice");
+
+    // A raw client skips the handshake and goes straight to chat
+    let mut stream = tokio::net::TcpStream::connect("127.0.0.1:8750").await.unwrap();
+    let message = P2pMessage {
+        id: "no-handshake".to_string(),
+        sender_id: "intruder".to_string(),
+        sender_name: "Intruder".to_string(),
+        content: Some(MessageContent {
+            content: Some(message_content::Content::Text(TextMessage { text: "let me in".to_string() })),
+        }),
+        ..Default::default()
+    };
+    let data = message.encode_to_vec();
+    stream.write_all(&(data.len() as u64).to_be_bytes()).await.unwrap();
+    stream.write_all(&data).await.unwrap();
+
+    // Alice hangs up after her own handshake instead of reading on
+    let mut received = Vec::new();
+    let closed = timeout(Duration::from_secs(2), stream.read_to_end(&mut received)).await;
+    assert!(closed.is_ok(), "Alice should close the connection");
+
+    sleep(Duration::from_millis(200)).await;
+    while let Some(event) = alice_events.try_recv() {
+        assert!(!matches!(event, P2PEvent::MessageReceived(_)), "Message without handshake was delivered");
+        assert!(!matches!(event, P2PEvent::PeerConnected(_)), "Intruder was reported as connected");
+    }
+    assert!(alice.get_connected_peers().await.is_empty());
+
+    alice.stop().await;
+}
+
+// Frames for tests that speak the wire protocol over a raw socket
+async fn raw_read_frame(stream: &mut tokio::net::TcpStream) -> P2pMessage {
+    use prost::Message;
+    use tokio::io::AsyncReadExt;
+
+    let mut size = [0u8; 8];
+    stream.read_exact(&mut size).await.unwrap();
+    let mut data = vec![0u8; u64::from_be_bytes(size) as usize];
+    stream.read_exact(&mut data).await.unwrap();
+    P2pMessage::decode(&data[..]).unwrap()
+}
+
+async fn raw_write_frame(stream: &mut tokio::net::TcpStream, content: message_content::Content) {
+    use prost::Message;
+    use tokio::io::AsyncWriteExt;
+
+    let message = P2pMessage {
+        id: uuid::Uuid::new_v4().to_string(),
+        content: Some(MessageContent { content: Some(content) }),
+        ..Default::default()
+    };
+    let data = message.encode_to_vec();
+    stream.write_all(&(data.len() as u64).to_be_bytes()).await.unwrap();
+    stream.write_all(&data).await.unwrap();
+}
+
+fn raw_content(message: P2pMessage) -> Option<message_content::Content> {
+    message.content.and_then(|content| content.content)
+}
+
+#[tokio::test]
+async fn test_replayed_handshake_proof_is_rejected() {
+    use archsockrust::discovery::NetworkGroup;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    let mut alice = P2PMessenger::with_ports("ReplayAlice".to_string(), 8770, 0).unwrap();
+    let mut bob = P2PMessenger::with_ports("ReplayBob".to_string(), 0, 0).unwrap();
+    alice.set_network_group(NetworkGroup::new("qa").with_secret(b"qa-secret"));
+    bob.set_network_group(NetworkGroup::new("qa").with_secret(b"qa-secret"));
+    let bob = std::sync::Arc::new(bob);
+    let mut alice_events = alice.subscribe_events();
+    alice.start().await.expect("Failed to start Alice");
+    bob.start().await.expect("Failed to start Bob");
+
+    // An eavesdropper gets Bob to answer a challenge of its choosing
+    let trap = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let trap_address = trap.local_addr().unwrap().to_string();
+    let dialer = std::sync::Arc::clone(&bob);
+    tokio::spawn(async move { dialer.connect_to_address(&trap_address).await });
+    let (mut from_bob, _) = trap.accept().await.unwrap();
+    let Some(message_content::Content::Handshake(bob_hello)) = raw_content(raw_read_frame(&mut from_bob).await) else {
+        panic!("Bob should open with a handshake");
+    };
+    raw_write_frame(&mut from_bob, message_content::Content::Handshake(HandshakeMessage {
+        peer_id: "eavesdropper".to_string(),
+        group: "qa".to_string(),
+        nonce: vec![7; 16],
+        ..Default::default()
+    })).await;
+    let Some(message_content::Content::HandshakeProof(captured)) = raw_content(raw_read_frame(&mut from_bob).await) else {
+        panic!("Bob should answer the challenge");
+    };
+
+    // Replaying Bob's handshake and proof to Alice fails her fresh challenge
+    let mut to_alice = TcpStream::connect("127.0.0.1:8770").await.unwrap();
+    raw_write_frame(&mut to_alice, message_content::Content::Handshake(bob_hello)).await;
+    raw_write_frame(&mut to_alice, message_content::Content::HandshakeProof(captured)).await;
+
+    let mut received = Vec::new();
+    let closed = timeout(Duration::from_secs(2), to_alice.read_to_end(&mut received)).await;
+    assert!(closed.is_ok(), "Alice should close the connection");
+
+    sleep(Duration::from_millis(200)).await;
+    let mut rejected = false;
+    while let Some(event) = alice_events.try_recv() {
+        match event {
+            P2PEvent::PeerConnected(peer) => panic!("Replayed proof let {} in", peer.name),
+            P2PEvent::Error(error) if error.contains("network group") => rejected = true,
+            _ => {}
+        }
+    }
+    assert!(rejected, "Alice should report the rejected handshake");
+    assert!(alice.get_connected_peers().await.is_empty());
+
+    // The real Bob still gets in
+    let peer = bob.connect_to_address("127.0.0.1:8770").await.expect("Bob should still connect");
+    assert_eq!(peer.id, alice.peer_id());
+
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_reflected_handshake_proof_is_rejected() {
+    use archsockrust::discovery::NetworkGroup;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    let mut alice = P2PMessenger::with_ports("MirrorAlice".to_string(), 8830, 0).unwrap();
+    alice.set_network_group(NetworkGroup::new("qa").with_secret(b"qa-secret"));
+    let mut alice_events = alice.subscribe_events();
+    alice.start().await.expect("Failed to start Alice");
+
+    // Without the secret, an attacker takes Alice's challenge on one
+    // connection and gets her to answer it on another
+    let mut first = TcpStream::connect("127.0.0.1:8830").await.unwrap();
+    let Some(message_content::Content::Handshake(first_hello)) = raw_content(raw_read_frame(&mut first).await) else {
+        panic!("Alice should open with a handshake");
+    };
+    let mut second = TcpStream::connect("127.0.0.1:8830").await.unwrap();
+    let Some(message_content::Content::Handshake(second_hello)) = raw_content(raw_read_frame(&mut second).await) else {
+        panic!("Alice should open with a handshake");
+    };
+    raw_write_frame(&mut second, message_content::Content::Handshake(HandshakeMessage {
+        peer_id: "mirror".to_string(),
+        group: "qa".to_string(),
+        nonce: first_hello.nonce.clone(),
+        ..Default::default()
+    })).await;
+    let Some(message_content::Content::HandshakeProof(reflected)) = raw_content(raw_read_frame(&mut second).await) else {
+        panic!("Alice should answer the challenge");
+    };
+
+    // Posing as Alice with her own answer gets the first connection nowhere
+    raw_write_frame(&mut first, message_content::Content::Handshake(HandshakeMessage {
+        peer_id: alice.peer_id().to_string(),
+        group: "qa".to_string(),
+        nonce: second_hello.nonce.clone(),
+        ..Default::default()
+    })).await;
+    raw_write_frame(&mut first, message_content::Content::HandshakeProof(reflected)).await;
+
+    let mut received = Vec::new();
+    let closed = timeout(Duration::from_secs(2), first.read_to_end(&mut received)).await;
+    assert!(closed.is_ok(), "Alice should close the connection");
+
+    sleep(Duration::from_millis(200)).await;
+    while let Some(event) = alice_events.try_recv() {
+        if let P2PEvent::PeerConnected(peer) = event {
+            panic!("Reflected proof let {} in", peer.name);
+        }
+    }
+    assert!(alice.get_connected_peers().await.is_empty());
+
+    alice.stop().await;
+}
+
+#[tokio::test]
+async fn test_stopped_messenger_cancels_handlers_and_refuses_connections() {
+    let alice = std::sync::Arc::new(P2PMessenger::with_ports("StoppedAlice".to_string(), 0, 0).unwrap());
+    let bob = P2PMessenger::with_ports("StoppedBob".to_string(), 0, 0).unwrap();
+    let alice_port = alice.start().await.expect("Failed to start Alice").tcp.port();
+    let bob_port = bob.start().await.expect("Failed to start Bob").tcp.port();
+
+    // Bob's handler never finishes; the receiver only hears when it is dropped
+    let (held, released) = tokio::sync::oneshot::channel::<()>();
+    let held = std::sync::Mutex::new(Some(held));
+    bob.register_rpc_handler("hang", move |_| {
+        let held = held.lock().unwrap().take();
+        async move {
+            let _held = held;
+            sleep(Duration::from_secs(60)).await;
+            Ok(Vec::new())
+        }
+    });
+    alice.connect_to_address(&format!("127.0.0.1:{}", bob_port)).await.expect("Alice should reach Bob");
+    let caller = std::sync::Arc::clone(&alice);
+    let bob_id = bob.peer_id().to_string();
+    tokio::spawn(async move { caller.call(&bob_id, "hang", Vec::new(), Duration::from_secs(30)).await });
+    sleep(Duration::from_millis(300)).await;
+
+    bob.stop().await;
+    let cancelled = timeout(Duration::from_secs(1), released).await;
+    assert!(cancelled.is_ok(), "Stopping should abandon running RPC handlers");
+
+    // A stopped messenger makes no new connections until it starts again
+    let refused = bob.connect_to_address(&format!("127.0.0.1:{}", alice_port)).await;
+    assert!(matches!(refused, Err(archsockrust::error::P2PError::InvalidState(_))), "Expected a refusal, got {:?}", refused);
+    assert!(bob.get_connected_peers().await.is_empty());
+
+    bob.start().await.expect("Bob should start again");
+    bob.connect_to_address(&format!("127.0.0.1:{}", alice_port)).await.expect("Restarted Bob should connect");
+
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_slow_dial_does_not_block_other_commands() {
+    let alice = std::sync::Arc::new(P2PMessenger::with_ports("PatientAlice".to_string(), 0, 0).unwrap());
+    alice.start().await.expect("Failed to start Alice");
+
+    // A listener whose backlog is full drops further SYNs, leaving the dial
+    // hanging until its timeout
+    let full = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+    full.bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)).into()).unwrap();
+    full.listen(0).unwrap();
+    let full_address = full.local_addr().unwrap().as_socket().unwrap();
+    let _queued: Vec<_> = (0..4)
+        .filter_map(|_| std::net::TcpStream::connect_timeout(&full_address, Duration::from_millis(200)).ok())
+        .collect();
+    let dialer = std::sync::Arc::clone(&alice);
+    let dial = tokio::spawn(async move { dialer.connect_to_address(&full_address.to_string()).await });
+    sleep(Duration::from_millis(100)).await;
+
+    let peers = timeout(Duration::from_millis(500), alice.get_connected_peers()).await;
+    assert!(peers.is_ok(), "Actor should keep answering while a dial is pending");
+
+    // Stopping abandons the dial instead of waiting it out
+    timeout(Duration::from_secs(3), alice.stop()).await.expect("Stop should not wait for the dial");
+    let result = timeout(Duration::from_secs(1), dial).await.expect("Dial should end with the messenger");
+    assert!(result.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn test_broadcast_skips_connections_without_handshake() {
+    use tokio::io::AsyncReadExt;
+
+    let alice = P2PMessenger::with_ports("BroadcastAlice".to_string(), 8760, 0).unwrap();
+    let bob = P2PMessenger::with_ports("BroadcastBob".to_string(), 0, 0).unwrap();
+    alice.start().await.expect("Failed to start Alice");
+    bob.start().await.expect("Failed to start Bob");
+
+    // A raw client connects but never introduces itself
+    let mut silent = tokio::net::TcpStream::connect("127.0.0.1:8760").await.unwrap();
+    bob.connect_to_address("127.0.0.1:8760").await.expect("Bob should reach Alice");
+    sleep(Duration::from_millis(200)).await;
+
+    let results = alice.broadcast_text_message("members only".to_string()).await.unwrap();
+    assert_eq!(results.keys().collect::<Vec<_>>(), vec![bob.peer_id()], "Only Bob completed a handshake");
+    let connected = alice.get_connected_peers().await;
+    assert_eq!(connected.len(), 1, "Silent client should not be listed");
+
+    // The silent client only ever gets Alice's handshake
+    let mut size = [0u8; 8];
+    silent.read_exact(&mut size).await.unwrap();
+    let mut handshake = vec![0u8; u64::from_be_bytes(size) as usize];
+    silent.read_exact(&mut handshake).await.unwrap();
+    let more = timeout(Duration::from_millis(300), silent.read(&mut size)).await;
+    assert!(more.is_err(), "Broadcast reached a connection without a handshake");
+
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_dropped_connection_reports_handshaken_peer() {
+    use tokio::net::TcpStream;
+
+    let alice = P2PMessenger::with_ports("DroppedAlice".to_string(), 8800, 0).unwrap();
+    let mut alice_events = alice.subscribe_events();
+    alice.start().await.expect("Failed to start Alice");
+
+    let hello = || {
+        message_content::Content::Handshake(HandshakeMessage {
+            peer_id: "raw-peer".to_string(),
+            peer_name: "RawPeer".to_string(),
+            tcp_port: 8802,
+            nonce: vec![1; 16],
+            ..Default::default()
+        })
+    };
+    let mut first = TcpStream::connect("127.0.0.1:8800").await.unwrap();
+    raw_write_frame(&mut first, hello()).await;
+    sleep(Duration::from_millis(200)).await;
+
+    // The same peer reconnects; the old socket closing later must not end the new link
+    let mut second = TcpStream::connect("127.0.0.1:8800").await.unwrap();
+    raw_write_frame(&mut second, hello()).await;
+    sleep(Duration::from_millis(200)).await;
+    drop(first);
+    sleep(Duration::from_millis(200)).await;
+    let connected = alice.get_connected_peers().await;
+    assert_eq!(connected.len(), 1, "Newer connection should survive the old one closing");
+    assert_eq!(connected[0].id, "raw-peer");
+    while alice_events.try_recv().is_some() {}
+
+    // Vanishing without a Close still disconnects it, under its real identity
+    drop(second);
+    let disconnected = timeout(Duration::from_secs(2), async {
+        loop {
+            if let Some(P2PEvent::PeerDisconnected(peer)) = alice_events.recv().await {
+                return peer;
+            }
+        }
+    })
+    .await
+    .expect("Alice should report the dropped peer");
+    assert_eq!(disconnected.id, "raw-peer");
+    assert_eq!(disconnected.name, "RawPeer");
+    assert!(alice.get_connected_peers().await.is_empty(), "Dropped peer should be forgotten");
+
+    alice.stop().await;
+}
+
+#[tokio::test]
+async fn test_ephemeral_tcp_port_is_announced() {
+    // Both pick a free TCP port but share a discovery port to find each other
+    let alice = P2PMessenger::with_ports("EphemeralAlice".to_string(), 0, 8710).unwrap();
+    let bob = P2PMessenger::with_ports("EphemeralBob".to_string(), 0, 8710).unwrap();
+
+    let alice_addresses = alice.start().await.expect("Failed to start Alice");
+    let bob_addresses = bob.start().await.expect("Failed to start Bob");
+    assert_ne!(alice_addresses.tcp.port(), bob_addresses.tcp.port());
+    assert_eq!(bob_addresses.discovery.map(|addr| addr.port()), Some(8710));
+
+    let bob_seen = timeout(Duration::from_secs(3), async {
+        loop {
+            let peers = alice.discover_peers().unwrap();
+            if let Some(peer) = peers.into_iter().find(|peer| peer.id == bob.peer_id()) {
+                return peer;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }).await.expect("Alice should discover Bob");
+
+    // Discovery carries the port Bob really listens on, so Alice can dial it
+    assert_eq!(bob_seen.port, bob_addresses.tcp.port() as u32);
+    assert!(alice.connect_to_peer(&bob_seen).await.is_ok(), "Alice should connect to Bob's announced port");
+
+    println!("✅ Bob announced ephemeral port {}", bob_seen.port);
+
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_bind_addresses_keep_messenger_on_one_interface() {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    let loopback = Ipv4Addr::LOCALHOST;
+    let build = |name: &str| {
+        let config = P2PConfig::builder(name)
+            .tcp_port(0)
+            .listen_address(IpAddr::V4(loopback))
+            .discovery_port(8720)
+            .discovery_bind_address(IpAddr::V4(loopback))
+            .build()
+            .expect("Config should be valid");
+        P2PMessenger::with_config(config).expect("Failed to create messenger")
+    };
+    let alice = build("BoundAlice");
+    let bob = build("BoundBob");
+
+    let bob_addresses = bob.start().await.expect("Failed to start Bob");
+    alice.start().await.expect("Failed to start Alice");
+    assert_eq!(bob_addresses.tcp.ip(), IpAddr::V4(loopback));
+    assert_eq!(bob_addresses.discovery, Some(SocketAddr::from((loopback, 8720))));
+    assert_eq!(bob.get_local_ip(), "127.0.0.1");
+
+    // Only the bound address accepts connections
+    let port = bob_addresses.tcp.port();
+    assert!(alice.connect_to_address(&format!("[::1]:{}", port)).await.is_err());
+
+    // Discovery runs over the bound interface and reports the bound address
+    let bob_seen = timeout(Duration::from_secs(3), async {
+        loop {
+            let peers = alice.discover_peers().unwrap();
+            if let Some(peer) = peers.into_iter().find(|peer| peer.id == bob.peer_id()) {
+                return peer;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }).await.expect("Alice should discover Bob over loopback");
+    assert_eq!((bob_seen.ip.as_str(), bob_seen.port), ("127.0.0.1", port as u32));
+    assert!(alice.connect_to_peer(&bob_seen).await.is_ok());
+
+    println!("✅ Bob kept to {}", bob_addresses.tcp);
+
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_discovery_bound_to_an_ipv6_address() {
+    use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+    // A routable address, so peers can dial it without a scope
+    let address = archsockrust::discovery::InterfaceFilter::default()
+        .interfaces()
+        .into_iter()
+        .find_map(|interface| match interface.ip {
+            IpAddr::V6(ip) if !ip.is_loopback() && ip.segments()[0] & 0xffc0 != 0xfe80 => Some(ip),
+            _ => None,
+        });
+    let Some(address) = address else {
+        println!("ℹ️ No routable IPv6 address on this host, skipping");
+        return;
+    };
+
+    let build = |name: &str| {
+        let config = P2PConfig::builder(name)
+            .tcp_port(0)
+            .listen_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED))
+            .discovery_port(8860)
+            .discovery_bind_address(IpAddr::V6(address))
+            .build()
+            .expect("Config should be valid");
+        P2PMessenger::with_config(config).expect("Failed to create messenger")
+    };
+    let alice = build("V6BoundAlice");
+    let bob = build("V6BoundBob");
+
+    let bob_addresses = bob.start().await.expect("Failed to start Bob");
+    alice.start().await.expect("Failed to start Alice");
+    assert_eq!(bob_addresses.discovery, Some(SocketAddr::from((address, 8860))));
+    assert_eq!(bob.get_local_ip(), address.to_string());
+
+    // Discovery runs over IPv6 alone and reports the bound address
+    let bob_seen = timeout(Duration::from_secs(3), async {
+        loop {
+            let peers = alice.discover_peers().unwrap();
+            if let Some(peer) = peers.into_iter().find(|peer| peer.id == bob.peer_id()) {
+                return peer;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }).await.expect("Alice should discover Bob over IPv6");
+    assert_eq!(bob_seen.ip, address.to_string());
+    assert!(alice.connect_to_peer(&bob_seen).await.is_ok());
+
+    println!("✅ Bob discovered at [{}]:{}", bob_seen.ip, bob_seen.port);
+
+    alice.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_stop_flushes_closes_and_releases_port() {
+    let alice = P2PMessenger::with_ports("ClosingAlice".to_string(), 8730, 0).unwrap();
+    let bob = P2PMessenger::with_ports("ClosingBob".to_string(), 0, 0).unwrap();
+    let mut bob_events = bob.subscribe_events();
+    alice.start().await.expect("Failed to start Alice");
+    bob.start().await.expect("Failed to start Bob");
+
+    let peer = bob.connect_to_address("127.0.0.1:8730").await.expect("Bob should reach Alice");
+    sleep(Duration::from_millis(200)).await;
+
+    // A message queued just before stopping still arrives, followed by the close
+    alice.send_text_message(bob.peer_id(), "last words".to_string()).await.expect("Send should queue");
+    alice.stop().await;
+
+    let (mut delivered, mut closed) = (false, false);
+    timeout(Duration::from_secs(3), async {
+        while let Some(event) = bob_events.recv().await {
+            match event {
+                P2PEvent::MessageReceived(message) if message.sender_id == peer.id => delivered = true,
+                P2PEvent::PeerDisconnected(info) if info.id == peer.id => {
+                    closed = true;
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }).await.expect("Bob should see Alice close the connection");
+    assert!(delivered, "Queued message should be flushed before closing");
+    assert!(closed);
+    sleep(Duration::from_millis(100)).await;
+    assert!(bob.get_connected_peers().await.is_empty(), "Bob should forget Alice");
+
+    // The port is free as soon as stop returns
+    let replacement = P2PMessenger::with_ports("ReplacementAlice".to_string(), 8730, 0).unwrap();
+    assert!(replacement.start().await.is_ok(), "Port should be released by stop");
+
+    // Dropping shuts down the same way, in the background
+    drop(replacement);
+    sleep(Duration::from_millis(300)).await;
+    let after_drop = P2PMessenger::with_ports("AfterDrop".to_string(), 8730, 0).unwrap();
+    assert!(after_drop.start().await.is_ok(), "Port should be released on drop");
+
+    after_drop.stop().await;
+    bob.stop().await;
+}
+
+#[tokio::test]
+async fn test_restart_after_stop_keeps_events_and_reports_state() {
+    let alice = P2PMessenger::with_ports("RestartAlice".to_string(), 8740, 0).unwrap();
+    let bob = P2PMessenger::with_ports("RestartBob".to_string(), 0, 0).unwrap();
+    let mut alice_events = alice.subscribe_events();
+    assert_eq!(alice.state(), MessengerState::Stopped);
+    bob.start().await.expect("Failed to start Bob");
+
+    let mut states = Vec::new();
+    for round in 0..2 {
+        let addresses = alice.start().await.expect("Alice should start");
+        assert_eq!(alice.state(), MessengerState::Running);
+        assert_eq!(alice.start().await.unwrap(), addresses, "Starting again while running is a no-op");
+
+        // Each run accepts connections and delivers to the same receiver
+        let peer = bob.connect_to_address("127.0.0.1:8740").await.expect("Bob should reach Alice");
+        bob.send_text_message(&peer.id, format!("round {}", round)).await.unwrap();
+        timeout(Duration::from_secs(3), async {
+            while let Some(event) = alice_events.recv().await {
+                match event {
+                    P2PEvent::StateChanged(state) => states.push(state),
+                    P2PEvent::MessageReceived(message) if message.sender_id == bob.peer_id() => break,
+                    _ => {}
+                }
+            }
+        }).await.expect("Alice should receive Bob's message after each start");
+
+        alice.stop().await;
+        assert_eq!(alice.state(), MessengerState::Stopped);
+        assert!(alice.listen_addresses().is_none());
+        assert!(alice.get_connected_peers().await.is_empty());
+        alice.stop().await;
+    }
+
+    sleep(Duration::from_millis(100)).await;
+    while let Some(event) = alice_events.try_recv() {
+        if let P2PEvent::StateChanged(state) = event {
+            states.push(state);
+        }
+    }
+    use MessengerState::*;
+    assert_eq!(states, [Starting, Running, Stopping, Stopped, Starting, Running, Stopping, Stopped]);
+
+    bob.stop().await;
+}
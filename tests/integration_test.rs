@@ -27,20 +27,20 @@ async fn test_complete_system_integration() {
     assert!(alice.is_ok(), "Should create Alice messenger");
     assert!(bob.is_ok(), "Should create Bob messenger");
     
-    let mut alice = alice.unwrap();
-    let mut bob = bob.unwrap();
+    let alice = alice.unwrap();
+    let bob = bob.unwrap();
     
     println!("✅ Step 2: Created messengers with Unicode names");
     println!("   Alice: {} ({})", alice.peer_name(), alice.peer_id());
     println!("   Bob: {} ({})", bob.peer_name(), bob.peer_id());
     
-    // Step 3: Test startup and event system
+    // Step 3: Test startup and event system, subscribing first so no early event is missed
+    let mut alice_events = alice.subscribe_events();
+    let mut bob_events = bob.subscribe_events();
+    
     assert!(alice.start().await.is_ok(), "Alice should start successfully");
     assert!(bob.start().await.is_ok(), "Bob should start successfully");
     
-    let mut alice_events = alice.get_event_receiver().unwrap();
-    let mut bob_events = bob.get_event_receiver().unwrap();
-    
     println!("✅ Step 3: Both messengers started with event systems");
     
     // Step 4: Test discovery with restored broadcast functionality
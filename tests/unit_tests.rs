@@ -14,6 +14,7 @@ fn test_peer_info_creation_and_validation() {
         ip: "192.168.1.100".to_string(),
         port: 6969,
         last_seen: current_time,
+        ..Default::default()
     };
     
     // Validate all fields
@@ -30,6 +31,7 @@ fn test_peer_info_creation_and_validation() {
         ip: "10.0.0.1".to_string(),
         port: 7000,
         last_seen: current_time,
+        ..Default::default()
     };
     
     assert_eq!(unicode_peer.name, "用户名 🌟");
@@ -183,13 +185,12 @@ fn test_protocol_constants() {
 async fn test_event_system_basic_operations() {
     // Test basic event system functionality
     
-    use archsockrust::events::EventManager;
+    use archsockrust::events::{EventKind, EventManager};
     
     let event_manager = EventManager::new();
     let sender = event_manager.get_sender();
-    
-    // Verify sender is functional
-    assert!(sender.is_closed() == false, "Event sender should not be closed initially");
+    let mut all_events = event_manager.subscribe();
+    let mut errors = event_manager.subscribe().with_kinds(&[EventKind::Error]);
     
     // Create test peer for events
     let test_peer = PeerInfo {
@@ -198,6 +199,7 @@ async fn test_event_system_basic_operations() {
         ip: "192.168.1.200".to_string(),
         port: 7777,
         last_seen: archsockrust::get_current_timestamp(),
+        ..Default::default()
     };
     
     // Test sending different event types
@@ -213,10 +215,101 @@ async fn test_event_system_basic_operations() {
         assert!(send_result.is_ok(), "Should be able to send event");
     }
     
+    // Every subscriber gets its own copy, narrowed by its filter
+    let mut kinds = Vec::new();
+    while let Some(event) = all_events.try_recv() {
+        kinds.push(event.kind());
+    }
+    assert_eq!(kinds, [EventKind::PeerDiscovered, EventKind::PeerConnected, EventKind::PeerDisconnected, EventKind::Error]);
+    assert!(matches!(errors.try_recv(), Some(P2PEvent::Error(_))));
+    assert!(errors.try_recv().is_none());
+    
+    // A subscriber that falls too far behind is told how much it missed
+    let mut slow = event_manager.subscribe().with_kinds(&[EventKind::Error]);
+    for _ in 0..archsockrust::protocol::message::EVENT_BUFFER_SIZE + 5 {
+        let _ = sender.send(P2PEvent::PeerConnected(test_peer.clone()));
+    }
+    assert!(matches!(slow.try_recv(), Some(P2PEvent::Lagged { missed: 5 })));
+    assert!(slow.try_recv().is_none(), "Filtered events are skipped after the lag report");
+    
     println!("✅ Event system basic operations test passed");
     println!("   Successfully sent {} different event types", 4);
 }
 
+#[tokio::test]
+async fn test_event_buffer_size_comes_from_config() {
+    use archsockrust::discovery::DiscoveryBackend;
+
+    let config = P2PConfig::builder("TinyBuffer")
+        .tcp_port(0)
+        .discovery_backend(DiscoveryBackend::None)
+        .event_buffer_size(1)
+        .build()
+        .unwrap();
+    let messenger = P2PMessenger::with_config(config).unwrap();
+    let mut events = messenger.subscribe_events();
+
+    // Starting and stopping emit four state changes; one slot keeps only the last
+    messenger.start().await.unwrap();
+    messenger.stop().await;
+    assert!(matches!(events.try_recv(), Some(P2PEvent::Lagged { missed: 3 })));
+}
+
+#[tokio::test]
+async fn test_event_subscription_filters_by_kind() {
+    use archsockrust::events::{EventKind, EventManager};
+
+    let event_manager = EventManager::new();
+    let mut peers = event_manager
+        .subscribe()
+        .with_kinds(&[EventKind::PeerConnected, EventKind::PeerDisconnected]);
+
+    event_manager.emit_event(P2PEvent::Error("skipped".to_string()));
+    event_manager.emit_event(P2PEvent::PeerConnected(PeerInfo::default()));
+    event_manager.emit_event(P2PEvent::MessageSent(P2pMessage::default()));
+    event_manager.emit_event(P2PEvent::PeerDisconnected(PeerInfo::default()));
+
+    // `recv` waits past the filtered-out events instead of returning them
+    assert_eq!(peers.recv().await.map(|event| event.kind()), Some(EventKind::PeerConnected));
+    assert_eq!(peers.recv().await.map(|event| event.kind()), Some(EventKind::PeerDisconnected));
+    assert!(peers.try_recv().is_none());
+}
+
+#[tokio::test]
+async fn test_event_subscription_reports_lag_through_filter() {
+    use archsockrust::events::{EventKind, EventManager};
+    use archsockrust::protocol::message::EVENT_BUFFER_SIZE;
+
+    let event_manager = EventManager::new();
+    let mut errors = event_manager.subscribe().with_kinds(&[EventKind::Error]);
+
+    // Overflow the buffer with events the filter doesn't even want
+    for _ in 0..EVENT_BUFFER_SIZE + 3 {
+        event_manager.emit_event(P2PEvent::PeerConnected(PeerInfo::default()));
+    }
+    event_manager.emit_event(P2PEvent::Error("after the flood".to_string()));
+
+    assert!(matches!(errors.recv().await, Some(P2PEvent::Lagged { missed: 4 })));
+    assert!(matches!(errors.recv().await, Some(P2PEvent::Error(error)) if error == "after the flood"));
+}
+
+#[tokio::test]
+async fn test_event_subscription_ends_when_sender_is_gone() {
+    use archsockrust::events::EventManager;
+
+    let event_manager = EventManager::new();
+    let sender = event_manager.get_sender();
+    let mut events = event_manager.subscribe();
+    sender.send(P2PEvent::Error("last words".to_string())).unwrap();
+
+    // Queued events are still delivered, then the stream ends
+    drop(sender);
+    drop(event_manager);
+    assert!(matches!(events.recv().await, Some(P2PEvent::Error(_))));
+    assert!(events.recv().await.is_none());
+    assert!(events.try_recv().is_none());
+}
+
 #[test]
 fn test_ip_address_validation() {
     // Test IP address validation and parsing
@@ -343,6 +436,7 @@ fn test_string_encoding_and_unicode() {
             ip: "127.0.0.1".to_string(),
             port: 6969,
             last_seen: archsockrust::get_current_timestamp(),
+            ..Default::default()
         };
         
         assert_eq!(peer.name, test_str, "PeerInfo should preserve Unicode in name");
@@ -376,6 +470,7 @@ fn test_error_conditions_and_edge_cases() {
         ip: "255.255.255.255".to_string(), // Max IP
         port: u16::MAX as u32, // Max port
         last_seen: u64::MAX, // Max timestamp
+        ..Default::default()
     };
     
     assert_eq!(edge_peer.id.len(), 1000, "Should handle long IDs");
@@ -388,4 +483,369 @@ fn test_error_conditions_and_edge_cases() {
     println!("      Port: {}", edge_peer.port);
     
     println!("✅ Error conditions and edge cases test completed");
-}
\ No newline at end of file
+}
+#[test]
+fn test_peer_address_round_trip() {
+    use archsockrust::protocol::address::{ip_to_string, parse_socket_addr};
+    use std::net::{Ipv6Addr, SocketAddrV6};
+
+    // v4-mapped addresses from a dual-stack listener come back as plain IPv4
+    let mapped: SocketAddr = "[::ffff:192.168.1.20]:6969".parse().unwrap();
+    assert_eq!(ip_to_string(&mapped), "192.168.1.20");
+
+    // Link-local addresses keep their interface
+    let link_local = SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 6969, 0, 3));
+    assert_eq!(ip_to_string(&link_local), "fe80::1%3");
+    assert_eq!(parse_socket_addr("fe80::1%3", 6969).unwrap(), link_local);
+
+    let global: SocketAddr = "[2001:db8::7]:6969".parse().unwrap();
+    assert_eq!(ip_to_string(&global), "2001:db8::7");
+
+    let peer = PeerInfo {
+        id: "v6".to_string(),
+        name: "V6".to_string(),
+        ip: "fe80::1%3".to_string(),
+        port: 6969,
+        last_seen: 0,
+        ..Default::default()
+    };
+    let addr = peer.socket_addr().expect("Link-local peer should be dialable");
+    assert_eq!(addr.to_string(), "[fe80::1%3]:6969");
+    assert_eq!(addr.ip(), Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+
+    assert!(parse_socket_addr("fe80::1%eth0", 6969).is_err(), "Scope must be an interface index");
+    assert!(parse_socket_addr("not-an-ip", 6969).is_err());
+
+    println!("✅ Peer addresses round-trip across IPv4 and IPv6");
+}
+
+#[test]
+fn test_network_group_seal_and_open() {
+    use archsockrust::discovery::NetworkGroup;
+
+    let request = || DiscoveryMessage {
+        message: Some(discovery_message::Message::Request(PeerRequest {
+            peer_id: "group-peer".to_string(),
+        })),
+        ..Default::default()
+    };
+
+    // Plain groups only filter by name
+    let qa = NetworkGroup::new("qa");
+    let sealed = qa.seal(request());
+    assert!(qa.open(&sealed).is_some());
+    assert!(NetworkGroup::default().open(&sealed).is_none(), "Other groups should ignore it");
+
+    // Secret groups need the same secret on both ends
+    let secret = NetworkGroup::new("qa").with_secret(b"s3cret");
+    let signed = secret.seal(request());
+    let opened = secret.open(&signed).expect("Same secret should verify");
+    assert_eq!(opened.group, "qa");
+    assert!(matches!(opened.message, Some(discovery_message::Message::Request(ref r)) if r.peer_id == "group-peer"));
+    assert!(NetworkGroup::new("qa").with_secret(b"other").open(&signed).is_none(), "Wrong secret");
+    assert!(qa.open(&signed).is_none(), "Signed traffic needs the secret to be read");
+    assert!(secret.open(&sealed).is_none(), "Unsigned traffic is refused by a secret group");
+
+    // Any change to the signed bytes breaks the tag
+    let mut tampered = signed.clone();
+    tampered[4] ^= 0x01;
+    assert!(secret.open(&tampered).is_none(), "Tampered datagram should be rejected");
+    assert!(secret.open(&signed[..signed.len() - 1]).is_none(), "Truncated tag should be rejected");
+
+    // The secret stays out of debug output
+    assert!(!format!("{:?}", secret).contains("s3cret"));
+}
+
+#[test]
+fn test_announcement_signatures() {
+    use archsockrust::discovery::identity::{self, PeerIdentity, Verification};
+
+    let identity = PeerIdentity::generate();
+    let mut announce = PeerAnnouncement {
+        peer_name: "Signed".to_string(),
+        peer_id: "signed-peer".to_string(),
+        tcp_port: 7000,
+        timestamp: 1_700_000_000,
+        metadata: [("room".to_string(), "lab".to_string()), ("role".to_string(), "qa".to_string())]
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    };
+    assert_eq!(identity::verify_announcement(&announce), Verification::Unsigned);
+
+    identity.sign_announcement(&mut announce);
+    assert_eq!(announce.public_key, identity.public_key());
+    assert_eq!(identity::verify_announcement(&announce), Verification::Valid);
+
+    // Changing any signed field, or swapping in another key, breaks it
+    let mut moved = announce.clone();
+    moved.tcp_port = 7001;
+    assert_eq!(identity::verify_announcement(&moved), Verification::Invalid);
+    let mut rekeyed = announce.clone();
+    rekeyed.public_key = PeerIdentity::generate().public_key();
+    assert_eq!(identity::verify_announcement(&rekeyed), Verification::Invalid);
+    let mut stripped = announce.clone();
+    stripped.signature.clear();
+    assert_eq!(identity::verify_announcement(&stripped), Verification::Invalid);
+
+    // Goodbyes are checked against the key pinned from announcements
+    let mut goodbye = PeerGoodbye {
+        peer_id: "signed-peer".to_string(),
+        timestamp: 1_700_000_000_000,
+        ..Default::default()
+    };
+    assert_eq!(identity::verify_goodbye(&goodbye, &identity.public_key()), Verification::Unsigned);
+    identity.sign_goodbye(&mut goodbye);
+    assert_eq!(identity::verify_goodbye(&goodbye, &identity.public_key()), Verification::Valid);
+    let other = PeerIdentity::generate();
+    assert_eq!(identity::verify_goodbye(&goodbye, &other.public_key()), Verification::Invalid);
+    let mut backdated = goodbye.clone();
+    backdated.timestamp -= 1;
+    assert_eq!(identity::verify_goodbye(&backdated, &identity.public_key()), Verification::Invalid);
+
+    // Identities can be saved and restored, and the secret stays out of debug output
+    let restored = PeerIdentity::from_secret(&identity.secret());
+    assert_eq!(restored.public_key(), identity.public_key());
+    let secret_hex: String = identity.secret().iter().map(|byte| format!("{:02x}", byte)).collect();
+    let debug = format!("{:?}", identity);
+    assert!(!debug.contains(&secret_hex) && !debug.contains(&format!("{:?}", identity.secret())));
+}
+
+#[test]
+fn test_announce_schedule_bounds() {
+    use archsockrust::discovery::AnnounceSchedule;
+    use archsockrust::protocol::discovery::PEER_TIMEOUT_SECS;
+    use std::time::Duration;
+
+    let defaults = AnnounceSchedule::default();
+    assert!(defaults.min_interval() <= defaults.max_interval());
+    assert!(defaults.max_interval() * 3 <= Duration::from_secs(PEER_TIMEOUT_SECS));
+
+    let schedule = AnnounceSchedule::new(Duration::from_millis(500), Duration::from_secs(10))
+        .unwrap()
+        .with_startup_announcements(5)
+        .with_budget(100);
+    assert_eq!(schedule.min_interval(), Duration::from_millis(500));
+    assert_eq!(schedule.max_interval(), Duration::from_secs(10));
+    assert_eq!(schedule.startup_announcements(), 5);
+    assert_eq!(schedule.budget_per_sec(), 100);
+
+    assert!(AnnounceSchedule::new(Duration::ZERO, Duration::from_secs(10)).is_err());
+    assert!(AnnounceSchedule::new(Duration::from_secs(5), Duration::from_secs(1)).is_err());
+
+    // Peers must hear from us well within their timeout, whatever it is configured to
+    let slow = AnnounceSchedule::new(Duration::from_secs(1), Duration::from_secs(PEER_TIMEOUT_SECS)).unwrap();
+    assert!(archsockrust::P2PConfig::builder("Peer").announce_schedule(slow).build().is_err());
+    let patient = archsockrust::P2PConfig::builder("Peer")
+        .announce_schedule(slow)
+        .peer_timeout(Duration::from_secs(PEER_TIMEOUT_SECS * 3))
+        .build();
+    assert!(patient.is_ok());
+
+    let messenger = archsockrust::P2PMessenger::new("Scheduled".to_string()).unwrap();
+    assert!(messenger.set_announce_schedule(slow).is_err());
+    assert!(messenger.set_announce_schedule(schedule).is_ok());
+}
+
+#[test]
+fn test_config_builder_validation() {
+    use archsockrust::discovery::{AnnounceSchedule, DiscoveryBackend};
+    use archsockrust::error::P2PError;
+    use std::time::Duration;
+
+    let defaults = P2PConfig::builder("Defaults").build().unwrap();
+    assert_eq!(defaults.peer_name(), "Defaults");
+    assert_eq!((defaults.tcp_port(), defaults.discovery_port()), (6969, 6968));
+    assert_eq!(defaults.discovery_backend(), DiscoveryBackend::default());
+    assert_eq!(defaults.download_dir(), std::path::Path::new("recibidos"));
+    assert_eq!(defaults.peer_timeout(), Duration::from_secs(60));
+
+    let config = P2PConfig::builder("Custom")
+        .tcp_port(7000)
+        .discovery_port(7001)
+        .discovery_backend(DiscoveryBackend::None)
+        .relay_enabled(true)
+        .handshake_timeout(Duration::from_secs(3))
+        .event_buffer_size(16)
+        .download_dir("inbox")
+        .build()
+        .unwrap();
+    assert_eq!((config.tcp_port(), config.discovery_port()), (7000, 7001));
+    assert!(config.relay_enabled());
+    assert_eq!(config.handshake_timeout(), Duration::from_secs(3));
+    assert_eq!(config.event_buffer_size(), 16);
+    assert_eq!(config.download_dir(), std::path::Path::new("inbox"));
+
+    // Port 0 asks the OS for a free port
+    let ephemeral = P2PConfig::builder("Ephemeral").tcp_port(0).discovery_port(0).build().unwrap();
+    assert_eq!((ephemeral.tcp_port(), ephemeral.discovery_port()), (0, 0));
+
+    // Discovery may bind an IPv6 address as long as the listener takes IPv6
+    let v6 = "fd00::2".parse::<std::net::IpAddr>().unwrap();
+    let dual_stack = P2PConfig::builder("DualStack")
+        .listen_address("::".parse().unwrap())
+        .discovery_bind_address(v6)
+        .build()
+        .unwrap();
+    assert_eq!(dual_stack.discovery_bind_address(), Some(v6));
+
+    let invalid = [
+        P2PConfig::builder(" ").build(),
+        P2PConfig::builder("Peer").peer_id("").build(),
+        P2PConfig::builder("Peer").multicast_group(Ipv4Addr::new(192, 168, 1, 1)).build(),
+        P2PConfig::builder("Peer")
+            .announce_schedule(AnnounceSchedule::new(Duration::from_secs(1), Duration::from_secs(20)).unwrap())
+            .peer_timeout(Duration::from_secs(30))
+            .build(),
+        P2PConfig::builder("Peer").listen_address(Ipv4Addr::LOCALHOST.into()).discovery_bind_address(v6).build(),
+        P2PConfig::builder("Peer")
+            .listen_address("::1".parse().unwrap())
+            .discovery_bind_address(Ipv4Addr::LOCALHOST.into())
+            .build(),
+        P2PConfig::builder("Peer").handshake_timeout(Duration::ZERO).build(),
+        P2PConfig::builder("Peer").event_buffer_size(0).build(),
+        P2PConfig::builder("Peer").download_dir("").build(),
+        P2PConfig::builder("Peer").bootstrap_peer("no-port").build(),
+    ];
+    for result in invalid {
+        assert!(matches!(result, Err(P2PError::InvalidConfig(_))), "Expected InvalidConfig, got {:?}", result);
+    }
+}
+
+#[test]
+fn test_config_file_and_env_overrides() {
+    use archsockrust::discovery::{DiscoveryBackend, PeerIdentity};
+    use archsockrust::error::P2PError;
+    use std::time::Duration;
+
+    let identity = PeerIdentity::generate();
+    let key: String = identity.secret().iter().map(|b| format!("{:02x}", b)).collect();
+    let mut settings = ConfigFile::parse(&format!(
+        r#"
+        name = "Alice"
+        peer_id = "alice-1"
+        identity_key = "{}"
+        tcp_port = 7100
+        download_dir = "inbox"
+        trusted_peers = ["10.0.0.5:6969"]
+
+        [discovery]
+        backend = "mdns"
+        group = "qa-lab"
+        exclude_interfaces = ["docker"]
+        announce_max_ms = 10000
+        peer_timeout_secs = 45
+        "#,
+        key
+    ))
+    .unwrap();
+
+    // Variables override the file; unrelated ones are ignored
+    let env = [
+        ("ARCHSOCKRUST_TCP_PORT", "7200"),
+        ("ARCHSOCKRUST_DISCOVERY_BACKEND", "both"),
+        ("ARCHSOCKRUST_TRUSTED_PEERS", "10.0.0.6:6969, 10.0.0.7:6969"),
+        ("HOME", "/home/alice"),
+    ];
+    settings
+        .apply_env(env.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+        .unwrap();
+
+    let config = settings.into_builder("Default").unwrap().build().unwrap();
+    assert_eq!(config.peer_name(), "Alice");
+    assert_eq!((config.tcp_port(), config.discovery_port()), (7200, 6968));
+    assert_eq!(config.discovery_backend(), DiscoveryBackend::Both);
+    assert_eq!(config.network_group().name(), "qa-lab");
+    assert_eq!(config.download_dir(), std::path::Path::new("inbox"));
+    assert_eq!(config.announce_schedule().max_interval(), Duration::from_secs(10));
+    assert_eq!(config.peer_timeout(), Duration::from_secs(45));
+    assert_eq!(config.bootstrap_peers(), ["10.0.0.6:6969", "10.0.0.7:6969"]);
+
+    // Positional arguments win over everything else
+    let args: Vec<String> = ["Bob", "7300"].iter().map(|s| s.to_string()).collect();
+    let from_args = ConfigFile::from_args(&args).unwrap();
+    assert_eq!(from_args.name.as_deref(), Some("Bob"));
+    assert_eq!(from_args.tcp_port, Some(7300));
+    assert_eq!(ConfigFile::default().into_builder("Fallback").unwrap().build().unwrap().peer_name(), "Fallback");
+
+    let invalid_files = ["tcp_port = \"x\"", "unknown = 1", "[discovery]\nbackend = \"carrier-pigeon\""];
+    for text in invalid_files {
+        let result = ConfigFile::parse(text).and_then(|settings| settings.into_builder("Peer"));
+        assert!(matches!(result, Err(P2PError::InvalidConfig(_))), "Expected InvalidConfig for {:?}", text);
+    }
+    let mut bad_env = ConfigFile::default();
+    // A stale variable is only worth a warning, a bad value is still an error
+    assert!(bad_env.apply_env([("ARCHSOCKRUST_TCP_PORTS".to_string(), "1".to_string())]).is_ok());
+    assert_eq!(bad_env.warnings, ["ignoring unknown setting ARCHSOCKRUST_TCP_PORTS"]);
+    assert!(bad_env.apply_env([("ARCHSOCKRUST_TCP_PORT".to_string(), "port".to_string())]).is_err());
+    assert!(ConfigFile { identity_key: Some("abc".to_string()), ..Default::default() }
+        .into_builder("Peer")
+        .is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_config_load_skips_non_unicode_environment() {
+    use std::os::unix::ffi::OsStrExt;
+
+    // Some other program's variable holding raw bytes
+    std::env::set_var("UNIT_TEST_RAW_BYTES", std::ffi::OsStr::from_bytes(b"\xff\xfe"));
+    let loaded = ConfigFile::load(None);
+    std::env::remove_var("UNIT_TEST_RAW_BYTES");
+    assert!(loaded.is_ok(), "Non-Unicode variables should be skipped, got {:?}", loaded);
+}
+
+#[test]
+fn test_interface_rules_and_filter() {
+    use archsockrust::discovery::{InterfaceFilter, InterfaceRule, NetworkInterface};
+
+    let interface = |name: &str, ip: &str| NetworkInterface {
+        name: name.to_string(),
+        index: 1,
+        ip: ip.parse().unwrap(),
+        prefix_len: 24,
+    };
+    let eth = interface("eth0", "192.168.1.20");
+    let docker = interface("docker0", "172.17.0.1");
+    let vpn = interface("tun0", "10.8.0.6");
+    let eth_v6 = interface("eth0", "fd00::2");
+
+    // Rules match by exact name, name prefix or subnet
+    assert!("eth0".parse::<InterfaceRule>().unwrap().matches(&eth));
+    assert!(!"eth".parse::<InterfaceRule>().unwrap().matches(&eth));
+    assert!("docker*".parse::<InterfaceRule>().unwrap().matches(&docker));
+    assert!("10.8.0.0/16".parse::<InterfaceRule>().unwrap().matches(&vpn));
+    assert!(!"10.8.0.0/16".parse::<InterfaceRule>().unwrap().matches(&eth));
+    assert!("fd00::/8".parse::<InterfaceRule>().unwrap().matches(&eth_v6));
+    assert!("0.0.0.0/0".parse::<InterfaceRule>().unwrap().matches(&eth));
+    assert!("10.0.0.0/33".parse::<InterfaceRule>().is_err());
+    assert!(matches!("nonsense/8".parse::<InterfaceRule>(), Err(archsockrust::error::P2PError::InvalidConfig(_))));
+    assert!("".parse::<InterfaceRule>().is_err());
+
+    // The default filter allows everything
+    let all = InterfaceFilter::new();
+    assert!([&eth, &docker, &vpn, &eth_v6].iter().all(|interface| all.allows(interface)));
+
+    // Excludes win over includes
+    let filter = InterfaceFilter::new()
+        .exclude("docker*").unwrap()
+        .exclude("10.8.0.0/16").unwrap();
+    assert!(filter.allows(&eth));
+    assert!(!filter.allows(&docker));
+    assert!(!filter.allows(&vpn));
+
+    let filter = InterfaceFilter::new()
+        .include("eth0").unwrap()
+        .exclude("fd00::/8").unwrap();
+    assert!(filter.allows(&eth));
+    assert!(!filter.allows(&eth_v6));
+    assert!(!filter.allows(&docker));
+
+    // A bind address narrows any filter to the interface holding it
+    let bound = InterfaceFilter::new().exclude("docker*").unwrap().with_address(eth.ip);
+    assert!(bound.allows(&eth));
+    assert!(!bound.allows(&eth_v6));
+    assert!(!bound.allows(&vpn));
+
+    assert_eq!(eth.to_string(), "eth0 192.168.1.20/24");
+}